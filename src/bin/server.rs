@@ -0,0 +1,97 @@
+// A dedicated server: runs the level headless and serves it to clients over UDP.
+// cargo run --bin server -- --bind 127.0.0.1:5757
+// cargo run -- --connect 127.0.0.1:5757
+use bevy::{
+    app::ScheduleRunnerPlugin,
+    log::LogPlugin,
+    pbr::wireframe::WireframeConfig,
+    prelude::*,
+    render::{
+        settings::{RenderCreation, WgpuSettings},
+        RenderPlugin,
+    },
+    window::ExitCondition,
+    winit::WinitPlugin,
+};
+use bevy_rapier3d::{prelude::*, render::DebugRenderContext};
+use clap::Parser;
+use mipo::{
+    asset_cache, encounters, enemy, hitpoints, items, level, leylines, mana, missions, navigation,
+    net, objects, player, respawn, status, world, GameState,
+};
+use std::{net::SocketAddr, path::PathBuf, time::Duration};
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// A heightmap to play on instead of the level's.
+    terrain: Option<PathBuf>,
+    #[arg(long, default_value = net::DEFAULT_SERVER_ADDR)]
+    bind: SocketAddr,
+    #[arg(long, default_value = "assets/levels/canyon.ron")]
+    level: PathBuf,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    App::new()
+        .add_plugins((
+            DefaultPlugins
+                .set(LogPlugin {
+                    filter: "info,wgpu_core=warn,wgpu_hal=warn,mipo=debug".into(),
+                    level: bevy::log::Level::DEBUG,
+                    ..default()
+                })
+                .set(WindowPlugin {
+                    primary_window: None,
+                    exit_condition: ExitCondition::DontExit,
+                    close_when_requested: false,
+                })
+                // Meshes and materials are still made for the level's entities, but never drawn.
+                .set(RenderPlugin {
+                    render_creation: RenderCreation::Automatic(WgpuSettings {
+                        backends: None,
+                        ..default()
+                    }),
+                    ..default()
+                })
+                .disable::<WinitPlugin>(),
+            ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1.0 / 60.0)),
+            asset_cache::AssetCachePlugin,
+            RapierPhysicsPlugin::<NoUserData>::default(),
+            player::PlayerPlugin,
+            world::WorldPlugin {
+                terrain_path: args
+                    .terrain
+                    .clone()
+                    .unwrap_or_else(|| "assets/grand_canyon_small_heightmap.png".into()),
+            },
+        ))
+        // Nobody plays on the server itself, so it's in the game from the start.
+        .insert_state(GameState::InGame)
+        .enable_state_scoped_entities::<GameState>()
+        // The world's debug view toggles expect these.
+        .init_resource::<WireframeConfig>()
+        .init_resource::<DebugRenderContext>()
+        .add_plugins((
+            hitpoints::HpPlugin,
+            mana::ManaPlugin,
+            status::StatusPlugin,
+            items::ItemsPlugin,
+            objects::TargetsPlugin,
+            objects::CheckpointsPlugin,
+            enemy::EnemyPlugin,
+            encounters::EncounterPlugin,
+            navigation::NavigationPlugin,
+            leylines::LeylinePlugin,
+            missions::MissionPlugin,
+            respawn::RespawnPlugin,
+        ))
+        .add_plugins(level::LevelPlugin {
+            level_path: args.level,
+            terrain: args.terrain,
+        })
+        .add_plugins(net::ServerPlugin { addr: args.bind })
+        .run();
+}
@@ -0,0 +1,627 @@
+use crate::{
+    camera::Flycam,
+    encounters::{EnemyKind, EnemySpawnerDefinition},
+    items::{PathMode, Waypoint},
+    level::{
+        save_level, spawn_placed, CheckpointPlacement, LaunchPadPlacement, LevelDefinition,
+        LevelPath, LightPlacement, PickupPlacement, Placed, Placement, PlatformPlacement,
+        PropPlacement, SpawnPointPlacement, TargetsPlacement,
+    },
+    objects::{CheckpointVolume, Targets},
+    prelude::*,
+    world::TerrainCollider,
+};
+use bevy::{prelude::*, window::PrimaryWindow};
+use bevy_inspector_egui::{
+    bevy_egui::{EguiContext, EguiContexts},
+    bevy_inspector, egui,
+};
+use bevy_rapier3d::prelude::*;
+
+/// The level editor, for `GameState::DevMode`. Click to select whatever the level placed, then move it about
+/// with the keys in `HELP`, and save the result over the level file with Ctrl+S. The selected entity's
+/// components can be edited in the inspector window, and edits to its `Placed` are saved with the level.
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EditorSelection>();
+        // Its placement types come along with it, for the inspector.
+        app.register_type::<Placed>();
+        app.add_systems(OnEnter(GameState::DevMode), spawn_editor_text);
+        app.add_systems(OnExit(GameState::DevMode), drop_selection);
+        app.add_systems(
+            Update,
+            (
+                select,
+                edit_selection,
+                // Shares the number keys with the spell hotbar, which only listens in InGame.
+                place_new,
+                save_edits,
+                sync_inspected_transforms,
+                respawn_inspected_placements,
+                update_editor_text,
+                draw_placements,
+            )
+                .chain()
+                .run_if(in_state(GameState::DevMode)),
+        );
+        app.add_systems(
+            Update,
+            inspect_selection
+                .after(draw_placements)
+                .run_if(in_state(GameState::DevMode)),
+        );
+    }
+}
+
+const HELP: &str = "Click: select    G: grab, click to drop, Esc to cancel    T: snap to terrain\n\
+PgUp/PgDn: raise/lower    [ ]: turn    Del: delete    Ctrl+S: save level\n\
+New at the cursor: 1 spawn point  2 launch pad  3 platform  4 targets  5 enemy spawner  6 light  7 prop  8 checkpoint  9 pickup";
+// How far the cursor's ray reaches into the world.
+const RAY_LENGTH: f32 = 5000.0;
+const NUDGE: f32 = 1.0;
+const TURN_DEGREES: f32 = 15.0;
+
+#[derive(Resource, Debug, Default)]
+pub struct EditorSelection {
+    pub entity: Option<Entity>,
+    /// While grabbed, the selection follows the cursor across the terrain. This is where it was before, for
+    /// cancelling.
+    grabbed_from: Option<Transform>,
+    message: String,
+}
+
+#[derive(Component)]
+struct EditorText;
+
+fn cursor_ray(
+    windows: &Query<&Window, With<PrimaryWindow>>,
+    flycam: &Query<(&Camera, &GlobalTransform), With<Flycam>>,
+) -> Option<Ray3d> {
+    let cursor = windows.get_single().ok()?.cursor_position()?;
+    let (camera, transform) = flycam.get_single().ok()?;
+    camera.viewport_to_world(transform, cursor)
+}
+
+/// Where a ray meets the terrain.
+fn terrain_hit(
+    rapier: &RapierContext,
+    terrain: &Query<(), With<TerrainCollider>>,
+    origin: Vec3,
+    direction: Vec3,
+) -> Option<Vec3> {
+    let predicate = |entity| terrain.contains(entity);
+    let filter = QueryFilter::only_fixed().predicate(&predicate);
+    rapier
+        .cast_ray(origin, direction, RAY_LENGTH, true, filter)
+        .map(|(_, distance)| origin + direction * distance)
+}
+
+/// The placement nearest along `ray` whose radius the ray passes through.
+pub fn pick(
+    ray: Ray3d,
+    candidates: impl IntoIterator<Item = (Entity, Vec3, f32)>,
+) -> Option<Entity> {
+    candidates
+        .into_iter()
+        .filter_map(|(entity, position, radius)| {
+            let along = (position - ray.origin).dot(*ray.direction);
+            let closest = ray.origin + *ray.direction * along;
+            (along > 0.0 && closest.distance(position) <= radius).then_some((entity, along))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(entity, _)| entity)
+}
+
+fn select(
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut egui: EguiContexts,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    flycam: Query<(&Camera, &GlobalTransform), With<Flycam>>,
+    placed: Query<(Entity, &Placed, &GlobalTransform)>,
+    mut selection: ResMut<EditorSelection>,
+) {
+    if !mouse.just_pressed(MouseButton::Left) || egui.ctx_mut().is_pointer_over_area() {
+        return;
+    }
+    // Clicking drops whatever's grabbed where it is; edit_selection commits it.
+    if selection.grabbed_from.is_some() {
+        return;
+    }
+    let Some(ray) = cursor_ray(&windows, &flycam) else {
+        return;
+    };
+    let candidates = placed.iter().map(|(entity, placed, transform)| {
+        (entity, transform.translation(), placed.placement.radius())
+    });
+    selection.entity = pick(ray, candidates);
+    selection.message.clear();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn edit_selection(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    assets: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    rapier: Res<RapierContext>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    flycam: Query<(&Camera, &GlobalTransform), With<Flycam>>,
+    terrain: Query<(), With<TerrainCollider>>,
+    mut placed: Query<(&Placed, &mut Transform)>,
+    mut selection: ResMut<EditorSelection>,
+) {
+    let Some(entity) = selection.entity else {
+        return;
+    };
+    let Ok((placed, mut transform)) = placed.get_mut(entity) else {
+        selection.entity = None;
+        selection.grabbed_from = None;
+        return;
+    };
+
+    let edited_transform = if let Some(from) = selection.grabbed_from {
+        if keyboard.just_pressed(KeyCode::Escape) {
+            *transform = from;
+            selection.grabbed_from = None;
+            return;
+        }
+        if let Some(hit) = cursor_ray(&windows, &flycam)
+            .and_then(|ray| terrain_hit(&rapier, &terrain, ray.origin, *ray.direction))
+        {
+            transform.translation = hit;
+        }
+        if !(keyboard.just_pressed(KeyCode::KeyG) || mouse.just_pressed(MouseButton::Left)) {
+            return;
+        }
+        selection.grabbed_from = None;
+        *transform
+    } else if keyboard.just_pressed(KeyCode::KeyG) {
+        selection.grabbed_from = Some(*transform);
+        return;
+    } else if keyboard.just_pressed(KeyCode::Delete) {
+        info!("Deleting a {}", placed.placement.name());
+        commands.entity(entity).despawn_recursive();
+        selection.entity = None;
+        return;
+    } else {
+        // A platform's transform is wherever it has got to along its path, so edit its path's start instead.
+        let mut base = match placed.placement {
+            Placement::Platform(_) => placed.placement.transform(),
+            _ => *transform,
+        };
+        if keyboard.just_pressed(KeyCode::KeyT) {
+            let above = Vec3::new(base.translation.x, RAY_LENGTH / 2.0, base.translation.z);
+            match terrain_hit(&rapier, &terrain, above, Vec3::NEG_Y) {
+                Some(ground) => base.translation = ground,
+                None => selection.message = "There's no terrain under that.".into(),
+            }
+        } else if keyboard.just_pressed(KeyCode::PageUp) {
+            base.translation.y += NUDGE;
+        } else if keyboard.just_pressed(KeyCode::PageDown) {
+            base.translation.y -= NUDGE;
+        } else if keyboard.just_pressed(KeyCode::BracketLeft) {
+            base.rotate_y(TURN_DEGREES.to_radians());
+        } else if keyboard.just_pressed(KeyCode::BracketRight) {
+            base.rotate_y(-TURN_DEGREES.to_radians());
+        } else {
+            return;
+        }
+        base
+    };
+
+    // Respawn from the edited placement, so that anything built from it, like a platform's path, follows.
+    let mut edited = placed.clone();
+    edited.placement.set_transform(&edited_transform);
+    commands.entity(entity).despawn_recursive();
+    selection.entity = Some(spawn_placed(&mut commands, &assets, &mut materials, edited));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn place_new(
+    mut commands: Commands,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    assets: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    rapier: Res<RapierContext>,
+    windows: Query<&Window, With<PrimaryWindow>>,
+    flycam: Query<(&Camera, &GlobalTransform), With<Flycam>>,
+    terrain: Query<(), With<TerrainCollider>>,
+    placed: Query<&Placed>,
+    mut selection: ResMut<EditorSelection>,
+) {
+    let kinds = [
+        KeyCode::Digit1,
+        KeyCode::Digit2,
+        KeyCode::Digit3,
+        KeyCode::Digit4,
+        KeyCode::Digit5,
+        KeyCode::Digit6,
+        KeyCode::Digit7,
+        KeyCode::Digit8,
+        KeyCode::Digit9,
+    ];
+    let Some(kind) = kinds.iter().position(|key| keyboard.just_pressed(*key)) else {
+        return;
+    };
+    let Some(ground) = cursor_ray(&windows, &flycam)
+        .and_then(|ray| terrain_hit(&rapier, &terrain, ray.origin, *ray.direction))
+    else {
+        selection.message = "Point at the terrain to place things.".into();
+        return;
+    };
+    let order = placed
+        .iter()
+        .map(|placed| placed.order + 1)
+        .max()
+        .unwrap_or(0);
+    let placement = new_placement(kind, ground, order);
+    info!("Placing a {} at {ground}", placement.name());
+    selection.entity = Some(spawn_placed(
+        &mut commands,
+        &assets,
+        &mut materials,
+        Placed { placement, order },
+    ));
+    selection.grabbed_from = None;
+}
+
+/// A placement of the `kind`th sort on the number keys, standing on `ground`. `order` goes into the names of
+/// things that need their own.
+fn new_placement(kind: usize, ground: Vec3, order: usize) -> Placement {
+    match kind {
+        0 => Placement::SpawnPoint(SpawnPointPlacement {
+            position: ground + Vec3::Y * 2.0,
+            yaw: 0.0,
+        }),
+        1 => Placement::LaunchPad(LaunchPadPlacement {
+            position: ground,
+            impulse: Vec3::Y * 50.0,
+        }),
+        2 => {
+            let start = ground + Vec3::Y * 10.0;
+            Placement::Platform(PlatformPlacement {
+                size: Vec3::new(8.0, 1.0, 8.0),
+                waypoints: vec![
+                    Waypoint {
+                        position: start,
+                        wait: 1.0,
+                    },
+                    Waypoint {
+                        position: start + Vec3::X * 20.0,
+                        wait: 1.0,
+                    },
+                ],
+                speed: 5.0,
+                easing: default(),
+                mode: PathMode::PingPong,
+                yaw_speed: 0.0,
+            })
+        }
+        3 => Placement::Targets(TargetsPlacement {
+            position: ground + Vec3::Y * 30.0,
+            targets: Targets::default(),
+        }),
+        4 => Placement::EnemySpawner(EnemySpawnerDefinition {
+            kind: EnemyKind::Grunt,
+            position: ground,
+            max_alive: 2,
+            interval: 15.0,
+            activation_radius: Some(40.0),
+        }),
+        5 => Placement::Light(LightPlacement::Point {
+            position: ground + Vec3::Y * 5.0,
+            color: [1.0; 3],
+            intensity: 100000.0,
+            range: 20.0,
+            shadows: false,
+        }),
+        6 => Placement::Prop(PropPlacement {
+            model: "torus1.stl".into(),
+            position: ground,
+            rotation: Vec3::ZERO,
+            scale: Vec3::splat(0.5),
+            color: [1.0; 3],
+            collider: None,
+        }),
+        7 => {
+            let volume = CheckpointVolume::default();
+            Placement::Checkpoint(CheckpointPlacement {
+                name: format!("checkpoint {order}"),
+                position: ground + Vec3::Y * volume.half_extents.y,
+                yaw: 0.0,
+                half_extents: volume.half_extents,
+            })
+        }
+        _ => Placement::Pickup(PickupPlacement {
+            item: "items/health_potion.item.ron".into(),
+            count: 1,
+            position: ground + Vec3::Y,
+        }),
+    }
+}
+
+fn save_edits(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    path: Res<LevelPath>,
+    mut level: ResMut<LevelDefinition>,
+    placed: Query<&Placed>,
+    mut selection: ResMut<EditorSelection>,
+) {
+    let ctrl = keyboard.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]);
+    if !(ctrl && keyboard.just_pressed(KeyCode::KeyS)) {
+        return;
+    }
+    let mut placements: Vec<&Placed> = placed.iter().collect();
+    placements.sort_by_key(|placed| placed.order);
+    *level = level.with_placements(
+        placements
+            .into_iter()
+            .map(|placed| placed.placement.clone()),
+    );
+    selection.message = match save_level(&path.0, &level) {
+        Ok(()) => {
+            info!("Saved the level to {:?}", path.0);
+            format!("Saved {}", path.0.display())
+        }
+        Err(e) => {
+            warn!("Couldn't save the level to {:?}: {e}", path.0);
+            format!("Couldn't save: {e}")
+        }
+    };
+}
+
+/// Keeps placements up to date with transforms edited in the inspector. Platforms are left out, since they move
+/// themselves. The entity already stands where its transform says, so this doesn't count as a change to the
+/// placement.
+fn sync_inspected_transforms(mut placed: Query<(&Transform, &mut Placed), Changed<Transform>>) {
+    for (transform, mut placed) in &mut placed {
+        if !matches!(placed.placement, Placement::Platform(_)) {
+            placed
+                .bypass_change_detection()
+                .placement
+                .set_transform(transform);
+        }
+    }
+}
+
+/// Spawns placements edited in the inspector again, so that the entity matches them.
+fn respawn_inspected_placements(
+    mut commands: Commands,
+    assets: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    placed: Query<(Entity, Ref<Placed>)>,
+    mut selection: ResMut<EditorSelection>,
+) {
+    for (entity, placed) in &placed {
+        // Freshly spawned ones are already up to date.
+        if !placed.is_changed() || placed.is_added() {
+            continue;
+        }
+        commands.entity(entity).despawn_recursive();
+        let respawned = spawn_placed(
+            &mut commands,
+            &assets,
+            &mut materials,
+            Placed::clone(&placed),
+        );
+        if selection.entity == Some(entity) {
+            selection.entity = Some(respawned);
+        }
+    }
+}
+
+/// Leaving DevMode puts back anything still grabbed.
+fn drop_selection(
+    mut selection: ResMut<EditorSelection>,
+    mut placed: Query<(&mut Transform, &mut Placed)>,
+) {
+    if let (Some(entity), Some(from)) = (selection.entity, selection.grabbed_from) {
+        if let Ok((mut transform, mut placed)) = placed.get_mut(entity) {
+            *transform = from;
+            if !matches!(placed.placement, Placement::Platform(_)) {
+                placed.placement.set_transform(&from);
+            }
+        }
+    }
+    *selection = EditorSelection::default();
+}
+
+fn spawn_editor_text(mut commands: Commands) {
+    commands.spawn((
+        Name::new("editor_help"),
+        StateScoped(GameState::DevMode),
+        EditorText,
+        TextBundle::from_section(
+            HELP,
+            TextStyle {
+                font_size: 20.0,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(20.0),
+            left: Val::Px(20.0),
+            ..default()
+        }),
+    ));
+}
+
+fn update_editor_text(
+    selection: Res<EditorSelection>,
+    placed: Query<&Placed>,
+    mut text: Query<&mut Text, With<EditorText>>,
+) {
+    if !selection.is_changed() {
+        return;
+    }
+    let Ok(mut text) = text.get_single_mut() else {
+        return;
+    };
+    let selected = selection
+        .entity
+        .and_then(|entity| placed.get(entity).ok())
+        .map_or("Nothing selected".to_string(), |placed| {
+            let grabbed = if selection.grabbed_from.is_some() {
+                " (grabbed)"
+            } else {
+                ""
+            };
+            format!("Selected: {}{grabbed}", placed.placement.name())
+        });
+    text.sections[0].value = format!("{HELP}\n\n{selected}\n{}", selection.message);
+}
+
+fn draw_placements(
+    mut gizmos: Gizmos,
+    selection: Res<EditorSelection>,
+    placed: Query<(Entity, &Placed, &GlobalTransform)>,
+) {
+    for (entity, placed, transform) in &placed {
+        let selected = selection.entity == Some(entity);
+        let color = if selected {
+            Palette::Yellow.to_color()
+        } else {
+            Palette::Blue.to_color()
+        };
+        let position = transform.translation();
+        gizmos.sphere(position, Quat::IDENTITY, placed.placement.radius(), color);
+        if selected {
+            gizmos.axes(*transform, placed.placement.radius() * 2.0);
+        }
+        match &placed.placement {
+            Placement::Platform(platform) => {
+                let mut path: Vec<Vec3> = platform.waypoints.iter().map(|w| w.position).collect();
+                if platform.mode == PathMode::Loop {
+                    path.extend(path.first().copied());
+                }
+                gizmos.linestrip(path, color);
+            }
+            Placement::Targets(targets) => {
+                gizmos.cuboid(
+                    Transform::from_translation(position).with_scale(targets.targets.extent()),
+                    color,
+                );
+            }
+            Placement::Light(LightPlacement::Spot { looking_at, .. }) => {
+                gizmos.arrow(position, *looking_at, color);
+            }
+            Placement::SpawnPoint(_) => {
+                gizmos.arrow(position, position + transform.forward() * 3.0, color);
+            }
+            Placement::Checkpoint(checkpoint) => {
+                let size = checkpoint.half_extents * 2.0;
+                gizmos.cuboid(transform.compute_transform().with_scale(size), color);
+                gizmos.arrow(position, position + transform.forward() * 3.0, color);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn inspect_selection(world: &mut World) {
+    let Some(entity) = world.resource::<EditorSelection>().entity else {
+        return;
+    };
+    if world.get_entity(entity).is_none() {
+        return;
+    }
+    let Ok(egui_context) = world
+        .query_filtered::<&mut EguiContext, With<PrimaryWindow>>()
+        .get_single(world)
+    else {
+        return;
+    };
+    let mut egui_context = egui_context.clone();
+    egui::Window::new("Selected").show(egui_context.get_mut(), |ui| {
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            bevy_inspector::ui_for_entity(world, entity, ui);
+        });
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn picks_the_nearest_placement_under_the_cursor() {
+        let mut world = World::new();
+        let [near, far, beside] = [(); 3].map(|_| world.spawn_empty().id());
+        let ray = Ray3d::new(Vec3::ZERO, Vec3::NEG_Z);
+        let candidates = [
+            (far, Vec3::new(0.5, 0.0, -20.0), 1.0),
+            (near, Vec3::new(0.0, 0.5, -10.0), 1.0),
+            (beside, Vec3::new(5.0, 0.0, -5.0), 1.0),
+        ];
+        assert_eq!(pick(ray, candidates), Some(near));
+        assert_eq!(pick(ray, [candidates[2]]), None);
+        // Nothing behind the camera.
+        assert_eq!(pick(ray, [(near, Vec3::new(0.0, 0.0, 10.0), 1.0)]), None);
+    }
+
+    #[test]
+    fn new_placements_stand_on_the_ground() {
+        let ground = Vec3::new(10.0, 20.0, 30.0);
+        for kind in 0..9 {
+            let placement = new_placement(kind, ground, 0);
+            let position = placement.transform().translation;
+            assert_eq!(position.xz(), ground.xz(), "{}", placement.name());
+            assert!(position.y >= ground.y, "{}", placement.name());
+        }
+    }
+
+    #[test]
+    fn nudges_a_platform_from_its_path_start() {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()));
+        app.init_asset::<StandardMaterial>();
+        app.init_resource::<ButtonInput<KeyCode>>();
+        app.init_resource::<ButtonInput<MouseButton>>();
+        app.init_resource::<RapierContext>();
+        app.init_resource::<EditorSelection>();
+        let ground = Vec3::new(10.0, 20.0, 30.0);
+        let placement = new_placement(2, ground, 0);
+        let start = placement.transform().translation;
+        // Partway along its path.
+        let moved = Transform::from_translation(start + Vec3::X * 10.0);
+        let platform = app
+            .world_mut()
+            .spawn((
+                Placed {
+                    placement,
+                    order: 0,
+                },
+                moved,
+            ))
+            .id();
+        app.world_mut().resource_mut::<EditorSelection>().entity = Some(platform);
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::PageUp);
+        app.world_mut().run_system_once(edit_selection);
+
+        let edited = app.world().resource::<EditorSelection>().entity.unwrap();
+        assert_ne!(edited, platform);
+        let Placement::Platform(platform) = &app.world().get::<Placed>(edited).unwrap().placement
+        else {
+            panic!("not a platform");
+        };
+        let waypoints: Vec<_> = platform
+            .waypoints
+            .iter()
+            .map(|waypoint| waypoint.position)
+            .collect();
+        assert_eq!(
+            waypoints,
+            [
+                start + Vec3::Y * NUDGE,
+                start + Vec3::X * 20.0 + Vec3::Y * NUDGE
+            ]
+        );
+    }
+}
@@ -0,0 +1,719 @@
+use crate::{
+    enemy::{AttackKind, Enemy, EnemyAttack, EnemyBundle},
+    hitpoints::{DamageSet, Dead},
+    items::Spellbook,
+    mana::{Mana, ManaRegen},
+    navigation::Navigation,
+    player::PlayerInput,
+    prelude::*,
+    progression::XpBounty,
+};
+use bevy::{prelude::*, utils::HashMap};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, time::Duration};
+
+/// Waves of enemies that come for the player once they get close to an `Encounter`, and `EnemySpawner`s that
+/// keep a few enemies about. The `EncounterDirector` keeps the number of enemies alive at once in check,
+/// across every encounter and spawner.
+pub struct EncounterPlugin;
+
+impl Plugin for EncounterPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<EncounterDirector>();
+        app.add_event::<WaveStarted>();
+        app.add_event::<WaveCleared>();
+        app.add_event::<WaveFailed>();
+        app.add_systems(
+            Update,
+            (run_encounters, run_spawners).chain().after(DamageSet),
+        );
+    }
+}
+
+// Enemies are dropped from a little above their spawn point, so they don't start out stuck in the ground.
+const SPAWN_CLEARANCE: f32 = 2.0;
+
+#[derive(Resource, Debug, Clone)]
+pub struct EncounterDirector {
+    /// No more encounter and spawner enemies than this are alive at once. Spawns that are due wait for a free
+    /// place.
+    pub max_alive: usize,
+}
+
+impl Default for EncounterDirector {
+    fn default() -> Self {
+        EncounterDirector { max_alive: 12 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Reflect)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum EnemyKind {
+    /// Runs at the player and hits them.
+    Grunt,
+    /// Slow and tough, and hits hard.
+    Brute,
+    /// Keeps its distance and throws fireballs.
+    Caster,
+}
+
+/// An encounter, as laid out in a level file.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EncounterDefinition {
+    pub position: Vec3,
+    /// The first wave is on its way once the player comes this close to `position`.
+    pub trigger_radius: f32,
+    /// Enemies take turns spawning at each of these, dropped onto the terrain below.
+    pub spawn_points: Vec<Vec3>,
+    pub waves: Vec<WaveDefinition>,
+    /// Once the last wave is cleared, start over from the first with more and tougher enemies. Without this,
+    /// clearing the last wave clears the encounter.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub escalation: Option<Escalation>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WaveDefinition {
+    /// Seconds between the encounter being triggered, or the previous wave being cleared, and this wave.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub delay: f32,
+    pub groups: Vec<WaveGroup>,
+    /// Seconds that the player has to clear the wave before it fails.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub time_limit: Option<f32>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WaveGroup {
+    pub kind: EnemyKind,
+    pub count: u32,
+    /// Seconds into the wave that the first of the group spawns.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub start: f32,
+    /// Seconds between each of the group spawning.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub interval: f32,
+}
+
+/// How much harder each round of waves gets, as fractions of the first round.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Escalation {
+    /// Extra enemies in each group per round.
+    pub count: f32,
+    /// Extra hit points for each enemy per round.
+    pub hp: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum EncounterState {
+    /// Waiting for the player to come within the trigger radius.
+    Dormant,
+    /// Counting down to `wave`.
+    Countdown {
+        wave: usize,
+        remaining: f32,
+    },
+    /// Fighting `wave`, `elapsed` seconds in.
+    InWave {
+        wave: usize,
+        elapsed: f32,
+    },
+    Cleared,
+    Failed,
+}
+
+/// Waves count up from 0 through every round, so with 3 waves, wave 4 is the second wave of the second round.
+#[derive(Component, Debug, Clone)]
+pub struct Encounter {
+    pub definition: EncounterDefinition,
+    pub state: EncounterState,
+    // Spawns left in the current wave, soonest first.
+    pending: VecDeque<ScheduledSpawn>,
+    next_spawn_point: usize,
+}
+
+impl Encounter {
+    pub fn new(definition: EncounterDefinition) -> Self {
+        Encounter {
+            definition,
+            state: EncounterState::Dormant,
+            pending: VecDeque::new(),
+            next_spawn_point: 0,
+        }
+    }
+
+    /// Puts the encounter back to waiting for the player, as the level started it.
+    pub fn rearm(&mut self) {
+        *self = Encounter::new(self.definition.clone());
+    }
+
+    /// Picks up from a saved `state`. Enemies aren't saved, so a wave that was under way starts over.
+    pub fn resume(&mut self, state: EncounterState) {
+        self.rearm();
+        self.state = match state {
+            EncounterState::InWave { wave, .. } if !self.definition.waves.is_empty() => {
+                EncounterState::Countdown {
+                    wave,
+                    remaining: self.wave_definition(wave).delay,
+                }
+            }
+            state => state,
+        };
+    }
+
+    fn wave_definition(&self, wave: usize) -> &WaveDefinition {
+        &self.definition.waves[wave % self.definition.waves.len()]
+    }
+
+    // Whether there's a wave after `wave`.
+    fn has_wave(&self, wave: usize) -> bool {
+        wave < self.definition.waves.len() || self.definition.escalation.is_some()
+    }
+
+    fn spawn_point(&mut self) -> Vec3 {
+        let points = &self.definition.spawn_points;
+        if points.is_empty() {
+            return self.definition.position;
+        }
+        let point = points[self.next_spawn_point % points.len()];
+        self.next_spawn_point += 1;
+        point
+    }
+}
+
+/// Marks the enemies that an encounter spawned.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct EncounterMember {
+    pub encounter: Entity,
+}
+
+/// An enemy spawner, as laid out in a level file.
+#[derive(Debug, Clone, Reflect)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EnemySpawnerDefinition {
+    pub kind: EnemyKind,
+    pub position: Vec3,
+    /// The spawner stops sending enemies while this many of its own are alive.
+    pub max_alive: usize,
+    /// Seconds between spawns.
+    pub interval: f32,
+    /// The spawner only runs while the player is this close. Without one, it always runs.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub activation_radius: Option<f32>,
+}
+
+/// Keeps up to `max_alive` enemies of one kind coming from a spot, for as long as the level lasts.
+#[derive(Component, Debug, Clone)]
+pub struct EnemySpawner {
+    pub definition: EnemySpawnerDefinition,
+    timer: Timer,
+}
+
+impl EnemySpawner {
+    pub fn new(definition: EnemySpawnerDefinition) -> Self {
+        EnemySpawner {
+            timer: Timer::from_seconds(definition.interval, TimerMode::Repeating),
+            definition,
+        }
+    }
+}
+
+/// Marks the enemies that a spawner spawned.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct SpawnerMember {
+    pub spawner: Entity,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct WaveStarted {
+    pub encounter: Entity,
+    pub wave: usize,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct WaveCleared {
+    pub encounter: Entity,
+    pub wave: usize,
+    /// Whether that was the encounter's last wave.
+    pub last: bool,
+}
+
+/// The wave ran out of time, or the player died. The encounter is over.
+#[derive(Event, Debug, Clone)]
+pub struct WaveFailed {
+    pub encounter: Entity,
+    pub wave: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct ScheduledSpawn {
+    /// Seconds into the wave.
+    at: f32,
+    kind: EnemyKind,
+    hp_scale: f32,
+}
+
+impl WaveDefinition {
+    // Every enemy in the wave, soonest first, as of the `round`th time through the waves.
+    fn schedule(&self, round: usize, escalation: Option<&Escalation>) -> Vec<ScheduledSpawn> {
+        let (count_scale, hp_scale) = escalation.map_or((1.0, 1.0), |escalation| {
+            (
+                1.0 + escalation.count * round as f32,
+                1.0 + escalation.hp * round as f32,
+            )
+        });
+        let mut spawns: Vec<ScheduledSpawn> = self
+            .groups
+            .iter()
+            .flat_map(|group| {
+                let count = (group.count as f32 * count_scale).round() as u32;
+                (0..count).map(move |i| ScheduledSpawn {
+                    at: group.start + group.interval * i as f32,
+                    kind: group.kind,
+                    hp_scale,
+                })
+            })
+            .collect();
+        spawns.sort_by(|a, b| a.at.total_cmp(&b.at));
+        spawns
+    }
+}
+
+impl EnemyKind {
+    fn spawn(
+        &self,
+        commands: &mut Commands,
+        assets: &AssetServer,
+        position: Vec3,
+        hp_scale: f32,
+    ) -> Entity {
+        let mut bundle = EnemyBundle::default();
+        let mut bounty = 20;
+        match self {
+            EnemyKind::Grunt => {}
+            EnemyKind::Brute => {
+                bounty = 50;
+                bundle.hitpoints.max = 150;
+                bundle.enemy.speed = 4.;
+                bundle.enemy.flee_below = 0.;
+                bundle.attack = EnemyAttack::new(
+                    AttackKind::Melee { damage: 25. },
+                    3.,
+                    Duration::from_millis(1500),
+                );
+            }
+            EnemyKind::Caster => {
+                bounty = 35;
+                bundle.hitpoints.max = 35;
+                bundle.enemy = Enemy {
+                    speed: 5.,
+                    flee_below: 0.5,
+                    ..default()
+                };
+                bundle.attack = EnemyAttack::new(
+                    AttackKind::Ranged { slot: 0 },
+                    30.,
+                    Duration::from_millis(2500),
+                );
+            }
+        }
+        bundle.hitpoints.max = (bundle.hitpoints.max as f32 * hp_scale).round() as u32;
+        bundle.hitpoints.current = bundle.hitpoints.max;
+
+        // Tougher enemies are worth more.
+        let bounty = XpBounty((bounty as f32 * hp_scale).round() as u32);
+        let mut enemy = commands.spawn((bundle, bounty, Transform::from_translation(position)));
+        if *self == EnemyKind::Caster {
+            enemy.insert((
+                Spellbook::from_files(assets, &["spells/fireball.spell.ron"]),
+                Mana {
+                    current: 50,
+                    max: 50,
+                },
+                ManaRegen {
+                    regen_mana_timer: Timer::from_seconds(1., TimerMode::Repeating),
+                    regen_per_tick: 5,
+                },
+            ));
+        }
+        enemy.id()
+    }
+}
+
+fn run_encounters(
+    mut commands: Commands,
+    time: Res<Time>,
+    director: Res<EncounterDirector>,
+    navigation: Res<Navigation>,
+    assets: Res<AssetServer>,
+    players: Query<&Transform, With<PlayerInput>>,
+    mut encounters: Query<(Entity, &mut Encounter)>,
+    members: Query<&EncounterMember, Without<Dead>>,
+    spawned: Query<(), (With<SpawnerMember>, Without<Dead>)>,
+    mut started: EventWriter<WaveStarted>,
+    mut cleared: EventWriter<WaveCleared>,
+    mut failed: EventWriter<WaveFailed>,
+) {
+    let mut alive: HashMap<Entity, usize> = HashMap::new();
+    for member in &members {
+        *alive.entry(member.encounter).or_default() += 1;
+    }
+    let mut total_alive: usize = alive.values().sum::<usize>() + spawned.iter().count();
+    let delta = time.delta_seconds();
+
+    for (entity, mut encounter) in &mut encounters {
+        let state = encounter.state.clone();
+        match state {
+            EncounterState::Dormant => {
+                let triggered = players.iter().any(|player| {
+                    player.translation.distance(encounter.definition.position)
+                        <= encounter.definition.trigger_radius
+                });
+                if !triggered {
+                    continue;
+                }
+                encounter.state = if encounter.definition.waves.is_empty() {
+                    EncounterState::Cleared
+                } else {
+                    EncounterState::Countdown {
+                        wave: 0,
+                        remaining: encounter.wave_definition(0).delay,
+                    }
+                };
+            }
+            EncounterState::Countdown { wave, remaining } => {
+                let remaining = remaining - delta;
+                if remaining > 0.0 {
+                    encounter.state = EncounterState::Countdown { wave, remaining };
+                    continue;
+                }
+                let round = wave / encounter.definition.waves.len();
+                let schedule = encounter
+                    .wave_definition(wave)
+                    .schedule(round, encounter.definition.escalation.as_ref());
+                encounter.pending = schedule.into();
+                encounter.state = EncounterState::InWave { wave, elapsed: 0.0 };
+                started.send(WaveStarted {
+                    encounter: entity,
+                    wave,
+                });
+            }
+            EncounterState::InWave { wave, elapsed } => {
+                let elapsed = elapsed + delta;
+                let out_of_time = encounter
+                    .wave_definition(wave)
+                    .time_limit
+                    .is_some_and(|limit| elapsed > limit);
+                // Everyone died.
+                if players.is_empty() || out_of_time {
+                    encounter.pending.clear();
+                    encounter.state = EncounterState::Failed;
+                    failed.send(WaveFailed {
+                        encounter: entity,
+                        wave,
+                    });
+                    continue;
+                }
+                encounter.state = EncounterState::InWave { wave, elapsed };
+
+                let members_alive = alive.entry(entity).or_default();
+                while total_alive < director.max_alive
+                    && encounter
+                        .pending
+                        .front()
+                        .is_some_and(|next| next.at <= elapsed)
+                {
+                    let spawn = encounter.pending.pop_front().unwrap();
+                    let point = encounter.spawn_point();
+                    let enemy = spawn.kind.spawn(
+                        &mut commands,
+                        &assets,
+                        drop_point(&navigation, point),
+                        spawn.hp_scale,
+                    );
+                    commands
+                        .entity(enemy)
+                        .insert(EncounterMember { encounter: entity });
+                    *members_alive += 1;
+                    total_alive += 1;
+                }
+
+                if encounter.pending.is_empty() && *members_alive == 0 {
+                    let next = wave + 1;
+                    let last = !encounter.has_wave(next);
+                    cleared.send(WaveCleared {
+                        encounter: entity,
+                        wave,
+                        last,
+                    });
+                    encounter.state = if last {
+                        EncounterState::Cleared
+                    } else {
+                        EncounterState::Countdown {
+                            wave: next,
+                            remaining: encounter.wave_definition(next).delay,
+                        }
+                    };
+                }
+            }
+            EncounterState::Cleared | EncounterState::Failed => {}
+        }
+    }
+}
+
+fn run_spawners(
+    mut commands: Commands,
+    time: Res<Time>,
+    director: Res<EncounterDirector>,
+    navigation: Res<Navigation>,
+    assets: Res<AssetServer>,
+    players: Query<&Transform, With<PlayerInput>>,
+    mut spawners: Query<(Entity, &mut EnemySpawner)>,
+    encounter_members: Query<(), (With<EncounterMember>, Without<Dead>)>,
+    members: Query<&SpawnerMember, Without<Dead>>,
+) {
+    let mut alive: HashMap<Entity, usize> = HashMap::new();
+    for member in &members {
+        *alive.entry(member.spawner).or_default() += 1;
+    }
+    let mut total_alive = alive.values().sum::<usize>() + encounter_members.iter().count();
+
+    for (entity, mut spawner) in &mut spawners {
+        let active = spawner.definition.activation_radius.is_none_or(|radius| {
+            players
+                .iter()
+                .any(|player| player.translation.distance(spawner.definition.position) <= radius)
+        });
+        if !active {
+            continue;
+        }
+        let members_alive = alive.get(&entity).copied().unwrap_or_default();
+        if members_alive >= spawner.definition.max_alive {
+            // Start the wait for a replacement once one is needed.
+            spawner.timer.reset();
+            continue;
+        }
+        if !spawner.timer.tick(time.delta()).just_finished() || total_alive >= director.max_alive {
+            continue;
+        }
+        let enemy = spawner.definition.kind.spawn(
+            &mut commands,
+            &assets,
+            drop_point(&navigation, spawner.definition.position),
+            1.0,
+        );
+        commands
+            .entity(enemy)
+            .insert(SpawnerMember { spawner: entity });
+        total_alive += 1;
+    }
+}
+
+/// Where to drop an enemy that's to spawn at `point`: just above the ground, if the ground's known there.
+fn drop_point(navigation: &Navigation, point: Vec3) -> Vec3 {
+    let ground = navigation
+        .grid
+        .as_ref()
+        .and_then(|grid| grid.ground(point))
+        .map_or(point, |ground| Vec3::new(point.x, ground.y, point.z));
+    ground + Vec3::Y * SPAWN_CLEARANCE
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{enemy::EnemyAiPlugin, hitpoints::HpPlugin, items::CastSpell};
+    use bevy::time::TimeUpdateStrategy;
+
+    fn grunts(count: u32, interval: f32) -> WaveDefinition {
+        WaveDefinition {
+            delay: 1.0,
+            groups: vec![WaveGroup {
+                kind: EnemyKind::Grunt,
+                count,
+                start: 0.0,
+                interval,
+            }],
+            time_limit: None,
+        }
+    }
+
+    fn app(waves: Vec<WaveDefinition>) -> (App, Entity) {
+        let mut app = App::new();
+        app.add_plugins((
+            MinimalPlugins,
+            AssetPlugin::default(),
+            HpPlugin,
+            EnemyAiPlugin,
+            EncounterPlugin,
+        ))
+        .init_resource::<Navigation>()
+        .add_event::<CastSpell>()
+        .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            100,
+        )));
+        app.world_mut()
+            .spawn((Player, PlayerInput::default(), Transform::default()));
+        let encounter = app
+            .world_mut()
+            .spawn(Encounter::new(EncounterDefinition {
+                position: Vec3::ZERO,
+                trigger_radius: 20.0,
+                spawn_points: vec![Vec3::new(50., 0., 0.), Vec3::new(-50., 0., 0.)],
+                waves,
+                escalation: None,
+            }))
+            .id();
+        (app, encounter)
+    }
+
+    fn step(app: &mut App, updates: usize) {
+        for _ in 0..updates {
+            app.update();
+        }
+    }
+
+    fn state(app: &App, encounter: Entity) -> EncounterState {
+        app.world()
+            .get::<Encounter>(encounter)
+            .unwrap()
+            .state
+            .clone()
+    }
+
+    fn members(app: &mut App) -> Vec<Entity> {
+        app.world_mut()
+            .query_filtered::<Entity, With<EncounterMember>>()
+            .iter(app.world())
+            .collect()
+    }
+
+    fn kill_members(app: &mut App) {
+        for member in members(app) {
+            app.world_mut().despawn(member);
+        }
+    }
+
+    #[test]
+    fn escalation_adds_enemies_and_hit_points() {
+        let wave = grunts(4, 0.5);
+        let escalation = Escalation {
+            count: 0.5,
+            hp: 1.0,
+        };
+        let first = wave.schedule(0, Some(&escalation));
+        assert_eq!(first.len(), 4);
+        assert_eq!(first[3].at, 1.5);
+        let third = wave.schedule(2, Some(&escalation));
+        assert_eq!(third.len(), 8);
+        assert!(third.iter().all(|spawn| spawn.hp_scale == 3.0));
+    }
+
+    #[test]
+    fn waves_run_in_order_until_cleared() {
+        let (mut app, encounter) = app(vec![grunts(2, 0.0), grunts(1, 0.0)]);
+        step(&mut app, 15);
+        assert!(
+            matches!(
+                state(&app, encounter),
+                EncounterState::InWave { wave: 0, .. }
+            ),
+            "{:?}",
+            state(&app, encounter)
+        );
+        assert_eq!(members(&mut app).len(), 2);
+
+        kill_members(&mut app);
+        step(&mut app, 1);
+        assert!(matches!(
+            state(&app, encounter),
+            EncounterState::Countdown { wave: 1, .. }
+        ));
+        step(&mut app, 15);
+        assert_eq!(members(&mut app).len(), 1);
+
+        kill_members(&mut app);
+        step(&mut app, 1);
+        assert_eq!(state(&app, encounter), EncounterState::Cleared);
+        let cleared = app.world().resource::<Events<WaveCleared>>();
+        assert!(cleared
+            .get_reader()
+            .read(cleared)
+            .any(|event| event.wave == 1 && event.last));
+    }
+
+    #[test]
+    fn director_caps_enemies_alive_at_once() {
+        let (mut app, _) = app(vec![grunts(10, 0.0)]);
+        app.insert_resource(EncounterDirector { max_alive: 3 });
+        step(&mut app, 20);
+        assert_eq!(members(&mut app).len(), 3);
+        kill_members(&mut app);
+        step(&mut app, 1);
+        assert_eq!(members(&mut app).len(), 3);
+    }
+
+    #[test]
+    fn spawners_replace_their_enemies() {
+        let (mut app, _) = app(vec![]);
+        app.world_mut()
+            .spawn(EnemySpawner::new(EnemySpawnerDefinition {
+                kind: EnemyKind::Grunt,
+                position: Vec3::new(0., 0., 100.),
+                max_alive: 2,
+                interval: 1.0,
+                activation_radius: None,
+            }));
+        let spawned = |app: &mut App| {
+            app.world_mut()
+                .query_filtered::<Entity, With<SpawnerMember>>()
+                .iter(app.world())
+                .collect::<Vec<_>>()
+        };
+        step(&mut app, 5);
+        assert_eq!(spawned(&mut app).len(), 0, "waits for the first interval");
+        step(&mut app, 30);
+        assert_eq!(spawned(&mut app).len(), 2);
+
+        let victim = spawned(&mut app)[0];
+        app.world_mut().despawn(victim);
+        step(&mut app, 5);
+        assert_eq!(spawned(&mut app).len(), 1, "waits to replace it");
+        step(&mut app, 10);
+        assert_eq!(spawned(&mut app).len(), 2);
+    }
+
+    #[test]
+    fn waves_fail_when_time_runs_out() {
+        let mut wave = grunts(1, 0.0);
+        wave.time_limit = Some(2.0);
+        let (mut app, encounter) = app(vec![wave]);
+        for _ in 0..40 {
+            app.update();
+            if state(&app, encounter) == EncounterState::Failed {
+                break;
+            }
+        }
+        assert_eq!(state(&app, encounter), EncounterState::Failed);
+        let failed = app.world().resource::<Events<WaveFailed>>();
+        assert_eq!(failed.get_reader().read(failed).count(), 1);
+    }
+
+    #[test]
+    fn waits_for_the_player() {
+        let (mut app, encounter) = app(vec![grunts(1, 0.0)]);
+        let mut player = app
+            .world_mut()
+            .query_filtered::<&mut Transform, With<Player>>();
+        player.single_mut(app.world_mut()).translation = Vec3::new(100., 0., 0.);
+        step(&mut app, 20);
+        assert_eq!(state(&app, encounter), EncounterState::Dormant);
+        assert!(members(&mut app).is_empty());
+    }
+}
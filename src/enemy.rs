@@ -1,26 +1,585 @@
-use crate::prelude::*;
+use crate::{
+    camera::Flycam,
+    hitpoints::{DamageEvent, DamageType, Hp},
+    items::CastSpell,
+    navigation::{NavAgent, NavigationSet},
+    player::{PlayerInput, GRAVITY},
+    prelude::*,
+    status::StatusEffects,
+};
 use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use std::time::Duration;
 
+/// Enemies in the world: their AI, plus the meshes, colliders and character controllers that carry it out.
 pub struct EnemyPlugin;
 
 impl Plugin for EnemyPlugin {
-    fn build(&self, app: &mut App) {}
+    fn build(&self, app: &mut App) {
+        app.add_plugins(EnemyAiPlugin);
+        app.add_systems(PreUpdate, load_enemies);
+        app.add_systems(
+            FixedUpdate,
+            (
+                line_of_sight
+                    .after(EnemySet::Perceive)
+                    .before(EnemySet::Decide),
+                steer_along_paths.after(EnemySet::Act).after(NavigationSet),
+                move_enemies.after(steer_along_paths),
+            ),
+        );
+    }
 }
 
-#[derive(Debug, Copy, Clone, Default)]
-enum EnemyBehavior {
+/// The AI on its own, which doesn't need a physics world. Enemies see everything within their aggro radius
+/// unless `EnemyPlugin`'s line of sight check says otherwise, and only say where they want to go in
+/// `EnemyMotion`.
+pub struct EnemyAiPlugin;
+
+impl Plugin for EnemyAiPlugin {
+    fn build(&self, app: &mut App) {
+        app.configure_sets(
+            FixedUpdate,
+            (EnemySet::Perceive, EnemySet::Decide, EnemySet::Act).chain(),
+        );
+        app.add_systems(
+            FixedUpdate,
+            (
+                perceive.in_set(EnemySet::Perceive),
+                decide.in_set(EnemySet::Decide),
+                act.in_set(EnemySet::Act),
+            ),
+        );
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum EnemySet {
+    Perceive,
+    Decide,
+    Act,
+}
+
+const ENEMY_RADIUS: f32 = 0.5;
+const ENEMY_HALF_HEIGHT: f32 = 0.5;
+const EYE_HEIGHT: f32 = 0.8;
+// How close a fleeing or pursuing enemy has to get to where it's going to count as there.
+const ARRIVAL_DISTANCE: f32 = 1.0;
+// How far in front of the flycam an enemy spawned from the HUD is dropped onto the terrain.
+const ENEMY_SPAWN_DISTANCE: f32 = 30.0;
+
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum EnemyBehavior {
     #[default]
+    Idle,
+    /// Chasing the player, or where the player was last seen.
     InterceptingPlayer,
+    Attacking,
+    Fleeing,
 }
 
-#[derive(Component, Debug, Copy, Clone, Default)]
+/// An enemy. An `EnemyBundle` holds only its brain and stats; `load_enemies` gives it a capsule body and a
+/// character controller. One spawned without a Transform is dropped onto the terrain in front of the flycam.
+#[derive(Component, Debug, Copy, Clone)]
 pub struct Enemy {
-    visual_aggro_radius: f32,
-    behavior: EnemyBehavior,
+    pub visual_aggro_radius: f32,
+    pub behavior: EnemyBehavior,
+    pub speed: f32,
+    /// Below this fraction of its max Hp, the enemy runs away.
+    pub flee_below: f32,
+}
+
+impl Default for Enemy {
+    fn default() -> Self {
+        Enemy {
+            visual_aggro_radius: 60.,
+            behavior: EnemyBehavior::Idle,
+            speed: 6.,
+            flee_below: 0.25,
+        }
+    }
+}
+
+/// What the enemy knows about the nearest player.
+#[derive(Component, Debug, Clone, Default)]
+pub struct EnemyPerception {
+    pub target: Option<Entity>,
+    pub target_position: Vec3,
+    pub distance: f32,
+    pub sees_target: bool,
+    /// Where the player was when the enemy last saw them. Cleared once the enemy gets there.
+    pub last_seen: Option<Vec3>,
+}
+
+#[derive(Debug, Clone)]
+pub enum AttackKind {
+    Melee {
+        damage: f32,
+    },
+    /// Casts the spell in this slot of the enemy's `Spellbook`.
+    Ranged {
+        slot: usize,
+    },
+}
+
+#[derive(Component, Debug, Clone)]
+pub struct EnemyAttack {
+    pub kind: AttackKind,
+    pub range: f32,
+    pub cooldown: Timer,
 }
 
-#[derive(Bundle, Default)]
+impl EnemyAttack {
+    pub fn new(kind: AttackKind, range: f32, cooldown: Duration) -> Self {
+        EnemyAttack {
+            kind,
+            range,
+            cooldown: Timer::new(cooldown, TimerMode::Repeating),
+        }
+    }
+}
+
+impl Default for EnemyAttack {
+    fn default() -> Self {
+        EnemyAttack::new(
+            AttackKind::Melee { damage: 10. },
+            2.5,
+            Duration::from_millis(1000),
+        )
+    }
+}
+
+/// Where the AI wants to go. `move_enemies` carries it out with the character controller.
+#[derive(Component, Debug, Clone, Default)]
+pub struct EnemyMotion {
+    /// Horizontal velocity, in world space.
+    pub desired_velocity: Vec3,
+    /// Where the enemy is headed, if anywhere in particular. Enemies with a `NavAgent` find their way there
+    /// around cliffs instead of walking straight at it.
+    pub destination: Option<Vec3>,
+    vertical_speed: f32,
+}
+
+#[derive(Bundle)]
 pub struct EnemyBundle {
-    enemy: Enemy,
-    hitpoints: crate::hitpoints::Hp,
+    pub enemy: Enemy,
+    pub hitpoints: Hp,
+    pub perception: EnemyPerception,
+    pub attack: EnemyAttack,
+    pub motion: EnemyMotion,
+}
+
+impl Default for EnemyBundle {
+    fn default() -> Self {
+        EnemyBundle {
+            enemy: Enemy::default(),
+            hitpoints: Hp {
+                current: 50,
+                max: 50,
+            },
+            perception: EnemyPerception::default(),
+            attack: EnemyAttack::default(),
+            motion: EnemyMotion::default(),
+        }
+    }
+}
+
+/// Each enemy goes after the nearest player, whether that's the local one or one that the network server
+/// runs for a client.
+fn perceive(
+    players: Query<(Entity, &Transform), (With<PlayerInput>, Without<Enemy>)>,
+    mut enemies: Query<(&Enemy, &Transform, &mut EnemyPerception)>,
+) {
+    for (enemy, transform, mut perception) in &mut enemies {
+        let nearest = players.iter().min_by(|(_, a), (_, b)| {
+            a.translation
+                .distance_squared(transform.translation)
+                .total_cmp(&b.translation.distance_squared(transform.translation))
+        });
+        let Some((player, player_transform)) = nearest else {
+            perception.target = None;
+            perception.sees_target = false;
+            perception.last_seen = None;
+            continue;
+        };
+        perception.target = Some(player);
+        perception.target_position = player_transform.translation;
+        perception.distance = transform.translation.distance(player_transform.translation);
+        perception.sees_target = perception.distance <= enemy.visual_aggro_radius;
+    }
+}
+
+fn line_of_sight(
+    rapier_context: Res<RapierContext>,
+    mut enemies: Query<(Entity, &Transform, &mut EnemyPerception)>,
+) {
+    for (entity, transform, mut perception) in &mut enemies {
+        let Some(target) = perception.target.filter(|_| perception.sees_target) else {
+            continue;
+        };
+        let eye = transform.translation + Vec3::Y * EYE_HEIGHT;
+        let to_target = perception.target_position - eye;
+        let hit = rapier_context.cast_ray(
+            eye,
+            to_target.normalize_or_zero(),
+            to_target.length(),
+            true,
+            QueryFilter::default().exclude_collider(entity),
+        );
+        perception.sees_target = match hit {
+            Some((blocker, _)) => blocker == target,
+            None => true,
+        };
+    }
+}
+
+/// Picks a behavior from what the enemy knows. Also remembers where the player was last seen.
+fn decide(mut enemies: Query<(&mut Enemy, &Hp, &mut EnemyPerception, Option<&EnemyAttack>)>) {
+    for (mut enemy, hp, mut perception, attack) in &mut enemies {
+        if perception.sees_target {
+            perception.last_seen = Some(perception.target_position);
+        }
+        let hurt = hp.max > 0 && (hp.current as f32) < hp.max as f32 * enemy.flee_below;
+        let in_range = attack.is_some_and(|attack| perception.distance <= attack.range);
+
+        enemy.behavior = if hurt && perception.last_seen.is_some() {
+            EnemyBehavior::Fleeing
+        } else if perception.sees_target && in_range {
+            EnemyBehavior::Attacking
+        } else if perception.last_seen.is_some() {
+            EnemyBehavior::InterceptingPlayer
+        } else {
+            EnemyBehavior::Idle
+        };
+    }
+}
+
+fn act(
+    time: Res<Time>,
+    mut enemies: Query<(
+        Entity,
+        &Enemy,
+        &Transform,
+        &mut EnemyPerception,
+        &mut EnemyMotion,
+        Option<&mut EnemyAttack>,
+    )>,
+    mut damage: EventWriter<DamageEvent>,
+    mut casts: EventWriter<CastSpell>,
+) {
+    for (entity, enemy, transform, mut perception, mut motion, attack) in &mut enemies {
+        let position = transform.translation;
+        let flat_toward = |target: Vec3| {
+            let mut toward = target - position;
+            toward.y = 0.0;
+            toward.normalize_or_zero()
+        };
+
+        let last_seen = perception.last_seen;
+        motion.destination = None;
+        motion.desired_velocity = match enemy.behavior {
+            EnemyBehavior::Idle | EnemyBehavior::Attacking => Vec3::ZERO,
+            EnemyBehavior::InterceptingPlayer => match last_seen {
+                Some(goal) if position.distance(goal) > ARRIVAL_DISTANCE => {
+                    motion.destination = Some(goal);
+                    flat_toward(goal) * enemy.speed
+                }
+                _ => {
+                    // Got to where the player was and they're nowhere to be seen.
+                    perception.last_seen = None;
+                    Vec3::ZERO
+                }
+            },
+            EnemyBehavior::Fleeing => match last_seen {
+                Some(threat) if position.distance(threat) < enemy.visual_aggro_radius => {
+                    -flat_toward(threat) * enemy.speed
+                }
+                _ => {
+                    // Far enough away to forget about it.
+                    perception.last_seen = None;
+                    Vec3::ZERO
+                }
+            },
+        };
+
+        let Some(mut attack) = attack else {
+            continue;
+        };
+        attack.cooldown.tick(time.delta());
+        if enemy.behavior != EnemyBehavior::Attacking || !attack.cooldown.finished() {
+            continue;
+        }
+        let Some(target) = perception.target else {
+            continue;
+        };
+        match attack.kind {
+            AttackKind::Melee { damage: amount } => {
+                damage.send(DamageEvent {
+                    source: Some(entity),
+                    target,
+                    amount,
+                    damage_type: DamageType::Physical,
+                    hit_point: perception.target_position,
+                    status: None,
+                });
+            }
+            AttackKind::Ranged { slot } => {
+                let eye = position + Vec3::Y * EYE_HEIGHT;
+                let Ok(direction) = Dir3::new(perception.target_position - eye) else {
+                    continue;
+                };
+                casts.send(CastSpell {
+                    caster: entity,
+                    slot,
+                    origin: eye + direction * (ENEMY_RADIUS * 2.0),
+                    direction,
+                });
+            }
+        }
+    }
+}
+
+/// Turns enemies that are headed somewhere toward the next waypoint of their path, keeping their speed.
+fn steer_along_paths(mut enemies: Query<(&Transform, &mut EnemyMotion, &mut NavAgent)>) {
+    for (transform, mut motion, mut agent) in &mut enemies {
+        agent.destination = motion.destination;
+        let Some(waypoint) = agent.next_waypoint() else {
+            continue;
+        };
+        let mut toward = waypoint - transform.translation;
+        toward.y = 0.0;
+        motion.desired_velocity = toward.normalize_or_zero() * motion.desired_velocity.length();
+    }
+}
+
+fn move_enemies(
+    time: Res<Time>,
+    mut enemies: Query<(
+        &mut Transform,
+        &mut EnemyMotion,
+        &mut KinematicCharacterController,
+        Option<&KinematicCharacterControllerOutput>,
+        Option<&StatusEffects>,
+    )>,
+) {
+    let delta_time = time.delta_seconds();
+    for (mut transform, mut motion, mut controller, output, statuses) in &mut enemies {
+        if output.is_some_and(|output| output.grounded) {
+            motion.vertical_speed = 0.0;
+        }
+        motion.vertical_speed += GRAVITY * delta_time;
+        let speed = statuses.map_or(1.0, |statuses| statuses.speed_multiplier());
+        let velocity = motion.desired_velocity * speed + Vec3::Y * motion.vertical_speed;
+        controller.translation = Some(velocity * delta_time);
+
+        // Face the way we're going.
+        if motion.desired_velocity != Vec3::ZERO {
+            let target = transform.translation + motion.desired_velocity;
+            transform.look_at(
+                Vec3::new(target.x, transform.translation.y, target.z),
+                Vec3::Y,
+            );
+        }
+    }
+}
+
+fn load_enemies(
+    mut commands: Commands,
+    enemies: Query<(Entity, Option<&Transform>), Added<Enemy>>,
+    flycam: Query<&GlobalTransform, With<Flycam>>,
+    rapier_context: Res<RapierContext>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if enemies.is_empty() {
+        return;
+    }
+    let mesh = meshes.add(Capsule3d::new(ENEMY_RADIUS, ENEMY_HALF_HEIGHT * 2.0));
+    let material = materials.add(Palette::Red.to_color());
+
+    for (entity, transform) in &enemies {
+        let transform = match transform {
+            Some(t) => *t,
+            None => {
+                let Ok(cam) = flycam.get_single() else {
+                    warn!(
+                        "Enemy spawned without a Transform and there's no Flycam to place it with."
+                    );
+                    commands.entity(entity).despawn_recursive();
+                    continue;
+                };
+                let ahead = cam.translation() + cam.forward() * ENEMY_SPAWN_DISTANCE;
+                let ground = rapier_context
+                    .cast_ray(
+                        ahead,
+                        Vec3::NEG_Y,
+                        f32::MAX,
+                        true,
+                        QueryFilter::only_fixed(),
+                    )
+                    .map(|(_, toi)| ahead + Vec3::NEG_Y * toi)
+                    .unwrap_or(ahead);
+                Transform::from_translation(ground + Vec3::Y * (ENEMY_HALF_HEIGHT + ENEMY_RADIUS))
+            }
+        };
+        info!("Spawning enemy at {}", transform.translation);
+        commands.entity(entity).insert((
+            Name::new("enemy"),
+            PbrBundle {
+                mesh: mesh.clone(),
+                material: material.clone(),
+                transform,
+                ..default()
+            },
+            RigidBody::KinematicPositionBased,
+            Collider::capsule_y(ENEMY_HALF_HEIGHT, ENEMY_RADIUS),
+            KinematicCharacterController {
+                up: Vec3::Y,
+                offset: CharacterLength::Absolute(0.01),
+                max_slope_climb_angle: 45.0_f32.to_radians(),
+                min_slope_slide_angle: 30.0_f32.to_radians(),
+                snap_to_ground: Some(CharacterLength::Absolute(0.2)),
+                ..default()
+            },
+            NavAgent::default(),
+            OutOfBounds::Teleport,
+        ));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hitpoints::HpPlugin;
+    use bevy::time::TimeUpdateStrategy;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, HpPlugin, EnemyAiPlugin))
+            .add_event::<CastSpell>()
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )));
+        app
+    }
+
+    fn spawn_player(app: &mut App, position: Vec3) -> Entity {
+        app.world_mut()
+            .spawn((
+                Player,
+                PlayerInput::default(),
+                Transform::from_translation(position),
+                Hp {
+                    current: 100,
+                    max: 100,
+                },
+            ))
+            .id()
+    }
+
+    fn spawn_enemy(app: &mut App, hp: u32) -> Entity {
+        let mut bundle = EnemyBundle::default();
+        bundle.hitpoints.current = hp;
+        app.world_mut()
+            .spawn((bundle, Transform::from_translation(Vec3::ZERO)))
+            .id()
+    }
+
+    fn step(app: &mut App, updates: usize) {
+        for _ in 0..updates {
+            app.update();
+        }
+    }
+
+    fn behavior(app: &App, enemy: Entity) -> EnemyBehavior {
+        app.world().get::<Enemy>(enemy).unwrap().behavior
+    }
+
+    fn desired_velocity(app: &App, enemy: Entity) -> Vec3 {
+        app.world()
+            .get::<EnemyMotion>(enemy)
+            .unwrap()
+            .desired_velocity
+    }
+
+    #[test]
+    fn idles_until_the_player_comes_within_aggro_radius() {
+        let mut app = app();
+        let enemy = spawn_enemy(&mut app, 50);
+        let player = spawn_player(&mut app, Vec3::new(100., 0., 0.));
+        step(&mut app, 3);
+        assert_eq!(behavior(&app, enemy), EnemyBehavior::Idle);
+        assert_eq!(desired_velocity(&app, enemy), Vec3::ZERO);
+
+        app.world_mut()
+            .get_mut::<Transform>(player)
+            .unwrap()
+            .translation = Vec3::new(30., 5., 0.);
+        step(&mut app, 3);
+        assert_eq!(behavior(&app, enemy), EnemyBehavior::InterceptingPlayer);
+        let velocity = desired_velocity(&app, enemy);
+        assert!(velocity.x > 0.0 && velocity.y == 0.0, "{velocity}");
+    }
+
+    #[test]
+    fn goes_after_the_nearest_player() {
+        let mut app = app();
+        let enemy = spawn_enemy(&mut app, 50);
+        spawn_player(&mut app, Vec3::new(40., 0., 0.));
+        // A client's character on the network server, which isn't the local `Player`.
+        let remote = app
+            .world_mut()
+            .spawn((
+                PlayerInput::default(),
+                Transform::from_translation(Vec3::new(-20., 0., 0.)),
+            ))
+            .id();
+        step(&mut app, 3);
+        let perception = app.world().get::<EnemyPerception>(enemy).unwrap();
+        assert_eq!(perception.target, Some(remote));
+        let velocity = desired_velocity(&app, enemy);
+        assert!(velocity.x < 0.0, "{velocity}");
+    }
+
+    #[test]
+    fn attacks_the_player_in_melee_range() {
+        let mut app = app();
+        let enemy = spawn_enemy(&mut app, 50);
+        let player = spawn_player(&mut app, Vec3::new(1.5, 0., 0.));
+        step(&mut app, 15);
+        assert_eq!(behavior(&app, enemy), EnemyBehavior::Attacking);
+        assert_eq!(desired_velocity(&app, enemy), Vec3::ZERO);
+        let hp = app.world().get::<Hp>(player).unwrap().current;
+        assert!(hp < 100, "player still has {hp} hp");
+    }
+
+    #[test]
+    fn flees_at_low_hp() {
+        let mut app = app();
+        let enemy = spawn_enemy(&mut app, 5);
+        spawn_player(&mut app, Vec3::new(10., 0., 0.));
+        step(&mut app, 3);
+        assert_eq!(behavior(&app, enemy), EnemyBehavior::Fleeing);
+        let velocity = desired_velocity(&app, enemy);
+        assert!(velocity.x < 0.0, "{velocity}");
+    }
+
+    #[test]
+    fn gives_up_the_chase_where_the_player_was_last_seen() {
+        let mut app = app();
+        let enemy = spawn_enemy(&mut app, 50);
+        let player = spawn_player(&mut app, Vec3::new(10., 0., 0.));
+        step(&mut app, 1);
+        // The player gets away, and the enemy arrives where it last saw them.
+        app.world_mut()
+            .get_mut::<Transform>(player)
+            .unwrap()
+            .translation = Vec3::new(500., 0., 0.);
+        app.world_mut()
+            .get_mut::<Transform>(enemy)
+            .unwrap()
+            .translation = Vec3::new(10., 0., 0.);
+        step(&mut app, 3);
+        assert_eq!(behavior(&app, enemy), EnemyBehavior::Idle);
+    }
 }
@@ -1,25 +1,97 @@
-use bevy::prelude::*;
+use crate::status::{ApplyStatus, StatusEffect, StatusEffects};
+use bevy::{prelude::*, utils::HashMap};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 pub struct HpPlugin;
 
 impl Plugin for HpPlugin {
     fn build(&self, app: &mut App) {
+        app.add_event::<DamageEvent>();
+        app.add_event::<DeathEvent>();
         app.add_systems(FixedUpdate, regen);
+        app.add_systems(Update, wear_off_invulnerability.before(DamageSet));
+        app.add_systems(
+            Update,
+            (apply_damage, despawn_dead).chain().in_set(DamageSet),
+        );
     }
 }
+
+/// Anything that sends `DamageEvent`s should run before this set to have them applied in the same frame.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DamageSet;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DamageType {
+    #[default]
+    Physical,
+    Fire,
+    Arcane,
+}
+
+/// Every source of damage (fireballs, hitscan, enemy attacks) sends one of these rather than touching `Hp`.
+#[derive(Event, Debug, Clone)]
+pub struct DamageEvent {
+    pub source: Option<Entity>,
+    pub target: Entity,
+    pub amount: f32,
+    pub damage_type: DamageType,
+    /// Where, in world space, the target was hit.
+    pub hit_point: Vec3,
+    /// Applied to the target along with the damage, unless the target is invulnerable.
+    pub status: Option<StatusEffect>,
+}
+
+/// The fraction, from 0 to 1, of each damage type that an entity shrugs off.
+#[derive(Component, Debug, Clone, Default)]
+pub struct Resistances(pub HashMap<DamageType, f32>);
+
+impl Resistances {
+    pub fn mitigate(&self, damage_type: DamageType, amount: f32) -> f32 {
+        let resistance = self.0.get(&damage_type).copied().unwrap_or_default();
+        amount * (1.0 - resistance.clamp(0.0, 1.0))
+    }
+}
+
+/// Added when an entity's `Hp` reaches zero. Dead entities are despawned at the end of `DamageSet`; for the
+/// player, that goes through the `Player` on_remove hook and back to `GameState::Prespawn`.
+#[derive(Component, Debug, Default)]
+pub struct Dead {
+    /// Whatever dealt the killing blow.
+    pub killer: Option<Entity>,
+    pub damage_type: DamageType,
+}
+
+/// Damage is ignored until the timer runs out, after which the component removes itself.
+#[derive(Component, Debug, Default)]
+pub struct Invulnerable(pub Timer);
+
+#[derive(Event, Debug, Clone)]
+pub struct DeathEvent {
+    pub entity: Entity,
+    pub killer: Option<Entity>,
+}
 #[derive(Component, Default)]
 pub struct Hp {
     pub current: u32,
     pub max: u32,
 }
 
+impl Hp {
+    pub fn restore(&mut self) {
+        self.current = self.max;
+    }
+}
+
 #[derive(Component, Default)]
 pub struct HpRegen {
     pub tick_timer: Timer,
     pub regen_per_tick: u32,
 }
 
-fn regen(time: Res<Time>, mut hp_query: Query<(&mut Hp, &mut HpRegen)>) {
+fn regen(time: Res<Time>, mut hp_query: Query<(&mut Hp, &mut HpRegen), Without<Dead>>) {
     for (mut hp, mut regen) in &mut hp_query {
         // give the player some mana back
         regen.tick_timer.tick(time.delta());
@@ -29,3 +101,162 @@ fn regen(time: Res<Time>, mut hp_query: Query<(&mut Hp, &mut HpRegen)>) {
         }
     }
 }
+
+// A client's replicas show Hp that the network server keeps, so only the server damages them.
+#[cfg(feature = "serde")]
+type Damageable = Without<crate::net::Replica>;
+#[cfg(not(feature = "serde"))]
+type Damageable = ();
+
+#[allow(clippy::type_complexity)]
+pub(crate) fn apply_damage(
+    mut commands: Commands,
+    mut damage: EventReader<DamageEvent>,
+    mut deaths: EventWriter<DeathEvent>,
+    mut statuses: EventWriter<ApplyStatus>,
+    mut targets: Query<
+        (&mut Hp, Option<&Resistances>, Option<&mut StatusEffects>),
+        (Without<Dead>, Without<Invulnerable>, Damageable),
+    >,
+) {
+    for event in damage.read() {
+        let Ok((mut hp, resistances, shield)) = targets.get_mut(event.target) else {
+            continue;
+        };
+        // Already killed by an earlier event this frame.
+        if hp.current == 0 {
+            continue;
+        }
+        let amount = match resistances {
+            Some(resistances) => resistances.mitigate(event.damage_type, event.amount),
+            None => event.amount,
+        };
+        let amount = match shield {
+            Some(mut shield) => shield.absorb(amount),
+            None => amount,
+        };
+        if let Some(effect) = &event.status {
+            statuses.send(ApplyStatus {
+                target: event.target,
+                source: event.source,
+                effect: effect.clone(),
+            });
+        }
+        hp.current = hp.current.saturating_sub(amount.round().max(0.0) as u32);
+        if hp.current == 0 {
+            commands.entity(event.target).insert(Dead {
+                killer: event.source,
+                damage_type: event.damage_type,
+            });
+            deaths.send(DeathEvent {
+                entity: event.target,
+                killer: event.source,
+            });
+        }
+    }
+}
+
+fn wear_off_invulnerability(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut invulnerable: Query<(Entity, &mut Invulnerable)>,
+) {
+    for (entity, mut invulnerable) in &mut invulnerable {
+        if invulnerable.0.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<Invulnerable>();
+        }
+    }
+}
+
+fn despawn_dead(mut commands: Commands, dead: Query<Entity, Added<Dead>>) {
+    for entity in &dead {
+        info!("{entity} died.");
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    fn world_with_target(hp: u32, resistances: Option<Resistances>) -> (World, Entity) {
+        let mut world = World::new();
+        world.init_resource::<Events<DamageEvent>>();
+        world.init_resource::<Events<DeathEvent>>();
+        world.init_resource::<Events<ApplyStatus>>();
+        let mut target = world.spawn(Hp {
+            current: hp,
+            max: hp,
+        });
+        if let Some(resistances) = resistances {
+            target.insert(resistances);
+        }
+        let target = target.id();
+        (world, target)
+    }
+
+    fn hit(world: &mut World, target: Entity, amount: f32, damage_type: DamageType) {
+        world.send_event(DamageEvent {
+            source: None,
+            target,
+            amount,
+            damage_type,
+            hit_point: Vec3::ZERO,
+            status: None,
+        });
+        world.run_system_once(apply_damage);
+        // Every run_system_once starts a fresh reader, which would read this hit again next time.
+        world.resource_mut::<Events<DamageEvent>>().clear();
+    }
+
+    #[test]
+    fn resistances_reduce_damage_of_their_type() {
+        let resistances = Resistances([(DamageType::Fire, 0.75)].into_iter().collect());
+        let (mut world, target) = world_with_target(100, Some(resistances));
+        hit(&mut world, target, 40., DamageType::Fire);
+        assert_eq!(world.get::<Hp>(target).unwrap().current, 90);
+        hit(&mut world, target, 40., DamageType::Physical);
+        assert_eq!(world.get::<Hp>(target).unwrap().current, 50);
+    }
+
+    #[test]
+    fn lethal_damage_marks_the_target_dead_once() {
+        let (mut world, target) = world_with_target(10, None);
+        hit(&mut world, target, 25., DamageType::Physical);
+        hit(&mut world, target, 25., DamageType::Physical);
+        assert_eq!(world.get::<Hp>(target).unwrap().current, 0);
+        assert!(world.get::<Dead>(target).is_some());
+        let deaths = world.resource::<Events<DeathEvent>>();
+        assert_eq!(deaths.len(), 1);
+    }
+
+    #[test]
+    fn invulnerable_targets_take_no_damage() {
+        let (mut world, target) = world_with_target(10, None);
+        world
+            .entity_mut(target)
+            .insert(Invulnerable(Timer::from_seconds(1.0, TimerMode::Once)));
+        hit(&mut world, target, 25., DamageType::Fire);
+        assert_eq!(world.get::<Hp>(target).unwrap().current, 10);
+        world.entity_mut(target).remove::<Invulnerable>();
+        hit(&mut world, target, 25., DamageType::Fire);
+        let dead = world.get::<Dead>(target).unwrap();
+        assert_eq!(dead.damage_type, DamageType::Fire);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn replicas_are_left_to_the_server() {
+        use crate::net::{Replica, ReplicaKind};
+
+        let (mut world, target) = world_with_target(10, None);
+        world.entity_mut(target).insert(Replica {
+            kind: ReplicaKind::Target,
+            velocity: Vec3::ZERO,
+        });
+        hit(&mut world, target, 25., DamageType::Physical);
+        assert_eq!(world.get::<Hp>(target).unwrap().current, 10);
+        assert!(world.get::<Dead>(target).is_none());
+    }
+}
@@ -0,0 +1,44 @@
+use crate::mana::Mana;
+use bevy::prelude::*;
+use std::time::Duration;
+
+/// Cooldown and mana cost, shared by every active ability.
+#[derive(Debug, Clone)]
+pub struct AbilityCost {
+    pub mana_cost: u32,
+    pub cooldown_timer: Timer, // should be a repeating timer.
+}
+
+impl AbilityCost {
+    /// A cost whose cooldown starts out finished, so the ability is ready as soon as it's granted.
+    pub fn new(mana_cost: u32, cooldown: Duration) -> Self {
+        let mut cooldown_timer = Timer::new(cooldown, TimerMode::Repeating);
+        cooldown_timer.tick(cooldown);
+        AbilityCost {
+            mana_cost,
+            cooldown_timer,
+        }
+    }
+
+    pub fn tick(&mut self, delta: Duration) {
+        // Repeating timers are only ready in the first tick after they finish, then they become unready again.
+        // So, only tick if still in cooldown.
+        if !self.cooldown_timer.finished() {
+            self.cooldown_timer.tick(delta);
+        }
+    }
+
+    pub fn ready(&self, mana: &Mana) -> bool {
+        self.cooldown_timer.finished() && mana.current >= self.mana_cost
+    }
+
+    /// Spends the mana and puts the ability back on cooldown, if it's ready.
+    pub fn try_activate(&mut self, mana: &mut Mana) -> bool {
+        if !self.ready(mana) {
+            return false;
+        }
+        self.cooldown_timer.reset();
+        mana.current -= self.mana_cost;
+        true
+    }
+}
@@ -1,7 +1,5 @@
-use std::time::Duration;
-
 use crate::prelude::*;
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashSet};
 use bevy_firework::{
     core::{BlendMode, ParticleSpawnerBundle, ParticleSpawnerSettings},
     emission_shape::EmissionShape,
@@ -10,96 +8,254 @@ use bevy_rapier3d::prelude::*;
 use bevy_utilitarian::prelude::*;
 use std::f32::consts::PI;
 pub struct FireballPlugin;
-use crate::{camera::FirstPersonCam, mana::Mana};
+use crate::{
+    hitpoints::{DamageEvent, DamageSet, DamageType, Hp},
+    status::StatusEffect,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 impl Plugin for FireballPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, (throw_fireball, clean_fireball));
+        app.add_event::<ExplosionEvent>();
+        app.add_systems(
+            Update,
+            (
+                (detonate_on_contact, clean_fireball, explode)
+                    .chain()
+                    .before(DamageSet),
+                clean_explosion_effects,
+            ),
+        );
     }
 }
 
 #[derive(Component)]
 pub struct Fireball {
     lifetime: Timer,
+    /// Whoever threw the fireball. It doesn't detonate on touching them on the way out.
+    caster: Entity,
+    explosion: Explosion,
 }
 
-#[derive(Component, Debug, Clone)]
-pub struct FireballAbility {
-    pub projectile_lifetime: Timer,
-    pub projectile_speed: f32,
-    pub projectile_radius: f32,
-    pub mana_cost: u32,
-    pub cooldown_timer: Timer, // should be a repeating timer.
-    pub damping: f32,
-    pub gravity: f32,
+/// The blast of a detonating fireball. Damage and knockback fall off linearly to nothing at `radius`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Explosion {
+    pub radius: f32,
+    pub damage: f32,
+    /// Velocity given to dynamic bodies at the center of the blast, pointing away from it.
+    pub knockback: f32,
+    /// Applied in full to everything that the blast hurts, e.g. a burn.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub status: Option<StatusEffect>,
 }
 
-impl Default for FireballAbility {
-    fn default() -> Self {
-        FireballAbility {
-            projectile_lifetime: Timer::new(Duration::from_millis(5000), TimerMode::Once),
-            projectile_speed: 20.,
-            projectile_radius: 1.,
-            mana_cost: 5,
-            cooldown_timer: Timer::new(Duration::from_millis(3000), TimerMode::Repeating),
-            damping: 2.,
-            gravity: 1.,
-        }
+impl Explosion {
+    /// How much of the blast reaches something `distance` away from its center.
+    pub fn falloff(&self, distance: f32) -> f32 {
+        (1.0 - distance / self.radius).clamp(0.0, 1.0)
     }
 }
 
+#[derive(Event, Debug, Clone)]
+pub struct ExplosionEvent {
+    pub position: Vec3,
+    pub explosion: Explosion,
+    pub source: Option<Entity>,
+}
+
+// Lets the explosion particle burst fade out before it's despawned.
+#[derive(Component)]
+struct ExplosionEffect {
+    lifetime: Timer,
+}
+
+/// How a fireball flies. Cast by the projectile spells in `spells`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Projectile {
+    pub speed: f32,
+    pub radius: f32,
+    /// Seconds before the fireball explodes on its own.
+    pub lifetime: f32,
+    pub damping: f32,
+    pub gravity: f32,
+    pub explosion: Explosion,
+}
+
 pub fn clean_fireball(
     mut commands: Commands,
-    mut fireballs: Query<(Entity, &mut Fireball)>,
+    mut fireballs: Query<(Entity, &Transform, &mut Fireball)>,
+    mut explosions: EventWriter<ExplosionEvent>,
     time: Res<Time>,
 ) {
-    for (entity, mut fireball) in &mut fireballs {
+    for (entity, transform, mut fireball) in &mut fireballs {
         fireball.lifetime.tick(time.delta());
         if fireball.lifetime.finished() {
+            // Fireballs that run out of time explode at the end of their journey.
+            explosions.send(ExplosionEvent {
+                position: transform.translation,
+                explosion: fireball.explosion.clone(),
+                source: Some(fireball.caster),
+            });
             commands.entity(entity).despawn_recursive();
         }
     }
 }
 
-pub fn throw_fireball(
+pub fn detonate_on_contact(
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mouse: Res<ButtonInput<MouseButton>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut thrower_query: Query<(&mut FireballAbility, &mut Mana), With<FireballAbility>>,
-    camera_query: Query<(&GlobalTransform), With<FirstPersonCam>>,
-    time: Res<Time>,
+    mut collisions: EventReader<CollisionEvent>,
+    fireballs: Query<(&Transform, &Fireball)>,
+    parents: Query<&Parent>,
+    mut explosions: EventWriter<ExplosionEvent>,
 ) {
-    let (mut ability, mut mana) = match thrower_query.get_single_mut() {
-        Ok(p) => p,
-        Err(e) => return,
-    };
-    let transform: GlobalTransform = match camera_query.get_single() {
-        Ok(v) => v.clone(),
-        Err(e) => {
-            return warn!("Couldn't get FirstPersonCam, don't know how to aim the fireball: {e}")
+    // A fireball can touch several things in one frame; it only explodes once.
+    let mut detonated = HashSet::new();
+    for event in collisions.read() {
+        let CollisionEvent::Started(a, b, _) = event else {
+            continue;
+        };
+        let (entity, other) = if fireballs.contains(*a) {
+            (*a, *b)
+        } else if fireballs.contains(*b) {
+            (*b, *a)
+        } else {
+            continue;
+        };
+        let Ok((transform, fireball)) = fireballs.get(entity) else {
+            continue;
+        };
+        let touched_caster = std::iter::once(other)
+            .chain(parents.iter_ancestors(other))
+            .any(|e| e == fireball.caster);
+        if touched_caster || !detonated.insert(entity) {
+            continue;
         }
-    };
-
-    // Repeating timers are only ready in the first tick after they finish, then they become unready again.
-    // So, only tick if still in cooldown.
-    if !ability.cooldown_timer.finished() {
-        ability.cooldown_timer.tick(time.delta());
+        explosions.send(ExplosionEvent {
+            position: transform.translation,
+            explosion: fireball.explosion.clone(),
+            source: Some(fireball.caster),
+        });
+        commands.entity(entity).despawn_recursive();
     }
+}
 
-    if !ability.cooldown_timer.finished() {
-        return;
-    }
-    if !mouse.pressed(MouseButton::Right) {
-        return;
+pub fn explode(
+    mut commands: Commands,
+    mut explosions: EventReader<ExplosionEvent>,
+    rapier_context: Res<RapierContext>,
+    transforms: Query<&GlobalTransform>,
+    bodies: Query<&RigidBody, Without<Fireball>>,
+    damageable: Query<(), With<Hp>>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    for event in explosions.read() {
+        let ExplosionEvent {
+            position,
+            explosion,
+            source,
+        } = event;
+        commands.spawn((
+            ParticleSpawnerBundle::from_settings(ParticleSpawnerSettings {
+                one_shot: true,
+                rate: 2000.0,
+                emission_shape: EmissionShape::Sphere(explosion.radius * 0.25),
+                lifetime: RandF32 { min: 0.4, max: 0.9 },
+                initial_velocity: RandVec3 {
+                    magnitude: RandF32 {
+                        min: explosion.radius,
+                        max: explosion.radius * 3.,
+                    },
+                    direction: Vec3::Y,
+                    spread: PI,
+                },
+                initial_scale: RandF32 {
+                    min: 0.05,
+                    max: 0.2,
+                },
+                scale_curve: ParamCurve::constant(1.),
+                color: Gradient::linear(vec![
+                    (0., LinearRgba::new(1.0, 0.8, 0.3, 1.)),
+                    (0.3, LinearRgba::new(0.9, 0.3, 0.1, 1.)),
+                    (1., LinearRgba::new(0.1, 0.1, 0.1, 0.)),
+                ]),
+                blend_mode: BlendMode::Blend,
+                linear_drag: 2.0,
+                pbr: false,
+                ..default()
+            }),
+            SpatialBundle::from_transform(Transform::from_translation(*position)),
+            ExplosionEffect {
+                lifetime: Timer::from_seconds(1.0, TimerMode::Once),
+            },
+            Name::new("explosion"),
+        ));
+
+        rapier_context.intersections_with_shape(
+            *position,
+            Quat::IDENTITY,
+            &Collider::ball(explosion.radius),
+            QueryFilter::default(),
+            |entity| {
+                let Ok(transform) = transforms.get(entity) else {
+                    return true;
+                };
+                let offset = transform.translation() - *position;
+                let falloff = explosion.falloff(offset.length());
+
+                // Casters don't get hurt by their own blasts.
+                if damageable.contains(entity) && Some(entity) != *source {
+                    damage.send(DamageEvent {
+                        source: *source,
+                        target: entity,
+                        amount: explosion.damage * falloff,
+                        damage_type: DamageType::Fire,
+                        hit_point: transform.translation(),
+                        status: explosion.status.clone(),
+                    });
+                }
+
+                if matches!(bodies.get(entity), Ok(RigidBody::Dynamic)) {
+                    let mass = rapier_context
+                        .entity2body()
+                        .get(&entity)
+                        .and_then(|handle| rapier_context.bodies.get(*handle))
+                        .map(|body| body.mass())
+                        .unwrap_or_default();
+                    let direction = offset.try_normalize().unwrap_or(Vec3::Y);
+                    commands.entity(entity).try_insert(ExternalImpulse {
+                        impulse: direction * explosion.knockback * falloff * mass,
+                        ..default()
+                    });
+                }
+                true
+            },
+        );
     }
-    if mana.current < ability.mana_cost {
-        return;
+}
+
+fn clean_explosion_effects(
+    mut commands: Commands,
+    mut effects: Query<(Entity, &mut ExplosionEffect)>,
+    time: Res<Time>,
+) {
+    for (entity, mut effect) in &mut effects {
+        if effect.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
     }
-    // Put the fireball back on cooldown.
-    ability.cooldown_timer.reset();
-    mana.current -= ability.mana_cost;
+}
 
-    let local_transform: Transform = transform.into();
+/// Throws a fireball from `transform`, in its forward direction.
+pub fn spawn_fireball(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    caster: Entity,
+    transform: Transform,
+    projectile: &Projectile,
+) {
     let forward = transform.forward();
     commands
         .spawn(
@@ -132,13 +288,8 @@ pub fn throw_fireball(
         )
         .insert((
             PbrBundle {
-                mesh: meshes.add(
-                    Sphere::new(ability.projectile_radius)
-                        .mesh()
-                        .ico(6)
-                        .unwrap(),
-                ),
-                transform: local_transform,
+                mesh: meshes.add(Sphere::new(projectile.radius).mesh().ico(6).unwrap()),
+                transform,
                 material: materials.add(StandardMaterial {
                     diffuse_transmission: 0.0,
                     specular_transmission: 1.0,
@@ -151,24 +302,27 @@ pub fn throw_fireball(
                 ..default()
             },
             Fireball {
-                lifetime: ability.projectile_lifetime.clone(),
+                lifetime: Timer::from_seconds(projectile.lifetime, TimerMode::Once),
+                caster,
+                explosion: projectile.explosion.clone(),
             },
-            Leash(1000.),
+            OutOfBounds::Despawn,
         ))
         .insert((
             RigidBody::Dynamic,
-            Collider::ball(ability.projectile_radius),
-            GravityScale(ability.gravity),
+            Collider::ball(projectile.radius),
+            GravityScale(projectile.gravity),
             // prevents "tunneling"
             Ccd::enabled(),
+            ActiveEvents::COLLISION_EVENTS,
             ColliderMassProperties::Density(5.),
             Damping {
-                linear_damping: ability.damping,
+                linear_damping: projectile.damping,
                 ..default()
             },
         ))
         .insert(Velocity {
-            linvel: forward * ability.projectile_speed,
+            linvel: forward * projectile.speed,
             ..default()
         })
         .with_children(|builder| {
@@ -0,0 +1,321 @@
+use crate::{
+    camera::FirstPersonCam,
+    hitpoints::{DamageEvent, DamageSet, DamageType},
+    prelude::*,
+};
+use bevy::prelude::*;
+use bevy_firework::{
+    core::{BlendMode, ParticleSpawnerBundle, ParticleSpawnerSettings},
+    emission_shape::EmissionShape,
+};
+use bevy_rapier3d::prelude::*;
+use bevy_utilitarian::prelude::*;
+use rand::Rng;
+use std::{f32::consts::PI, time::Duration};
+
+pub struct HitscanPlugin;
+
+impl Plugin for HitscanPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<FireHitscan>();
+        app.add_event::<HitscanShot>();
+        app.add_systems(Startup, load_hitscan_assets);
+        app.add_systems(
+            Update,
+            (
+                (
+                    cool_hitscan_weapons,
+                    player_fire_input,
+                    fire_hitscan.before(DamageSet),
+                )
+                    .chain(),
+                update_hitscan_effects,
+            ),
+        );
+    }
+}
+
+const TRACER_RADIUS: f32 = 0.015;
+const TRACER_LIFETIME: f32 = 0.08;
+const IMPACT_LIFETIME: f32 = 0.5;
+// Tracers start a little below and to the right of the eye, where the weapon would be.
+const MUZZLE_OFFSET: Vec3 = Vec3::new(0.2, -0.15, 0.0);
+
+/// A gun that fires instantly along its holder's aim. The player fires theirs while the left mouse button
+/// is held.
+#[derive(Component, Debug, Clone)]
+pub struct HitscanWeapon {
+    pub shots_per_second: f32,
+    /// Largest angle, in radians, that a shot strays from the aim in each direction.
+    pub spread: f32,
+    pub range: f32,
+    pub damage: f32,
+    pub damage_type: DamageType,
+    /// Velocity given to dynamic bodies that are hit.
+    pub knockback: f32,
+    cooldown: Timer,
+}
+
+impl HitscanWeapon {
+    pub fn new(shots_per_second: f32, spread: f32, range: f32, damage: f32) -> Self {
+        // Starts out finished, so the weapon is ready as soon as it's picked up.
+        let period = period(shots_per_second);
+        let mut cooldown = Timer::new(period, TimerMode::Once);
+        cooldown.tick(period);
+        HitscanWeapon {
+            shots_per_second,
+            spread,
+            range,
+            damage,
+            damage_type: DamageType::Physical,
+            knockback: 5.,
+            cooldown,
+        }
+    }
+
+    /// Whether the weapon has cooled down since its last shot.
+    pub fn ready(&self) -> bool {
+        self.cooldown.finished()
+    }
+
+    /// Starts cooling down from a shot, at the weapon's current rate of fire.
+    pub fn restart(&mut self) {
+        self.cooldown.set_duration(period(self.shots_per_second));
+        self.cooldown.reset();
+    }
+}
+
+/// The time between shots. A weapon set to fire no shots, or fewer than none, waits so long between them
+/// that it never fires twice.
+fn period(shots_per_second: f32) -> Duration {
+    Duration::from_secs_f32(1.0 / shots_per_second.max(f32::EPSILON))
+}
+
+impl Default for HitscanWeapon {
+    fn default() -> Self {
+        HitscanWeapon::new(8., 0.01, 500., 10.)
+    }
+}
+
+/// Asks for `shooter` to fire its `HitscanWeapon` from `origin` along `direction`. The shot only goes off if
+/// the weapon is ready, and strays from `direction` by up to the weapon's spread.
+#[derive(Event, Debug, Clone)]
+pub struct FireHitscan {
+    pub shooter: Entity,
+    pub origin: Vec3,
+    pub direction: Dir3,
+}
+
+/// Sent for every shot fired, whether or not it hit anything.
+#[derive(Event, Debug, Clone)]
+pub struct HitscanShot {
+    pub shooter: Entity,
+    pub target: Option<Entity>,
+}
+
+#[derive(Resource)]
+pub(crate) struct HitscanAssets {
+    tracer_mesh: Handle<Mesh>,
+    tracer_material: Handle<StandardMaterial>,
+}
+
+// Tracers and impact bursts, which last a moment and are then despawned. Tracers thin out as they go.
+#[derive(Component)]
+struct HitscanEffect {
+    lifetime: Timer,
+    thin_out: bool,
+}
+
+fn load_hitscan_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    commands.insert_resource(HitscanAssets {
+        tracer_mesh: meshes.add(Cylinder::new(TRACER_RADIUS, 1.0)),
+        tracer_material: materials.add(StandardMaterial {
+            base_color: Palette::Yellow.to_color(),
+            emissive: LinearRgba::rgb(4.0, 3.0, 1.0),
+            unlit: true,
+            ..default()
+        }),
+    });
+}
+
+fn cool_hitscan_weapons(time: Res<Time>, mut weapons: Query<&mut HitscanWeapon>) {
+    for mut weapon in &mut weapons {
+        // Only tick while cooling down, so that the first shot after a pause goes off straight away.
+        if !weapon.ready() {
+            weapon.cooldown.tick(time.delta());
+        }
+    }
+}
+
+pub(crate) fn player_fire_input(
+    state: Res<State<GameState>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    player: Query<(Entity, &HitscanWeapon), With<Player>>,
+    camera: Query<&GlobalTransform, With<FirstPersonCam>>,
+    mut fire: EventWriter<FireHitscan>,
+) {
+    let Ok((shooter, weapon)) = player.get_single() else {
+        return;
+    };
+    if *state.get() != GameState::InGame || !mouse.pressed(MouseButton::Left) || !weapon.ready() {
+        return;
+    }
+    let Ok(aim) = camera.get_single() else {
+        return warn!("Couldn't get FirstPersonCam, don't know how to aim the weapon.");
+    };
+    fire.send(FireHitscan {
+        shooter,
+        origin: aim.translation(),
+        direction: aim.forward(),
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn fire_hitscan(
+    mut commands: Commands,
+    mut fire: EventReader<FireHitscan>,
+    mut weapons: Query<&mut HitscanWeapon>,
+    bodies: Query<&RigidBody>,
+    rapier_context: Res<RapierContext>,
+    assets: Res<HitscanAssets>,
+    mut damage: EventWriter<DamageEvent>,
+    mut shots: EventWriter<HitscanShot>,
+) {
+    for shot in fire.read() {
+        let shooter = shot.shooter;
+        let Ok(mut weapon) = weapons.get_mut(shooter) else {
+            continue;
+        };
+        if !weapon.ready() {
+            continue;
+        }
+        weapon.restart();
+        let aim = Transform::from_translation(shot.origin).looking_to(shot.direction, Vec3::Y);
+        let mut rng = rand::thread_rng();
+        let stray = Quat::from_euler(
+            EulerRot::YXZ,
+            rng.gen_range(-weapon.spread..=weapon.spread),
+            rng.gen_range(-weapon.spread..=weapon.spread),
+            0.0,
+        );
+        let direction = aim.rotation * stray * Vec3::NEG_Z;
+        let hit = rapier_context.cast_ray_and_get_normal(
+            aim.translation,
+            direction,
+            weapon.range,
+            true,
+            QueryFilter::default().exclude_collider(shooter),
+        );
+        shots.send(HitscanShot {
+            shooter,
+            target: hit.map(|(target, _)| target),
+        });
+
+        let end = match hit {
+            Some((target, intersection)) => {
+                damage.send(DamageEvent {
+                    source: Some(shooter),
+                    target,
+                    amount: weapon.damage,
+                    damage_type: weapon.damage_type,
+                    hit_point: intersection.point,
+                    status: None,
+                });
+                if matches!(bodies.get(target), Ok(RigidBody::Dynamic)) {
+                    let mass = rapier_context
+                        .entity2body()
+                        .get(&target)
+                        .and_then(|handle| rapier_context.bodies.get(*handle))
+                        .map(|body| body.mass())
+                        .unwrap_or_default();
+                    commands.entity(target).try_insert(ExternalImpulse {
+                        impulse: direction * weapon.knockback * mass,
+                        ..default()
+                    });
+                }
+                spawn_impact(&mut commands, intersection.point, intersection.normal);
+                intersection.point
+            }
+            None => aim.translation + direction * weapon.range,
+        };
+
+        let muzzle = aim.transform_point(MUZZLE_OFFSET);
+        let tracer = end - muzzle;
+        commands.spawn((
+            Name::new("tracer"),
+            PbrBundle {
+                mesh: assets.tracer_mesh.clone(),
+                material: assets.tracer_material.clone(),
+                transform: Transform {
+                    translation: muzzle + tracer / 2.0,
+                    rotation: Quat::from_rotation_arc(
+                        Vec3::Y,
+                        tracer.try_normalize().unwrap_or(Vec3::Y),
+                    ),
+                    scale: Vec3::new(1.0, tracer.length(), 1.0),
+                },
+                ..default()
+            },
+            HitscanEffect {
+                lifetime: Timer::from_seconds(TRACER_LIFETIME, TimerMode::Once),
+                thin_out: true,
+            },
+        ));
+    }
+}
+
+/// Sparks thrown off the surface that was hit, along its normal.
+fn spawn_impact(commands: &mut Commands, point: Vec3, normal: Vec3) {
+    commands.spawn((
+        Name::new("impact"),
+        ParticleSpawnerBundle::from_settings(ParticleSpawnerSettings {
+            one_shot: true,
+            rate: 300.0,
+            emission_shape: EmissionShape::Point,
+            lifetime: RandF32 { min: 0.1, max: 0.4 },
+            initial_velocity: RandVec3 {
+                magnitude: RandF32 { min: 2., max: 8. },
+                direction: normal,
+                spread: 45. / 180. * PI,
+            },
+            initial_scale: RandF32 {
+                min: 0.01,
+                max: 0.04,
+            },
+            scale_curve: ParamCurve::constant(1.),
+            color: Gradient::linear(vec![
+                (0., LinearRgba::new(1.0, 0.9, 0.5, 1.)),
+                (1., LinearRgba::new(0.4, 0.2, 0.1, 0.)),
+            ]),
+            blend_mode: BlendMode::Blend,
+            linear_drag: 1.0,
+            pbr: false,
+            ..default()
+        }),
+        SpatialBundle::from_transform(Transform::from_translation(point)),
+        HitscanEffect {
+            lifetime: Timer::from_seconds(IMPACT_LIFETIME, TimerMode::Once),
+            thin_out: false,
+        },
+    ));
+}
+
+fn update_hitscan_effects(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut effects: Query<(Entity, &mut Transform, &mut HitscanEffect)>,
+) {
+    for (entity, mut transform, mut effect) in &mut effects {
+        if effect.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        } else if effect.thin_out {
+            let left = effect.lifetime.fraction_remaining();
+            transform.scale.x = left;
+            transform.scale.z = left;
+        }
+    }
+}
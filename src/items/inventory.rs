@@ -0,0 +1,763 @@
+use crate::{
+    hitpoints::Hp,
+    items::Projectile,
+    mana::Mana,
+    missions::{Mission, MissionState},
+    player::character_sensor,
+    prelude::*,
+    status::{ApplyStatus, StatusEffect},
+};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier3d::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Items, as loaded from `.item.ron` files in assets/items: pickups lying about the world, the inventory that
+/// they go into, potions to drink and equipment that changes how the caster's projectiles fly. I opens the
+/// inventory panel, where the arrow keys pick an item and Enter uses or equips it.
+pub struct InventoryPlugin;
+
+impl Plugin for InventoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<ItemDefinition>();
+        #[cfg(feature = "serde")]
+        app.init_asset_loader::<loader::ItemLoader>();
+        app.add_event::<UseItem>();
+        app.init_resource::<InventoryPanel>();
+        app.add_systems(OnEnter(GameState::InGame), spawn_inventory_panel);
+        app.add_systems(
+            Update,
+            (
+                load_pickups,
+                attract_pickups,
+                collect_pickups,
+                // Only while the level's mission is under way, if it has one, as Enter also replays it from its
+                // summary.
+                inventory_input.run_if(in_state(GameState::InGame).and_then(
+                    in_state(MissionState::Active).or_else(not(resource_exists::<Mission>)),
+                )),
+                use_items,
+                update_projectile_modifiers,
+                update_inventory_panel,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// How far away pickups start drifting towards whoever has room for them.
+pub const MAGNET_RADIUS: f32 = 6.0;
+const MAGNET_SPEED: f32 = 12.0;
+const PICKUP_SIZE: f32 = 0.4;
+const PICKUP_SPIN: f32 = 2.0;
+const INVENTORY_SLOTS: usize = 12;
+
+/// An item, as loaded from a `.item.ron` file.
+#[derive(Asset, TypePath, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ItemDefinition {
+    pub name: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub description: String,
+    /// How many fit in one inventory slot.
+    #[cfg_attr(feature = "serde", serde(default = "one"))]
+    pub max_stack: u32,
+    /// The pickup's colour.
+    pub color: [f32; 3],
+    pub kind: ItemKind,
+}
+
+#[cfg(feature = "serde")]
+fn one() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum ItemKind {
+    /// Used up on use, restoring `Hp` and `Mana` and applying any `effects` to the user.
+    Consumable {
+        heal: u32,
+        mana: u32,
+        #[cfg_attr(feature = "serde", serde(default))]
+        effects: Vec<StatusEffect>,
+    },
+    /// Worn in `slot`, replacing whatever was there before.
+    Equipment {
+        slot: EquipmentSlot,
+        modifiers: ProjectileModifiers,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum EquipmentSlot {
+    Staff,
+    Ring,
+    Amulet,
+}
+
+/// Multiplies the parameters of the projectile spells that an entity casts. Equipment carries these, and
+/// `update_projectile_modifiers` keeps a combined one on whoever is wearing it, along with any
+/// `InnateProjectileModifiers`.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct ProjectileModifiers {
+    pub speed: f32,
+    pub radius: f32,
+    pub damage: f32,
+    pub blast_radius: f32,
+    pub knockback: f32,
+}
+
+impl Default for ProjectileModifiers {
+    fn default() -> Self {
+        ProjectileModifiers {
+            speed: 1.0,
+            radius: 1.0,
+            damage: 1.0,
+            blast_radius: 1.0,
+            knockback: 1.0,
+        }
+    }
+}
+
+impl ProjectileModifiers {
+    pub fn combine(&self, other: &ProjectileModifiers) -> ProjectileModifiers {
+        ProjectileModifiers {
+            speed: self.speed * other.speed,
+            radius: self.radius * other.radius,
+            damage: self.damage * other.damage,
+            blast_radius: self.blast_radius * other.blast_radius,
+            knockback: self.knockback * other.knockback,
+        }
+    }
+
+    pub fn apply(&self, projectile: &Projectile) -> Projectile {
+        let mut projectile = projectile.clone();
+        projectile.speed *= self.speed;
+        projectile.radius *= self.radius;
+        projectile.explosion.damage *= self.damage;
+        projectile.explosion.radius *= self.blast_radius;
+        projectile.explosion.knockback *= self.knockback;
+        projectile
+    }
+}
+
+/// Projectile modifiers that an entity has without wearing anything, e.g. from talents.
+#[derive(Component, Debug, Clone, Copy, PartialEq, Default)]
+pub struct InnateProjectileModifiers(pub ProjectileModifiers);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ItemStack {
+    pub item: Handle<ItemDefinition>,
+    pub count: u32,
+}
+
+/// What an entity is carrying, in slots of stacked items.
+#[derive(Component, Debug, Clone)]
+pub struct Inventory {
+    pub stacks: Vec<ItemStack>,
+    pub capacity: usize,
+}
+
+impl Default for Inventory {
+    fn default() -> Self {
+        Inventory {
+            stacks: vec![],
+            capacity: INVENTORY_SLOTS,
+        }
+    }
+}
+
+impl Inventory {
+    /// Adds `count` of `item`, topping up existing stacks before starting new ones. Returns how many didn't fit.
+    pub fn add(&mut self, item: &Handle<ItemDefinition>, count: u32, max_stack: u32) -> u32 {
+        let max_stack = max_stack.max(1);
+        let mut left = count;
+        for stack in self.stacks.iter_mut().filter(|stack| stack.item == *item) {
+            let added = left.min(max_stack.saturating_sub(stack.count));
+            stack.count += added;
+            left -= added;
+        }
+        while left > 0 && self.stacks.len() < self.capacity {
+            let added = left.min(max_stack);
+            self.stacks.push(ItemStack {
+                item: item.clone(),
+                count: added,
+            });
+            left -= added;
+        }
+        left
+    }
+
+    /// Whether any of `item` would fit.
+    pub fn has_room_for(&self, item: &Handle<ItemDefinition>, max_stack: u32) -> bool {
+        self.stacks.len() < self.capacity
+            || self
+                .stacks
+                .iter()
+                .any(|stack| stack.item == *item && stack.count < max_stack)
+    }
+
+    /// Takes one item out of the stack in `slot`, removing the stack once it's empty.
+    pub fn take_one(&mut self, slot: usize) -> Option<Handle<ItemDefinition>> {
+        let stack = self.stacks.get_mut(slot)?;
+        let item = stack.item.clone();
+        stack.count -= 1;
+        if stack.count == 0 {
+            self.stacks.remove(slot);
+        }
+        Some(item)
+    }
+}
+
+/// What an entity is wearing, one item per slot.
+#[derive(Component, Debug, Clone, Default)]
+pub struct Equipment(pub HashMap<EquipmentSlot, Handle<ItemDefinition>>);
+
+/// An item lying in the world, waiting to be walked over. It stays invisible until its definition has loaded;
+/// then `load_pickups` gives it a cube in the item's colour and the sensor that picks it up.
+#[derive(Component, Debug, Clone)]
+pub struct Pickup {
+    pub item: Handle<ItemDefinition>,
+    pub count: u32,
+}
+
+/// Asks for `user` to use or equip whatever is in `slot` of its `Inventory`.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct UseItem {
+    pub user: Entity,
+    pub slot: usize,
+}
+
+#[derive(Resource, Debug, Default)]
+struct InventoryPanel {
+    open: bool,
+    selected: usize,
+    message: String,
+}
+
+#[derive(Component)]
+struct InventoryPanelText;
+
+fn load_pickups(
+    mut commands: Commands,
+    items: Res<Assets<ItemDefinition>>,
+    pickups: Query<(Entity, &Pickup, Option<&Transform>), Without<Collider>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (entity, pickup, transform) in &pickups {
+        let Some(item) = items.get(&pickup.item) else {
+            continue;
+        };
+        let [r, g, b] = item.color;
+        let color = Color::srgb(r, g, b);
+        commands.entity(entity).insert((
+            Name::new(format!("pickup: {}", item.name)),
+            PbrBundle {
+                mesh: meshes.add(Cuboid::from_length(PICKUP_SIZE)),
+                material: materials.add(StandardMaterial {
+                    base_color: color,
+                    emissive: LinearRgba::from(color) * 2.0,
+                    ..default()
+                }),
+                transform: transform.copied().unwrap_or_default(),
+                ..default()
+            },
+            Collider::ball(PICKUP_SIZE),
+            character_sensor(),
+            OutOfBounds::Despawn,
+        ));
+    }
+}
+
+/// Spins pickups, and pulls them towards anyone nearby with room for them.
+fn attract_pickups(
+    time: Res<Time>,
+    items: Res<Assets<ItemDefinition>>,
+    collectors: Query<(&GlobalTransform, &Inventory)>,
+    mut pickups: Query<(&Pickup, &mut Transform), With<Collider>>,
+) {
+    let delta = time.delta_seconds();
+    for (pickup, mut transform) in &mut pickups {
+        transform.rotate_y(PICKUP_SPIN * delta);
+        let Some(item) = items.get(&pickup.item) else {
+            continue;
+        };
+        let nearest = collectors
+            .iter()
+            .filter(|(_, inventory)| inventory.has_room_for(&pickup.item, item.max_stack))
+            .map(|(collector, _)| collector.translation())
+            .min_by(|a, b| {
+                a.distance_squared(transform.translation)
+                    .total_cmp(&b.distance_squared(transform.translation))
+            });
+        if let Some(target) =
+            nearest.filter(|target| target.distance(transform.translation) < MAGNET_RADIUS)
+        {
+            let step = (target - transform.translation).clamp_length_max(MAGNET_SPEED * delta);
+            transform.translation += step;
+        }
+    }
+}
+
+fn collect_pickups(
+    mut commands: Commands,
+    mut collisions: EventReader<CollisionEvent>,
+    items: Res<Assets<ItemDefinition>>,
+    mut pickups: Query<&mut Pickup>,
+    mut collectors: Query<&mut Inventory>,
+) {
+    for event in collisions.read() {
+        let CollisionEvent::Started(a, b, _) = event else {
+            continue;
+        };
+        let (pickup_entity, collector) = match (pickups.contains(*a), pickups.contains(*b)) {
+            (true, _) => (*a, *b),
+            (_, true) => (*b, *a),
+            _ => continue,
+        };
+        let (Ok(mut pickup), Ok(mut inventory)) = (
+            pickups.get_mut(pickup_entity),
+            collectors.get_mut(collector),
+        ) else {
+            continue;
+        };
+        let Some(item) = items.get(&pickup.item) else {
+            continue;
+        };
+        let left = inventory.add(&pickup.item, pickup.count, item.max_stack);
+        if left < pickup.count {
+            info!("Picked up {} {}", pickup.count - left, item.name);
+        }
+        pickup.count = left;
+        if left == 0 {
+            commands.entity(pickup_entity).despawn_recursive();
+        }
+    }
+}
+
+fn inventory_input(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut panel: ResMut<InventoryPanel>,
+    player: Query<(Entity, &Inventory), With<Player>>,
+    mut uses: EventWriter<UseItem>,
+) {
+    if keyboard.just_pressed(KeyCode::KeyI) {
+        panel.open = !panel.open;
+        panel.message.clear();
+    }
+    let Ok((user, inventory)) = player.get_single() else {
+        return;
+    };
+    if !panel.open || inventory.stacks.is_empty() {
+        return;
+    }
+    let last = inventory.stacks.len() - 1;
+    if keyboard.just_pressed(KeyCode::ArrowDown) {
+        panel.selected = (panel.selected + 1).min(last);
+    }
+    if keyboard.just_pressed(KeyCode::ArrowUp) {
+        panel.selected = panel.selected.saturating_sub(1);
+    }
+    panel.selected = panel.selected.min(last);
+    if keyboard.just_pressed(KeyCode::Enter) {
+        uses.send(UseItem {
+            user,
+            slot: panel.selected,
+        });
+    }
+}
+
+fn use_items(
+    mut events: EventReader<UseItem>,
+    items: Res<Assets<ItemDefinition>>,
+    mut users: Query<(
+        &mut Inventory,
+        Option<&mut Equipment>,
+        Option<&mut Hp>,
+        Option<&mut Mana>,
+    )>,
+    mut panel: ResMut<InventoryPanel>,
+    mut statuses: EventWriter<ApplyStatus>,
+) {
+    for event in events.read() {
+        let Ok((mut inventory, equipment, hp, mana)) = users.get_mut(event.user) else {
+            continue;
+        };
+        let Some(item) = inventory
+            .stacks
+            .get(event.slot)
+            .and_then(|stack| items.get(&stack.item))
+        else {
+            continue;
+        };
+        match &item.kind {
+            ItemKind::Consumable {
+                heal,
+                mana: restore,
+                effects,
+            } => {
+                if let Some(mut hp) = hp {
+                    hp.current = (hp.current + heal).min(hp.max);
+                }
+                if let Some(mut mana) = mana {
+                    mana.current = (mana.current + restore).min(mana.max);
+                }
+                for effect in effects {
+                    statuses.send(ApplyStatus {
+                        target: event.user,
+                        source: Some(event.user),
+                        effect: effect.clone(),
+                    });
+                }
+                panel.message = format!("Used {}", item.name);
+                inventory.take_one(event.slot);
+            }
+            ItemKind::Equipment { slot, .. } => {
+                let Some(mut equipment) = equipment else {
+                    continue;
+                };
+                panel.message = format!("Equipped {}", item.name);
+                let Some(handle) = inventory.take_one(event.slot) else {
+                    continue;
+                };
+                if let Some(previous) = equipment.0.insert(*slot, handle) {
+                    let max_stack = items.get(&previous).map_or(1, |item| item.max_stack);
+                    inventory.add(&previous, 1, max_stack);
+                }
+            }
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn update_projectile_modifiers(
+    mut commands: Commands,
+    items: Res<Assets<ItemDefinition>>,
+    wearers: Query<
+        (
+            Entity,
+            Option<&Equipment>,
+            Option<&InnateProjectileModifiers>,
+            Option<&ProjectileModifiers>,
+        ),
+        Or<(With<Equipment>, With<InnateProjectileModifiers>)>,
+    >,
+) {
+    for (entity, equipment, innate, current) in &wearers {
+        let innate = innate.map(|innate| innate.0).unwrap_or_default();
+        let combined = equipment
+            .into_iter()
+            .flat_map(|equipment| equipment.0.values())
+            .filter_map(|handle| items.get(handle))
+            .filter_map(|item| match &item.kind {
+                ItemKind::Equipment { modifiers, .. } => Some(modifiers),
+                ItemKind::Consumable { .. } => None,
+            })
+            .fold(innate, |combined, modifiers| combined.combine(modifiers));
+        if current != Some(&combined) {
+            commands.entity(entity).insert(combined);
+        }
+    }
+}
+
+fn spawn_inventory_panel(mut commands: Commands) {
+    let mut background = Palette::HudBackground.to_color();
+    background.set_alpha(0.1);
+    commands
+        .spawn((
+            Name::new("inventory_panel"),
+            StateScoped(GameState::InGame),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    top: Val::Px(120.0),
+                    left: Val::Px(20.0),
+                    padding: UiRect::all(Val::Px(10.0)),
+                    ..default()
+                },
+                background_color: BackgroundColor(background),
+                visibility: Visibility::Hidden,
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                InventoryPanelText,
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 20.0,
+                        ..default()
+                    },
+                ),
+            ));
+        });
+}
+
+fn update_inventory_panel(
+    panel: Res<InventoryPanel>,
+    items: Res<Assets<ItemDefinition>>,
+    player: Query<(Ref<Inventory>, Option<Ref<Equipment>>), With<Player>>,
+    mut text: Query<(&mut Text, &Parent), With<InventoryPanelText>>,
+    mut visibility: Query<&mut Visibility>,
+) {
+    let Ok((mut text, parent)) = text.get_single_mut() else {
+        return;
+    };
+    if let Ok(mut visibility) = visibility.get_mut(parent.get()) {
+        let wanted = if panel.open {
+            Visibility::Inherited
+        } else {
+            Visibility::Hidden
+        };
+        if *visibility != wanted {
+            *visibility = wanted;
+        }
+    }
+    let Ok((inventory, equipment)) = player.get_single() else {
+        return;
+    };
+    let changed = panel.is_changed()
+        || inventory.is_changed()
+        || equipment
+            .as_ref()
+            .is_some_and(|equipment| equipment.is_changed());
+    if !panel.open || !changed {
+        return;
+    }
+    let name = |handle: &Handle<ItemDefinition>| {
+        items
+            .get(handle)
+            .map_or("...".to_string(), |item| item.name.clone())
+    };
+    let style = |color: Color| TextStyle {
+        font_size: 20.0,
+        color,
+        ..default()
+    };
+    let white = Color::WHITE;
+    let mut sections = vec![TextSection::new(
+        format!(
+            "Inventory ({}/{})\n",
+            inventory.stacks.len(),
+            inventory.capacity
+        ),
+        style(white),
+    )];
+    for (slot, stack) in inventory.stacks.iter().enumerate() {
+        let color = if slot == panel.selected {
+            Palette::Yellow.to_color()
+        } else {
+            white
+        };
+        sections.push(TextSection::new(
+            format!("{} x{}\n", name(&stack.item), stack.count),
+            style(color),
+        ));
+    }
+    if let Some(item) = inventory
+        .stacks
+        .get(panel.selected)
+        .and_then(|stack| items.get(&stack.item))
+    {
+        sections.push(TextSection::new(
+            format!("{}\n", item.description),
+            style(Palette::Blue.to_color()),
+        ));
+    }
+    if let Some(equipment) = equipment {
+        let mut worn: Vec<_> = equipment.0.iter().collect();
+        worn.sort_by_key(|(slot, _)| format!("{slot:?}"));
+        for (slot, handle) in worn {
+            sections.push(TextSection::new(
+                format!("{slot:?}: {}\n", name(handle)),
+                style(white),
+            ));
+        }
+    }
+    sections.push(TextSection::new(
+        format!("{}\nUp/Down: choose  Enter: use or equip", panel.message),
+        style(white),
+    ));
+    text.sections = sections;
+}
+
+#[cfg(feature = "serde")]
+mod loader {
+    use super::ItemDefinition;
+    use bevy::asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext};
+
+    #[derive(Default)]
+    pub struct ItemLoader;
+
+    impl AssetLoader for ItemLoader {
+        type Asset = ItemDefinition;
+        type Settings = ();
+        type Error = anyhow::Error;
+
+        async fn load<'a>(
+            &'a self,
+            reader: &'a mut Reader<'_>,
+            _settings: &'a (),
+            _load_context: &'a mut LoadContext<'_>,
+        ) -> Result<ItemDefinition, anyhow::Error> {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(ron::de::from_bytes(&bytes)?)
+        }
+
+        fn extensions(&self) -> &[&str] {
+            &["item.ron"]
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::items::Explosion;
+    use bevy::ecs::system::RunSystemOnce;
+
+    fn potion() -> ItemDefinition {
+        ItemDefinition {
+            name: "Health potion".into(),
+            description: String::new(),
+            max_stack: 5,
+            color: [1.0, 0.0, 0.0],
+            kind: ItemKind::Consumable {
+                heal: 30,
+                mana: 0,
+                effects: vec![],
+            },
+        }
+    }
+
+    fn staff(speed: f32) -> ItemDefinition {
+        ItemDefinition {
+            name: "Staff".into(),
+            description: String::new(),
+            max_stack: 1,
+            color: [1.0, 1.0, 0.0],
+            kind: ItemKind::Equipment {
+                slot: EquipmentSlot::Staff,
+                modifiers: ProjectileModifiers {
+                    speed,
+                    damage: 2.0,
+                    ..default()
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn items_stack_up_to_their_limit() {
+        let mut items = Assets::<ItemDefinition>::default();
+        let potion = items.add(potion());
+        let mut inventory = Inventory {
+            capacity: 2,
+            ..default()
+        };
+        assert_eq!(inventory.add(&potion, 3, 5), 0);
+        assert_eq!(inventory.add(&potion, 4, 5), 0);
+        let counts: Vec<u32> = inventory.stacks.iter().map(|stack| stack.count).collect();
+        assert_eq!(counts, vec![5, 2]);
+        assert_eq!(inventory.add(&potion, 5, 5), 2);
+        assert!(!inventory.has_room_for(&potion, 5));
+        inventory.take_one(1);
+        assert_eq!(inventory.stacks[1].count, 4);
+    }
+
+    #[test]
+    fn potions_heal_and_are_used_up() {
+        let mut world = World::new();
+        world.init_resource::<Events<UseItem>>();
+        world.init_resource::<Events<ApplyStatus>>();
+        world.init_resource::<InventoryPanel>();
+        let mut items = Assets::<ItemDefinition>::default();
+        let potion = items.add(potion());
+        world.insert_resource(items);
+        let mut inventory = Inventory::default();
+        inventory.add(&potion, 1, 5);
+        let user = world
+            .spawn((
+                inventory,
+                Hp {
+                    current: 50,
+                    max: 60,
+                },
+            ))
+            .id();
+        world.send_event(UseItem { user, slot: 0 });
+        world.run_system_once(use_items);
+        assert_eq!(world.get::<Hp>(user).unwrap().current, 60);
+        assert!(world.get::<Inventory>(user).unwrap().stacks.is_empty());
+    }
+
+    #[test]
+    fn equipment_swaps_and_modifies_projectiles() {
+        let mut world = World::new();
+        world.init_resource::<Events<UseItem>>();
+        world.init_resource::<Events<ApplyStatus>>();
+        world.init_resource::<InventoryPanel>();
+        let mut items = Assets::<ItemDefinition>::default();
+        let slow = items.add(staff(0.5));
+        let fast = items.add(staff(2.0));
+        world.insert_resource(items);
+        let mut inventory = Inventory::default();
+        inventory.add(&slow, 1, 1);
+        inventory.add(&fast, 1, 1);
+        let user = world.spawn((inventory, Equipment::default())).id();
+
+        for _ in 0..2 {
+            world.send_event(UseItem { user, slot: 0 });
+            world.run_system_once(use_items);
+            // Every run_system_once starts a fresh reader, which would use this item again next time.
+            world.resource_mut::<Events<UseItem>>().clear();
+        }
+        world.run_system_once(update_projectile_modifiers);
+
+        let equipment = world.get::<Equipment>(user).unwrap();
+        assert_eq!(equipment.0.get(&EquipmentSlot::Staff), Some(&fast));
+        let inventory = world.get::<Inventory>(user).unwrap();
+        assert_eq!(inventory.stacks[0].item, slow);
+        let projectile = Projectile {
+            speed: 10.0,
+            radius: 1.0,
+            lifetime: 1.0,
+            damping: 0.0,
+            gravity: 0.0,
+            explosion: Explosion {
+                radius: 4.0,
+                damage: 10.0,
+                knockback: 1.0,
+                status: None,
+            },
+        };
+        let modified = world
+            .get::<ProjectileModifiers>(user)
+            .unwrap()
+            .apply(&projectile);
+        assert_eq!(modified.speed, 20.0);
+        assert_eq!(modified.explosion.damage, 20.0);
+        assert_eq!(modified.explosion.radius, 4.0);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn item_files_parse() {
+        let mut count = 0;
+        for entry in std::fs::read_dir("assets/items").unwrap() {
+            let path = entry.unwrap().path();
+            let text = std::fs::read_to_string(&path).unwrap();
+            let item: ItemDefinition = ron::from_str(&text)
+                .unwrap_or_else(|e| panic!("Couldn't parse {}: {e}", path.display()));
+            assert!(item.max_stack > 0, "{} can't stack", path.display());
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+}
@@ -0,0 +1,115 @@
+use crate::prelude::*;
+use bevy::prelude::*;
+use bevy_firework::{
+    core::{BlendMode, ParticleSpawnerBundle, ParticleSpawnerSettings},
+    emission_shape::EmissionShape,
+};
+use bevy_utilitarian::prelude::*;
+use std::f32::consts::PI;
+
+pub struct JetpackPlugin;
+
+impl Plugin for JetpackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, update_exhaust);
+    }
+}
+
+/// Hold-to-thrust levitation. The thrust itself is applied in `player_movement`, which also
+/// burns and refills the fuel; this component only holds the tuning and the current fuel level.
+#[derive(Component, Debug, Clone)]
+pub struct JetpackAbility {
+    pub thrust: f32, // upward acceleration while thrusting, must beat the player's effective gravity.
+    pub max_rise_speed: f32, // thrust stops accelerating the player past this vertical speed.
+    pub fuel: f32,
+    pub max_fuel: f32,
+    pub burn_per_second: f32,
+    pub regen_per_second: f32, // only regenerates while grounded.
+    pub thrusting: bool,       // written by player_movement, read by the exhaust effect.
+}
+
+impl Default for JetpackAbility {
+    fn default() -> Self {
+        JetpackAbility {
+            thrust: 70.,
+            max_rise_speed: 15.,
+            fuel: 100.,
+            max_fuel: 100.,
+            burn_per_second: 35.,
+            regen_per_second: 50.,
+            thrusting: false,
+        }
+    }
+}
+
+impl JetpackAbility {
+    /// Burns fuel for one tick of thrust and returns the new vertical speed.
+    pub fn burn(&mut self, vertical_speed: f32, delta_time: f32) -> f32 {
+        self.fuel = (self.fuel - self.burn_per_second * delta_time).max(0.);
+        if vertical_speed >= self.max_rise_speed {
+            // Don't slow down a jump that is already faster than the jetpack can go.
+            return vertical_speed;
+        }
+        (vertical_speed + self.thrust * delta_time).min(self.max_rise_speed)
+    }
+
+    pub fn refuel(&mut self, delta_time: f32) {
+        self.fuel = (self.fuel + self.regen_per_second * delta_time).min(self.max_fuel);
+    }
+}
+
+#[derive(Component)]
+struct JetpackExhaust;
+
+fn update_exhaust(
+    mut commands: Commands,
+    jetpacks: Query<(Entity, &JetpackAbility, Option<&Children>), Changed<JetpackAbility>>,
+    exhausts: Query<Entity, With<JetpackExhaust>>,
+) {
+    for (entity, jetpack, children) in &jetpacks {
+        let exhaust = children.and_then(|c| c.iter().find(|child| exhausts.contains(**child)));
+        match (jetpack.thrusting, exhaust) {
+            (true, None) => {
+                let exhaust = commands
+                    .spawn((
+                        ParticleSpawnerBundle::from_settings(ParticleSpawnerSettings {
+                            one_shot: false,
+                            rate: 500.0,
+                            emission_shape: EmissionShape::Sphere(0.15),
+                            lifetime: RandF32::constant(0.4),
+                            inherit_parent_velocity: false,
+                            initial_velocity: RandVec3 {
+                                magnitude: RandF32 { min: 5., max: 12. },
+                                direction: Vec3::NEG_Y,
+                                spread: 15. / 180. * PI,
+                            },
+                            initial_scale: RandF32 {
+                                min: 0.02,
+                                max: 0.06,
+                            },
+                            scale_curve: ParamCurve::constant(1.),
+                            color: Gradient::linear(vec![
+                                (0., LinearRgba::new(0.9, 0.6, 0.2, 1.)),
+                                (0.5, LinearRgba::new(0.6, 0.2, 0.1, 1.)),
+                                (1., LinearRgba::new(0.2, 0.2, 0.2, 0.)),
+                            ]),
+                            blend_mode: BlendMode::Blend,
+                            linear_drag: 0.5,
+                            pbr: false,
+                            ..default()
+                        }),
+                        JetpackExhaust,
+                        Name::new("jetpack_exhaust"),
+                    ))
+                    // Just below and behind the player's collider.
+                    .insert(Transform::from_xyz(0., -0.8, 0.35))
+                    .id();
+                commands.entity(entity).add_child(exhaust);
+            }
+            (false, Some(exhaust)) => {
+                commands.entity(*exhaust).despawn_recursive();
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{camera::Flycam, prelude::*};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+pub struct LaunchPadsPlugin;
+
+impl Plugin for LaunchPadsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PreUpdate, load_launch_pads);
+        app.add_systems(Update, launch_dynamic_bodies);
+    }
+}
+
+const PAD_RADIUS: f32 = 2.0;
+const PAD_HALF_HEIGHT: f32 = 0.25;
+// How far in front of the flycam a pad spawned from the HUD is dropped onto the terrain.
+const PAD_SPAWN_DISTANCE: f32 = 20.0;
+
+/// A trampoline / launch pad. Inserting one is enough: `load_launch_pads` gives it its cylinder mesh and
+/// collider. A pad without a Transform is dropped onto the terrain in front of the flycam.
+///
+/// `impulse` is the velocity, in world space, that touching the pad gives you. The player's velocity is
+/// overridden in `player_movement`; dynamic bodies receive the equivalent Rapier impulse for their mass.
+#[derive(Component, Debug, Clone)]
+pub struct LaunchPad {
+    pub impulse: Vec3,
+}
+
+impl Default for LaunchPad {
+    fn default() -> Self {
+        LaunchPad {
+            impulse: Vec3::new(0., 60., 0.),
+        }
+    }
+}
+
+fn load_launch_pads(
+    mut commands: Commands,
+    pads: Query<(Entity, Option<&Transform>), Added<LaunchPad>>,
+    flycam: Query<&GlobalTransform, With<Flycam>>,
+    rapier_context: Res<RapierContext>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    if pads.is_empty() {
+        return;
+    }
+    let mesh = meshes.add(Cylinder::new(PAD_RADIUS, PAD_HALF_HEIGHT * 2.0));
+    let material = materials.add(Palette::Yellow.to_color());
+
+    for (entity, transform) in &pads {
+        let transform = match transform {
+            Some(t) => *t,
+            None => {
+                let Ok(cam) = flycam.get_single() else {
+                    warn!("Launch pad spawned without a Transform and there's no Flycam to place it with.");
+                    commands.entity(entity).despawn_recursive();
+                    continue;
+                };
+                let ahead = cam.translation() + cam.forward() * PAD_SPAWN_DISTANCE;
+                let ground = rapier_context
+                    .cast_ray(
+                        ahead,
+                        Vec3::NEG_Y,
+                        f32::MAX,
+                        true,
+                        QueryFilter::only_fixed(),
+                    )
+                    .map(|(_, toi)| ahead + Vec3::NEG_Y * toi)
+                    .unwrap_or(ahead);
+                Transform::from_translation(ground + Vec3::Y * PAD_HALF_HEIGHT)
+            }
+        };
+        info!("Spawning launch pad at {}", transform.translation);
+        commands.entity(entity).insert((
+            Name::new("launch_pad"),
+            PbrBundle {
+                mesh: mesh.clone(),
+                material: material.clone(),
+                transform,
+                ..default()
+            },
+            RigidBody::Fixed,
+            Collider::cylinder(PAD_HALF_HEIGHT, PAD_RADIUS),
+            ActiveEvents::COLLISION_EVENTS,
+            OutOfBounds::Despawn,
+        ));
+    }
+}
+
+fn launch_dynamic_bodies(
+    mut commands: Commands,
+    mut collisions: EventReader<CollisionEvent>,
+    pads: Query<&LaunchPad>,
+    bodies: Query<&RigidBody>,
+    rapier_context: Res<RapierContext>,
+) {
+    for event in collisions.read() {
+        let CollisionEvent::Started(a, b, _) = event else {
+            continue;
+        };
+        let (pad, other) = match (pads.get(*a), pads.get(*b)) {
+            (Ok(pad), _) => (pad, *b),
+            (_, Ok(pad)) => (pad, *a),
+            _ => continue,
+        };
+        if !matches!(bodies.get(other), Ok(RigidBody::Dynamic)) {
+            continue;
+        }
+        let Some(mass) = rapier_context
+            .entity2body()
+            .get(&other)
+            .and_then(|handle| rapier_context.bodies.get(*handle))
+            .map(|body| body.mass())
+        else {
+            continue;
+        };
+        commands.entity(other).insert(ExternalImpulse {
+            impulse: pad.impulse * mass,
+            ..default()
+        });
+    }
+}
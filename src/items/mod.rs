@@ -1,18 +1,48 @@
 use bevy::prelude::*;
 pub mod spinner;
 
+pub mod ability;
+pub use ability::*;
+
 pub mod fireball;
 pub use fireball::*;
 
+pub mod spells;
+pub use spells::*;
+
+pub mod hitscan;
+pub use hitscan::*;
+
 pub mod platforms;
 pub use platforms::*;
 
+pub mod platform_paths;
+pub use platform_paths::*;
+
+pub mod jetpack;
+pub use jetpack::*;
+
+pub mod launch_pads;
+pub use launch_pads::*;
+
+pub mod movement;
+pub use movement::*;
+
+pub mod inventory;
+pub use inventory::*;
+
 pub struct ItemsPlugin;
 
 impl Plugin for ItemsPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(spinner::SpinnerUiPlugin);
         app.add_plugins(FireballPlugin);
+        app.add_plugins(SpellsPlugin);
+        app.add_plugins(HitscanPlugin);
         app.add_plugins(platforms::PlatformsPlugin);
+        app.add_plugins(JetpackPlugin);
+        app.add_plugins(LaunchPadsPlugin);
+        app.add_plugins(MovementAbilitiesPlugin);
+        app.add_plugins(InventoryPlugin);
     }
 }
@@ -0,0 +1,295 @@
+use crate::{
+    items::AbilityCost,
+    mana::Mana,
+    player::{MovementSet, PlayerInput, PlayerMotion},
+};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use std::time::Duration;
+
+pub struct MovementAbilitiesPlugin;
+
+impl Plugin for MovementAbilitiesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            FixedUpdate,
+            (dash, slide, wall_run).in_set(MovementSet::Abilities),
+        );
+    }
+}
+
+// When a dash ends, the player keeps this fraction of the dash's velocity.
+const DASH_EXIT_FRACTION: f32 = 0.25;
+// How much of the walking speed is left for steering while sliding.
+const SLIDE_STEERING: f32 = 0.25;
+// Contact normals flatter than this are ground, steeper ones can be wall-run on.
+const GROUND_MIN_NORMAL_Y: f32 = 0.5;
+const WALL_MAX_NORMAL_Y: f32 = 0.3;
+// Wall runs push the player gently into the wall so that the character controller keeps reporting it.
+const WALL_STICK_SPEED: f32 = 1.0;
+// Rapier doesn't report the wall contact on every tick, so a wall run survives this long without one.
+const WALL_CONTACT_GRACE: f32 = 0.15;
+
+/// Surface normals of everything the character controller ran into last tick, pointing away from the surface.
+fn contact_normals(output: &KinematicCharacterControllerOutput) -> impl Iterator<Item = Vec3> + '_ {
+    output
+        .collisions
+        .iter()
+        .filter_map(|collision| collision.hit.details.as_ref().map(|d| -d.normal1))
+}
+
+pub fn ground_normal(output: &KinematicCharacterControllerOutput) -> Option<Vec3> {
+    contact_normals(output)
+        .filter(|n| n.y > GROUND_MIN_NORMAL_Y)
+        .max_by(|a, b| a.y.total_cmp(&b.y))
+}
+
+pub fn wall_normal(output: &KinematicCharacterControllerOutput) -> Option<Vec3> {
+    contact_normals(output).find(|n| n.y.abs() < WALL_MAX_NORMAL_Y)
+}
+
+/// A short burst of speed in the direction the player is moving, or forward if they aren't.
+#[derive(Component, Debug, Clone)]
+pub struct DashAbility {
+    pub cost: AbilityCost,
+    pub speed: f32,
+    pub duration: f32,
+    remaining: f32,
+    direction: Vec3,
+}
+
+impl Default for DashAbility {
+    fn default() -> Self {
+        DashAbility {
+            cost: AbilityCost::new(10, Duration::from_millis(1500)),
+            speed: 45.,
+            duration: 0.2,
+            remaining: 0.,
+            direction: Vec3::ZERO,
+        }
+    }
+}
+
+/// Crouching while moving on the ground starts a slide, which keeps accelerating down slopes.
+#[derive(Component, Debug, Clone)]
+pub struct SlideAbility {
+    pub cost: AbilityCost,
+    pub boost_speed: f32,
+    pub slope_acceleration: f32,
+    pub friction: f32, // fraction of the slide velocity lost per second.
+    pub min_speed: f32,
+    velocity: Option<Vec3>,
+}
+
+impl Default for SlideAbility {
+    fn default() -> Self {
+        SlideAbility {
+            cost: AbilityCost::new(0, Duration::from_millis(800)),
+            boost_speed: 18.,
+            slope_acceleration: 30.,
+            friction: 1.5,
+            min_speed: 3.,
+            velocity: None,
+        }
+    }
+}
+
+/// Holding forward while airborne against a steep surface runs along it. Jumping kicks off the wall.
+#[derive(Component, Debug, Clone)]
+pub struct WallRunAbility {
+    pub cost: AbilityCost,
+    pub speed: f32,
+    pub max_duration: f32,
+    pub gravity_scale: f32,
+    pub kick_speed: f32,
+    pub kick_jump_speed: f32,
+    run: Option<WallRun>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct WallRun {
+    normal: Vec3,
+    elapsed: f32,
+    since_contact: f32,
+}
+
+impl Default for WallRunAbility {
+    fn default() -> Self {
+        WallRunAbility {
+            cost: AbilityCost::new(2, Duration::from_millis(500)),
+            speed: 14.,
+            max_duration: 1.5,
+            gravity_scale: 0.1,
+            kick_speed: 15.,
+            kick_jump_speed: 25.,
+            run: None,
+        }
+    }
+}
+
+fn dash(
+    time: Res<Time>,
+    mut characters: Query<(
+        &Transform,
+        &PlayerInput,
+        &mut PlayerMotion,
+        &mut Mana,
+        &mut DashAbility,
+    )>,
+) {
+    for (transform, input, mut motion, mut mana, mut dash) in &mut characters {
+        dash.cost.tick(time.delta());
+
+        if dash.remaining > 0.0 {
+            dash.remaining -= time.delta_seconds();
+            if dash.remaining > 0.0 {
+                motion.external_velocity = dash.direction * dash.speed;
+                motion.vertical_speed = 0.0;
+                motion.gravity_scale = 0.0;
+                motion.walk_scale = 0.0;
+            } else {
+                motion.external_velocity = dash.direction * dash.speed * DASH_EXIT_FRACTION;
+            }
+            continue;
+        }
+
+        if !input.dash || !dash.cost.try_activate(&mut mana) {
+            continue;
+        }
+        let wish = Vec3::new(input.movement.x, 0.0, input.movement.z);
+        let local_direction = if wish == Vec3::ZERO {
+            Vec3::NEG_Z
+        } else {
+            wish.normalize()
+        };
+        dash.direction = transform.rotation * local_direction;
+        dash.remaining = dash.duration;
+    }
+}
+
+fn slide(
+    time: Res<Time>,
+    mut characters: Query<(
+        &Transform,
+        &PlayerInput,
+        Option<&KinematicCharacterControllerOutput>,
+        &mut PlayerMotion,
+        &mut Mana,
+        &mut SlideAbility,
+    )>,
+) {
+    let delta_time = time.delta_seconds();
+    for (transform, input, output, mut motion, mut mana, mut slide) in &mut characters {
+        slide.cost.tick(time.delta());
+
+        let ground = output.and_then(ground_normal);
+        let Some(normal) = ground.filter(|_| input.crouch) else {
+            // Leaving the ground mid-slide keeps the momentum in external_velocity, where it decays with air drag.
+            slide.velocity = None;
+            continue;
+        };
+
+        let current = slide.velocity;
+        let mut velocity = match current {
+            Some(velocity) => velocity,
+            None => {
+                let wish = Vec3::new(input.movement.x, 0.0, input.movement.z);
+                if wish == Vec3::ZERO || !slide.cost.try_activate(&mut mana) {
+                    continue;
+                }
+                transform.rotation * wish.normalize() * slide.boost_speed + motion.external_velocity
+            }
+        };
+
+        // Gravity pulls the slide down the slope: project it onto the ground plane.
+        let downhill = Vec3::NEG_Y - normal * normal.dot(Vec3::NEG_Y);
+        velocity += downhill * slide.slope_acceleration * delta_time;
+        velocity -= velocity * (slide.friction * delta_time).min(1.0);
+        // The character controller follows the terrain, so only the horizontal part is ours to keep.
+        velocity.y = 0.0;
+
+        if velocity.length() < slide.min_speed {
+            slide.velocity = None;
+            continue;
+        }
+        slide.velocity = Some(velocity);
+        motion.external_velocity = velocity;
+        motion.walk_scale = SLIDE_STEERING;
+    }
+}
+
+fn wall_run(
+    time: Res<Time>,
+    mut characters: Query<(
+        &Transform,
+        &mut PlayerInput,
+        Option<&KinematicCharacterControllerOutput>,
+        &mut PlayerMotion,
+        &mut Mana,
+        &mut WallRunAbility,
+    )>,
+) {
+    let delta_time = time.delta_seconds();
+    for (transform, mut input, output, mut motion, mut mana, mut wall_run) in &mut characters {
+        wall_run.cost.tick(time.delta());
+
+        let grounded = output.map(|o| o.grounded).unwrap_or(false);
+        let wall = output.and_then(wall_normal);
+        let forward = transform.rotation * Vec3::NEG_Z;
+        let holding_forward = input.movement.z < 0.0;
+
+        let current = wall_run.run;
+        let run = match current {
+            Some(mut run) => {
+                if input.movement.y > 0.0 {
+                    // Kick off the wall. Consume the jump so that player_movement doesn't spend an air jump on it.
+                    input.movement.y = 0.0;
+                    motion.external_velocity = (run.normal + forward * 0.5) * wall_run.kick_speed;
+                    motion.vertical_speed = wall_run.kick_jump_speed;
+                    wall_run.run = None;
+                    continue;
+                }
+                run.elapsed += delta_time;
+                match wall {
+                    Some(normal) => {
+                        run.normal = normal;
+                        run.since_contact = 0.0;
+                    }
+                    None => run.since_contact += delta_time,
+                }
+                if grounded
+                    || !holding_forward
+                    || run.since_contact > WALL_CONTACT_GRACE
+                    || run.elapsed > wall_run.max_duration
+                {
+                    wall_run.run = None;
+                    continue;
+                }
+                run
+            }
+            None => {
+                let Some(normal) = wall else {
+                    continue;
+                };
+                if grounded || !holding_forward || !wall_run.cost.try_activate(&mut mana) {
+                    continue;
+                }
+                // Catching a wall stops the fall.
+                motion.vertical_speed = motion.vertical_speed.max(0.0);
+                WallRun {
+                    normal,
+                    elapsed: 0.0,
+                    since_contact: 0.0,
+                }
+            }
+        };
+        wall_run.run = Some(run);
+
+        let mut along_wall = forward - run.normal * forward.dot(run.normal);
+        along_wall.y = 0.0;
+        motion.external_velocity =
+            along_wall.normalize_or_zero() * wall_run.speed - run.normal * WALL_STICK_SPEED;
+        motion.gravity_scale = wall_run.gravity_scale;
+        motion.walk_scale = 0.0;
+    }
+}
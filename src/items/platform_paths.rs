@@ -0,0 +1,233 @@
+use bevy::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// How a platform moves between two waypoints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Reflect)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Easing {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+/// What a platform does when it reaches its last waypoint.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Reflect)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum PathMode {
+    /// Carry on from the last waypoint back to the first.
+    #[default]
+    Loop,
+    /// Retrace the waypoints in reverse.
+    PingPong,
+    /// Stop at the last waypoint.
+    OneShot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Reflect)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Waypoint {
+    pub position: Vec3,
+    /// Seconds to wait on arriving at this waypoint.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub wait: f32,
+}
+
+/// Drives a `Platform` along waypoints instead of its `linvel`. `update_platforms` advances it each tick and
+/// keeps the platform's `linvel` up to date, so riders and anything else that reads it see the path's speed.
+#[derive(Component, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PlatformPath {
+    pub waypoints: Vec<Waypoint>,
+    pub speed: f32,
+    pub easing: Easing,
+    pub mode: PathMode,
+    progress: PathProgress,
+}
+
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+struct PathProgress {
+    from: usize,
+    to: usize,
+    t: f32, // 0..1 along the segment from -> to, before easing.
+    wait: f32,
+    reversing: bool,
+    finished: bool,
+}
+
+impl PlatformPath {
+    pub fn new(waypoints: Vec<Waypoint>, speed: f32, easing: Easing, mode: PathMode) -> Self {
+        let progress = PathProgress {
+            from: 0,
+            to: 1,
+            wait: waypoints.first().map(|w| w.wait).unwrap_or_default(),
+            // A path with fewer than two waypoints has nowhere to go.
+            finished: waypoints.len() < 2,
+            ..default()
+        };
+        PlatformPath {
+            waypoints,
+            speed,
+            easing,
+            mode,
+            progress,
+        }
+    }
+
+    pub fn finished(&self) -> bool {
+        self.progress.finished
+    }
+
+    pub fn position(&self) -> Vec3 {
+        let Some(from) = self.waypoints.get(self.progress.from) else {
+            return Vec3::ZERO;
+        };
+        if self.finished() {
+            return from.position;
+        }
+        let to = self.waypoints[self.progress.to].position;
+        from.position.lerp(to, self.easing.apply(self.progress.t))
+    }
+
+    /// Moves along the path by `delta` seconds and returns the new position.
+    pub fn advance(&mut self, delta: f32) -> Vec3 {
+        let mut remaining = delta;
+        // Bounded, so that a loop of coincident waypoints can't spin forever.
+        let mut arrivals = 0;
+        while remaining > 0.0 && !self.progress.finished && arrivals <= self.waypoints.len() * 2 {
+            if self.progress.wait > 0.0 {
+                let waited = self.progress.wait.min(remaining);
+                self.progress.wait -= waited;
+                remaining -= waited;
+                continue;
+            }
+            let from = self.waypoints[self.progress.from].position;
+            let to = self.waypoints[self.progress.to].position;
+            let segment_time = from.distance(to) / self.speed.max(f32::EPSILON);
+            let left_in_segment = (1.0 - self.progress.t) * segment_time;
+            if remaining < left_in_segment {
+                self.progress.t += remaining / segment_time;
+                remaining = 0.0;
+            } else {
+                remaining -= left_in_segment;
+                self.arrive();
+                arrivals += 1;
+            }
+        }
+        self.position()
+    }
+
+    fn arrive(&mut self) {
+        let last = self.waypoints.len() - 1;
+        let progress = &mut self.progress;
+        progress.from = progress.to;
+        progress.t = 0.0;
+        progress.wait = self.waypoints[progress.from].wait;
+        match self.mode {
+            PathMode::Loop => progress.to = (progress.from + 1) % self.waypoints.len(),
+            PathMode::PingPong => {
+                if progress.from == last {
+                    progress.reversing = true;
+                } else if progress.from == 0 {
+                    progress.reversing = false;
+                }
+                progress.to = if progress.reversing {
+                    progress.from - 1
+                } else {
+                    progress.from + 1
+                };
+            }
+            PathMode::OneShot => {
+                if progress.from == last {
+                    progress.finished = true;
+                } else {
+                    progress.to = progress.from + 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn waypoints(wait: f32) -> Vec<Waypoint> {
+        [Vec3::ZERO, Vec3::new(10., 0., 0.), Vec3::new(10., 10., 0.)]
+            .into_iter()
+            .map(|position| Waypoint { position, wait })
+            .collect()
+    }
+
+    fn assert_near(actual: Vec3, expected: Vec3) {
+        assert!(
+            actual.distance(expected) < EPSILON,
+            "expected {expected}, got {actual}"
+        );
+    }
+
+    #[test]
+    fn loop_returns_to_the_start() {
+        let mut path = PlatformPath::new(waypoints(0.), 10., Easing::Linear, PathMode::Loop);
+        assert_near(path.advance(0.5), Vec3::new(5., 0., 0.));
+        assert_near(path.advance(1.0), Vec3::new(10., 5., 0.));
+        // The closing segment is the diagonal back to the first waypoint.
+        let diagonal = 200f32.sqrt() / 10.;
+        assert_near(path.advance(0.5 + diagonal), Vec3::ZERO);
+        assert!(!path.finished());
+    }
+
+    #[test]
+    fn ping_pong_retraces_its_steps() {
+        let mut path = PlatformPath::new(waypoints(0.), 10., Easing::Linear, PathMode::PingPong);
+        assert_near(path.advance(2.0), Vec3::new(10., 10., 0.));
+        assert_near(path.advance(0.5), Vec3::new(10., 5., 0.));
+        assert_near(path.advance(1.0), Vec3::new(5., 0., 0.));
+        assert_near(path.advance(0.5), Vec3::ZERO);
+        // And turns around again at the start.
+        assert_near(path.advance(0.25), Vec3::new(2.5, 0., 0.));
+    }
+
+    #[test]
+    fn one_shot_stops_at_the_end() {
+        let mut path = PlatformPath::new(waypoints(0.), 10., Easing::Linear, PathMode::OneShot);
+        assert_near(path.advance(100.), Vec3::new(10., 10., 0.));
+        assert!(path.finished());
+        assert_near(path.advance(1.), Vec3::new(10., 10., 0.));
+    }
+
+    #[test]
+    fn waits_at_each_waypoint() {
+        let mut path = PlatformPath::new(waypoints(1.), 10., Easing::Linear, PathMode::OneShot);
+        assert_near(path.advance(1.0), Vec3::ZERO);
+        assert_near(path.advance(1.0), Vec3::new(10., 0., 0.));
+        assert_near(path.advance(1.0), Vec3::new(10., 0., 0.));
+        assert_near(path.advance(0.5), Vec3::new(10., 5., 0.));
+    }
+
+    #[test]
+    fn easing_starts_and_ends_slowly() {
+        let mut path = PlatformPath::new(waypoints(0.), 10., Easing::EaseInOut, PathMode::OneShot);
+        let early = path.advance(0.1);
+        assert!(early.x < 1.0, "{early}");
+        assert_near(path.advance(0.4), Vec3::new(5., 0., 0.));
+        assert_near(path.advance(0.5), Vec3::new(10., 0., 0.));
+    }
+}
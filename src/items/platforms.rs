@@ -1,4 +1,5 @@
 use crate::asset_cache::AssetCache;
+use crate::items::PlatformPath;
 use crate::prelude::*;
 use bevy::{prelude::*, time::Timer};
 use bevy_rapier3d::{na::Quaternion, prelude::*};
@@ -19,19 +20,126 @@ struct PlatformMeshes {
     meshes: Vec<Handle<Mesh>>,
 }
 
-#[derive(Default, Component)]
+/// A moving platform that carries whatever stands on it. The random spawner builds its own; level platforms
+/// only need this and a Transform, and `load_platforms` builds a box of `half_extents` for them. Add a
+/// `PlatformPath` to move it along waypoints instead of by `linvel`.
+#[derive(Default, Component, Debug, Clone)]
 pub struct Platform {
     pub linvel: Vec3,
+    /// World space angular velocity, in radians per second.
+    pub angvel: Vec3,
+    /// Half extents of the platform's box in its local space, for pushing riders sideways.
+    pub half_extents: Vec3,
+    /// The platform's transform before this tick's move. Riders are carried by the difference.
+    pub previous: Transform,
+}
+
+impl Platform {
+    /// Where a point attached to the platform was carried to by this tick's move.
+    pub fn carry(&self, current: &Transform, point: Vec3) -> Vec3 {
+        carry_point(&self.previous, current, point)
+    }
+}
+
+/// Moves `point` rigidly with a platform that went from `from` to `to`, by way of the platform's local frame.
+pub fn carry_point(from: &Transform, to: &Transform, point: Vec3) -> Vec3 {
+    let local = from.compute_affine().inverse().transform_point3(point);
+    to.compute_affine().transform_point3(local)
+}
+
+/// How far, in radians, a platform that went from `from` to `to` turned about the world's up axis.
+pub fn carry_yaw(from: &Transform, to: &Transform) -> f32 {
+    let delta = to.rotation * from.rotation.inverse();
+    let (yaw, _, _) = delta.to_euler(EulerRot::YXZ);
+    yaw
+}
+
+/// If a rider of `rider_radius` at `point` overlaps the side of a platform, the horizontal world space
+/// displacement that pushes it back out. Overlapping through the top or bottom is riding, not pushing.
+pub fn lateral_push(
+    platform: &Transform,
+    half_extents: Vec3,
+    point: Vec3,
+    rider_radius: f32,
+) -> Option<Vec3> {
+    let local = platform.compute_affine().inverse().transform_point3(point);
+    let overlap = half_extents + Vec3::splat(rider_radius) - local.abs();
+    if overlap.min_element() <= 0.0 {
+        return None;
+    }
+    let up_axis = up_axis(platform);
+    let push_axis = (0..3)
+        .min_by(|a, b| overlap[*a].total_cmp(&overlap[*b]))
+        .unwrap();
+    if push_axis == up_axis {
+        return None;
+    }
+    let mut push = Vec3::ZERO;
+    push[push_axis] = overlap[push_axis] * local[push_axis].signum();
+    let mut push = platform.rotation * push;
+    push.y = 0.0;
+    Some(push)
+}
+
+/// Whether a rider of `rider_radius` at `point` is still over the platform, looking down on it. A rider
+/// outside of this has stepped off, however recently the platform was underfoot.
+pub fn within_footprint(
+    platform: &Transform,
+    half_extents: Vec3,
+    point: Vec3,
+    rider_radius: f32,
+) -> bool {
+    let local = platform.compute_affine().inverse().transform_point3(point);
+    let up_axis = up_axis(platform);
+    (0..3)
+        .filter(|axis| *axis != up_axis)
+        .all(|axis| local[axis].abs() < half_extents[axis] + rider_radius)
+}
+
+/// The platform's local axis that points closest to the world's up axis, either way.
+fn up_axis(platform: &Transform) -> usize {
+    (0..3)
+        .max_by(|a, b| {
+            let a = (platform.rotation * Vec3::AXES[*a]).y.abs();
+            let b = (platform.rotation * Vec3::AXES[*b]).y.abs();
+            a.total_cmp(&b)
+        })
+        .unwrap()
 }
 
 impl Plugin for PlatformsPlugin {
     fn build(&self, app: &mut App) {
+        app.init_resource::<PlatformSettings>();
         app.add_systems(Startup, startup_platforms);
-        app.add_systems(FixedUpdate, update_platforms);
+        app.add_systems(PreUpdate, load_platforms);
+        app.add_systems(
+            FixedUpdate,
+            (spawn_random_platforms, update_platforms)
+                .chain()
+                .before(crate::player::MovementSet::Abilities),
+        );
+    }
+}
+
+/// Random platforms drifting through the sky. Levels that lay out their own platform routes turn this off.
+#[derive(Resource, Debug, Clone)]
+pub struct PlatformSettings {
+    pub random_spawner: bool,
+}
+
+impl Default for PlatformSettings {
+    fn default() -> Self {
+        PlatformSettings {
+            random_spawner: true,
+        }
     }
 }
 
-fn startup_platforms(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+fn startup_platforms(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    settings: Res<PlatformSettings>,
+) {
     // let meshes = vec![
     //     meshes.add(Extrusion::new(Rectangle::default(), 1.)),
     //     meshes.add(Extrusion::new(Capsule2d::default(), 1.)),
@@ -43,22 +151,50 @@ fn startup_platforms(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
     // ];
     let meshes = vec![meshes.add(Extrusion::new(Rectangle::new(100., 100.), 5.))];
     commands.insert_resource(PlatformMeshes { meshes });
-    commands.spawn(
-        (PlatformSpawner {
-            timer: Timer::from_seconds(0.0, TimerMode::Repeating),
-        }),
-    );
+    if settings.random_spawner {
+        commands.spawn(
+            (PlatformSpawner {
+                timer: Timer::from_seconds(0.0, TimerMode::Repeating),
+            }),
+        );
+    }
 }
-fn update_platforms(
+
+fn load_platforms(
+    mut commands: Commands,
+    mut platforms: Query<
+        (Entity, &Transform, &mut Platform),
+        (Added<Platform>, Without<Handle<Mesh>>),
+    >,
+    mut meshes: ResMut<Assets<Mesh>>,
+    asset_cache: Res<AssetCache>,
+) {
+    for (entity, transform, mut platform) in &mut platforms {
+        platform.previous = *transform;
+        let half_extents = platform.half_extents;
+        commands.entity(entity).insert((
+            Name::new("platform"),
+            PbrBundle {
+                mesh: meshes.add(Cuboid::from_size(half_extents * 2.0)),
+                material: asset_cache.debug_material.clone(),
+                transform: *transform,
+                ..default()
+            },
+            OutOfBounds::Despawn,
+            RigidBody::KinematicPositionBased,
+            Collider::cuboid(half_extents.x, half_extents.y, half_extents.z),
+        ));
+    }
+}
+
+fn spawn_random_platforms(
     mut commands: Commands,
     time: Res<Time>,
     mut spawners: Query<&mut PlatformSpawner>,
-    mut platforms: Query<(&mut Transform, &Platform)>,
     meshes: Res<Assets<Mesh>>,
     asset_cache: Res<AssetCache>,
     platform_meshes: Res<PlatformMeshes>,
 ) {
-    let delta_time = time.delta_seconds();
     for mut spawner in spawners.iter_mut() {
         if spawner.timer.tick(time.delta()).finished() {
             let picked_mesh = platform_meshes
@@ -71,6 +207,13 @@ fn update_platforms(
                 thread_rng().gen_range(-75.0..-25.0),
             ));
             transform.rotate_x(90.0f32.to_radians());
+            let mesh = meshes
+                .get(picked_mesh)
+                .expect("Couldn't get a mesh entity to spawn a collider.");
+            let half_extents = mesh
+                .compute_aabb()
+                .map(|aabb| Vec3::from(aabb.half_extents))
+                .unwrap_or_default();
             commands
                 .spawn((
                     PbrBundle {
@@ -88,25 +231,161 @@ fn update_platforms(
                         )
                         .normalize()
                             * thread_rng().gen_range(3.0..10.0),
+                        angvel: Vec3::Y * thread_rng().gen_range(-0.2..0.2),
+                        half_extents,
+                        previous: transform,
                     },
-                    Leash(1000.),
+                    OutOfBounds::Despawn,
                 ))
                 .insert((
                     RigidBody::KinematicPositionBased,
-                    Collider::from_bevy_mesh(
-                        meshes
-                            .get(picked_mesh)
-                            .expect("Couldn't get a mesh entity to spawn a collider."),
-                        &ComputedColliderShape::TriMesh,
-                    )
-                    .unwrap(),
+                    Collider::from_bevy_mesh(mesh, &ComputedColliderShape::TriMesh).unwrap(),
                 ));
             spawner
                 .timer
                 .set_duration(Duration::from_millis(thread_rng().gen_range(12000..12001)));
         }
     }
-    for (mut transform, platform) in platforms.iter_mut() {
-        transform.translation += platform.linvel * delta_time;
+}
+
+fn update_platforms(
+    time: Res<Time>,
+    mut platforms: Query<(&mut Transform, &mut Platform, Option<&mut PlatformPath>)>,
+) {
+    let delta_time = time.delta_seconds();
+    for (mut transform, mut platform, path) in platforms.iter_mut() {
+        platform.previous = *transform;
+        match path {
+            Some(mut path) => {
+                let target = path.advance(delta_time);
+                if delta_time > 0.0 {
+                    platform.linvel = (target - transform.translation) / delta_time;
+                }
+                transform.translation = target;
+            }
+            None => transform.translation += platform.linvel * delta_time,
+        }
+        transform.rotation =
+            Quat::from_scaled_axis(platform.angvel * delta_time) * transform.rotation;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::player::{player_movement, LookInput, MovementProfile, PlayerInput, PlayerMotion};
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy_rapier3d::rapier::prelude::ColliderBuilder;
+    use std::f32::consts::PI;
+
+    const EPSILON: f32 = 1e-3;
+    const DT: f32 = 1.0 / 64.0;
+
+    // A platform that accelerates along a curve while spinning about its up axis, laid flat like the
+    // spawned platforms are.
+    fn scripted_path(tick: u32) -> Transform {
+        let t = tick as f32 * DT;
+        let mut transform = Transform::from_xyz(10. * t * t, 3. * (t * 2.).sin(), -4. * t);
+        transform.rotate_x(90.0f32.to_radians());
+        transform.rotation = Quat::from_rotation_y(0.5 * PI * t) * transform.rotation;
+        transform
+    }
+
+    // Moves the platforms and the riders for one tick, and returns how far the rider was asked to move.
+    fn tick(world: &mut World, rider: Entity) -> Vec3 {
+        world.run_system_once(update_platforms);
+        world.run_system_once(player_movement);
+        let translation = world
+            .get::<KinematicCharacterController>(rider)
+            .unwrap()
+            .translation
+            .unwrap();
+        world.get_mut::<Transform>(rider).unwrap().translation += translation;
+        translation
+    }
+
+    #[test]
+    fn riders_are_carried_until_they_step_off() {
+        let mut world = World::new();
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs_f32(DT));
+        world.insert_resource(time);
+        world.init_resource::<LookInput>();
+        let half_extents = Vec3::new(5., 0.5, 5.);
+        let platform = world
+            .spawn((
+                Transform::IDENTITY,
+                Platform {
+                    linvel: Vec3::X * 4.,
+                    half_extents,
+                    ..default()
+                },
+            ))
+            .id();
+        // Rapier isn't stepped here, so the platform's collider stays put where the character's ray finds it.
+        let mut rapier_context = RapierContext::default();
+        rapier_context.colliders.insert(
+            ColliderBuilder::cuboid(half_extents.x, half_extents.y, half_extents.z)
+                .user_data(platform.to_bits() as u128)
+                .build(),
+        );
+        rapier_context
+            .query_pipeline
+            .update(&rapier_context.colliders);
+        world.insert_resource(rapier_context);
+        let rider = world
+            .spawn((
+                Transform::from_xyz(0., 1.5, 0.),
+                PlayerInput::default(),
+                KinematicCharacterController::default(),
+                MovementProfile::default(),
+                PlayerMotion::default(),
+            ))
+            .id();
+
+        let carried = tick(&mut world, rider);
+        assert!((carried.x - 4. * DT).abs() < EPSILON, "carried {carried}");
+        assert_eq!(
+            world.get::<PlayerMotion>(rider).unwrap().riding,
+            Some(platform)
+        );
+
+        // Off the edge, well within the time that a missed contact is forgiven.
+        world.get_mut::<Transform>(rider).unwrap().translation.x = 8.;
+        let carried = tick(&mut world, rider);
+        assert!(carried.x.abs() < EPSILON, "still carried {carried}");
+        assert_eq!(world.get::<PlayerMotion>(rider).unwrap().riding, None);
+    }
+
+    #[test]
+    fn rider_turns_with_the_platform() {
+        let mut yaw = 0.0;
+        for tick in 1..=64 {
+            yaw += carry_yaw(&scripted_path(tick - 1), &scripted_path(tick));
+        }
+        // One second at a quarter turn per second.
+        assert!((yaw - 0.5 * PI).abs() < EPSILON, "turned {yaw}");
+    }
+
+    #[test]
+    fn side_contact_pushes_rider_out_horizontally() {
+        let mut platform = Transform::from_xyz(0., 10., 0.);
+        platform.rotate_x(90.0f32.to_radians());
+        let half_extents = Vec3::new(50., 50., 2.5);
+
+        // Just inside the platform's +x edge, at the height of its middle.
+        let push = lateral_push(&platform, half_extents, Vec3::new(50.1, 10., 0.), 0.3).unwrap();
+        assert!((push - Vec3::new(0.2, 0., 0.)).length() < EPSILON, "{push}");
+
+        // Standing on top is riding, not pushing.
+        assert_eq!(
+            lateral_push(&platform, half_extents, Vec3::new(0., 12.7, 0.), 0.3),
+            None
+        );
+        // Nowhere near it.
+        assert_eq!(
+            lateral_push(&platform, half_extents, Vec3::new(60., 10., 0.), 0.3),
+            None
+        );
     }
 }
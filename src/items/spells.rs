@@ -0,0 +1,415 @@
+use crate::{
+    camera::FirstPersonCam,
+    hitpoints::{DamageEvent, DamageSet, DamageType, Hp},
+    items::{
+        spawn_fireball, AbilityCost, Explosion, ExplosionEvent, Projectile, ProjectileModifiers,
+    },
+    mana::Mana,
+    player::PlayerMotion,
+    prelude::*,
+    progression::Talent,
+    status::{ApplyStatus, StatusEffect},
+};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::{path::Path, time::Duration};
+
+pub struct SpellsPlugin;
+
+impl Plugin for SpellsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_asset::<SpellDefinition>();
+        #[cfg(feature = "serde")]
+        app.init_asset_loader::<loader::SpellLoader>();
+        app.add_event::<CastSpell>();
+        app.add_systems(
+            Update,
+            (
+                tick_spellbooks,
+                // The editor places things with the same keys in DevMode.
+                player_cast_input.run_if(in_state(GameState::InGame)),
+                cast_spells.before(DamageSet),
+            )
+                .chain(),
+        );
+    }
+}
+
+/// The keys that pick a spellbook slot, in slot order.
+const HOTBAR_KEYS: [KeyCode; 9] = [
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::Digit4,
+    KeyCode::Digit5,
+    KeyCode::Digit6,
+    KeyCode::Digit7,
+    KeyCode::Digit8,
+    KeyCode::Digit9,
+];
+
+/// A spell, as loaded from a `.spell.ron` file in assets/spells.
+#[derive(Asset, TypePath, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SpellDefinition {
+    pub name: String,
+    pub mana_cost: u32,
+    /// Seconds before the spell can be cast again.
+    pub cooldown: f32,
+    pub effect: SpellEffect,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SpellEffect {
+    /// Throws a fireball along the caster's aim.
+    Projectile(Projectile),
+    /// Instantly hits the first thing along the caster's aim.
+    Hitscan {
+        range: f32,
+        damage: f32,
+        damage_type: DamageType,
+        /// Applied to whatever is hit.
+        #[cfg_attr(feature = "serde", serde(default))]
+        status: Option<StatusEffect>,
+    },
+    /// A blast centred on the caster, which doesn't hurt the caster.
+    AreaOfEffect(Explosion),
+    Buff(BuffSpell),
+    /// Flings the caster along their aim.
+    Movement(MovementSpell),
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BuffSpell {
+    /// Hit points restored when the buff is cast.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub heal: u32,
+    /// Statuses that the caster gets, e.g. haste or a shield.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub effects: Vec<StatusEffect>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MovementSpell {
+    pub speed: f32,
+    pub vertical_speed: f32,
+}
+
+/// The spells that an entity can cast, one per hotbar slot. The player casts the `selected` slot with the
+/// right mouse button; anything else casts by sending `CastSpell`.
+#[derive(Component, Debug, Clone, Default)]
+pub struct Spellbook {
+    pub slots: Vec<SpellSlot>,
+    pub selected: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct SpellSlot {
+    pub spell: Handle<SpellDefinition>,
+    /// A locked slot holds its place on the hotbar but can't be cast, e.g. until a talent teaches its spell.
+    pub locked: bool,
+    // Built from the definition once it has loaded.
+    cost: Option<AbilityCost>,
+}
+
+impl SpellSlot {
+    pub fn new(spell: Handle<SpellDefinition>) -> Self {
+        SpellSlot {
+            spell,
+            locked: false,
+            cost: None,
+        }
+    }
+
+    /// Whether the slot is unlocked, and its spell has loaded and is off cooldown with enough mana to cast
+    /// it.
+    pub fn ready(&self, mana: &Mana) -> bool {
+        !self.locked && self.cost.as_ref().is_some_and(|cost| cost.ready(mana))
+    }
+}
+
+impl Spellbook {
+    /// The spells that every player starts out with. Those that talents teach are there from the start,
+    /// locked, so that learning them doesn't move the spells after them to other keys.
+    pub fn starting_spells(assets: &AssetServer) -> Self {
+        let mut spellbook = Spellbook::from_files(
+            assets,
+            &[
+                "spells/fireball.spell.ron",
+                "spells/arcane_bolt.spell.ron",
+                "spells/nova.spell.ron",
+                "spells/haste.spell.ron",
+                "spells/ward.spell.ron",
+                "spells/leap.spell.ron",
+            ],
+        );
+        for path in Talent::ALL.into_iter().filter_map(Talent::spell) {
+            spellbook.set_locked(path, true);
+        }
+        spellbook
+    }
+
+    /// A slot for each of the spell files, in order.
+    #[allow(unused_variables)]
+    pub fn from_files(assets: &AssetServer, paths: &[&str]) -> Self {
+        #[cfg(feature = "serde")]
+        let slots = paths
+            .iter()
+            .map(|path| SpellSlot::new(assets.load(path.to_string())))
+            .collect();
+        // Spell definitions are only loaded from files.
+        #[cfg(not(feature = "serde"))]
+        let slots = vec![];
+        Spellbook { slots, selected: 0 }
+    }
+
+    /// Locks or unlocks the slot with the spell file at `path`. Returns false if there's no such slot.
+    pub fn set_locked(&mut self, path: &str, locked: bool) -> bool {
+        let slot = self.slots.iter_mut().find(|slot| {
+            slot.spell
+                .path()
+                .is_some_and(|known| known.path() == Path::new(path))
+        });
+        match slot {
+            Some(slot) => {
+                slot.locked = locked;
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Multiplies the mana cost of every spell in an entity's `Spellbook`.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub struct SpellCostScale(pub f32);
+
+/// Asks for `caster` to cast the spell in `slot` of its `Spellbook`, aimed from `origin` along `direction`.
+/// The cast only happens if the spell is off cooldown and the caster has the mana for it.
+#[derive(Event, Debug, Clone)]
+pub struct CastSpell {
+    pub caster: Entity,
+    pub slot: usize,
+    pub origin: Vec3,
+    pub direction: Dir3,
+}
+
+fn tick_spellbooks(
+    time: Res<Time>,
+    spells: Res<Assets<SpellDefinition>>,
+    mut spellbooks: Query<(&mut Spellbook, Option<&SpellCostScale>)>,
+) {
+    for (mut spellbook, scale) in &mut spellbooks {
+        let scale = scale.map_or(1.0, |scale| scale.0);
+        for slot in &mut spellbook.slots {
+            let Some(spell) = spells.get(&slot.spell) else {
+                continue;
+            };
+            let mana_cost = (spell.mana_cost as f32 * scale).round() as u32;
+            match &mut slot.cost {
+                Some(cost) => {
+                    cost.mana_cost = mana_cost;
+                    cost.tick(time.delta());
+                }
+                None => {
+                    slot.cost = Some(AbilityCost::new(
+                        mana_cost,
+                        Duration::from_secs_f32(spell.cooldown),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn player_cast_input(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    mut player: Query<(Entity, &mut Spellbook), With<Player>>,
+    camera: Query<&GlobalTransform, With<FirstPersonCam>>,
+    mut casts: EventWriter<CastSpell>,
+) {
+    let Ok((caster, mut spellbook)) = player.get_single_mut() else {
+        return;
+    };
+    if let Some(slot) = HOTBAR_KEYS
+        .iter()
+        .position(|key| keyboard.just_pressed(*key))
+        .filter(|slot| *slot < spellbook.slots.len())
+    {
+        spellbook.selected = slot;
+    }
+    if !mouse.pressed(MouseButton::Right) {
+        return;
+    }
+    let Ok(aim) = camera.get_single() else {
+        return warn!("Couldn't get FirstPersonCam, don't know how to aim the spell.");
+    };
+    casts.send(CastSpell {
+        caster,
+        slot: spellbook.selected,
+        origin: aim.translation(),
+        direction: aim.forward(),
+    });
+}
+
+pub(crate) fn cast_spells(
+    mut commands: Commands,
+    mut casts: EventReader<CastSpell>,
+    spells: Res<Assets<SpellDefinition>>,
+    mut casters: Query<(&mut Spellbook, &mut Mana, Option<&ProjectileModifiers>)>,
+    mut hp: Query<&mut Hp>,
+    mut motions: Query<&mut PlayerMotion>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    rapier_context: Res<RapierContext>,
+    mut damage: EventWriter<DamageEvent>,
+    mut explosions: EventWriter<ExplosionEvent>,
+    mut statuses: EventWriter<ApplyStatus>,
+) {
+    for cast in casts.read() {
+        let Ok((mut spellbook, mut mana, modifiers)) = casters.get_mut(cast.caster) else {
+            continue;
+        };
+        let Some(slot) = spellbook.slots.get_mut(cast.slot) else {
+            continue;
+        };
+        if slot.locked {
+            continue;
+        }
+        let Some(spell) = spells.get(&slot.spell) else {
+            continue;
+        };
+        // Spends the mana and puts the spell back on cooldown.
+        let Some(cost) = slot.cost.as_mut() else {
+            continue;
+        };
+        if !cost.try_activate(&mut mana) {
+            continue;
+        }
+
+        match &spell.effect {
+            SpellEffect::Projectile(projectile) => {
+                // Equipment scales the spell's projectile.
+                let projectile = modifiers.map_or_else(
+                    || projectile.clone(),
+                    |modifiers| modifiers.apply(projectile),
+                );
+                spawn_fireball(
+                    &mut commands,
+                    &mut meshes,
+                    &mut materials,
+                    cast.caster,
+                    Transform::from_translation(cast.origin).looking_to(cast.direction, Vec3::Y),
+                    &projectile,
+                );
+            }
+            SpellEffect::Hitscan {
+                range,
+                damage: amount,
+                damage_type,
+                status,
+            } => {
+                let hit = rapier_context.cast_ray(
+                    cast.origin,
+                    *cast.direction,
+                    *range,
+                    true,
+                    QueryFilter::default().exclude_collider(cast.caster),
+                );
+                if let Some((target, toi)) = hit {
+                    damage.send(DamageEvent {
+                        source: Some(cast.caster),
+                        target,
+                        amount: *amount,
+                        damage_type: *damage_type,
+                        hit_point: cast.origin + *cast.direction * toi,
+                        status: status.clone(),
+                    });
+                }
+            }
+            SpellEffect::AreaOfEffect(explosion) => {
+                explosions.send(ExplosionEvent {
+                    position: cast.origin,
+                    explosion: explosion.clone(),
+                    source: Some(cast.caster),
+                });
+            }
+            SpellEffect::Buff(buff) => {
+                if let Ok(mut hp) = hp.get_mut(cast.caster) {
+                    hp.current = (hp.current + buff.heal).min(hp.max);
+                }
+                for effect in &buff.effects {
+                    statuses.send(ApplyStatus {
+                        target: cast.caster,
+                        source: Some(cast.caster),
+                        effect: effect.clone(),
+                    });
+                }
+            }
+            SpellEffect::Movement(movement) => {
+                let Ok(mut motion) = motions.get_mut(cast.caster) else {
+                    continue;
+                };
+                let mut along = *cast.direction;
+                along.y = 0.0;
+                motion.external_velocity = along.normalize_or_zero() * movement.speed;
+                motion.vertical_speed = movement.vertical_speed;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+mod loader {
+    use super::SpellDefinition;
+    use bevy::asset::{io::Reader, AssetLoader, AsyncReadExt, LoadContext};
+
+    #[derive(Default)]
+    pub struct SpellLoader;
+
+    impl AssetLoader for SpellLoader {
+        type Asset = SpellDefinition;
+        type Settings = ();
+        type Error = anyhow::Error;
+
+        async fn load<'a>(
+            &'a self,
+            reader: &'a mut Reader<'_>,
+            _settings: &'a (),
+            _load_context: &'a mut LoadContext<'_>,
+        ) -> Result<SpellDefinition, anyhow::Error> {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            Ok(ron::de::from_bytes(&bytes)?)
+        }
+
+        fn extensions(&self) -> &[&str] {
+            &["spell.ron"]
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod test {
+    use super::*;
+
+    #[test]
+    fn spell_files_parse() {
+        let mut count = 0;
+        for entry in std::fs::read_dir("assets/spells").unwrap() {
+            let path = entry.unwrap().path();
+            let text = std::fs::read_to_string(&path).unwrap();
+            let spell: SpellDefinition = ron::from_str(&text)
+                .unwrap_or_else(|e| panic!("Couldn't parse {}: {e}", path.display()));
+            assert!(!spell.name.is_empty(), "{} has no name", path.display());
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+}
@@ -0,0 +1,748 @@
+use crate::{
+    encounters::{Encounter, EncounterDefinition, EnemySpawner, EnemySpawnerDefinition},
+    items::{
+        Easing, LaunchPad, PathMode, Pickup, Platform, PlatformPath, PlatformSettings, Waypoint,
+    },
+    leylines::{LeylinePuzzle, LeylinePuzzleDefinition},
+    missions::{Mission, MissionDefinition},
+    objects::{CheckpointVolume, Targets},
+    player::SpawnPoint,
+    respawn::RespawnSettings,
+    world::TerrainSettings,
+};
+use anyhow::Result;
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Loads a level file and spawns the entities that it places in the world, on the terrain that it names.
+/// Level files are RON, see assets/levels.
+pub struct LevelPlugin {
+    pub level_path: PathBuf,
+    /// A heightmap to build the terrain from instead of the level's, such as one named on the command line.
+    /// It's scaled as the level's would have been.
+    pub terrain: Option<PathBuf>,
+}
+
+/// The file that the level was loaded from, and that the editor saves to.
+#[derive(Resource, Debug, Clone)]
+pub struct LevelPath(pub PathBuf);
+
+#[derive(Resource, Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LevelDefinition {
+    /// The heightmap to build the terrain from. Without one, the terrain is the `WorldPlugin`'s.
+    pub terrain: Option<TerrainSettings>,
+    /// Where the player spawns.
+    pub spawn_points: Vec<SpawnPointPlacement>,
+    /// How the player picks a spawn point after dying. Without any, `RespawnSettings::default()`.
+    pub respawn: Option<RespawnSettings>,
+    pub checkpoints: Vec<CheckpointPlacement>,
+    pub launch_pads: Vec<LaunchPadPlacement>,
+    pub platforms: Vec<PlatformPlacement>,
+    /// Whether the random platform spawner runs alongside the level's own platforms.
+    pub random_platforms: bool,
+    pub encounters: Vec<EncounterDefinition>,
+    pub puzzles: Vec<LeylinePuzzleDefinition>,
+    pub targets: Vec<TargetsPlacement>,
+    pub pickups: Vec<PickupPlacement>,
+    pub enemy_spawners: Vec<EnemySpawnerDefinition>,
+    /// Lights besides the sun.
+    pub lights: Vec<LightPlacement>,
+    pub props: Vec<PropPlacement>,
+    pub mission: Option<MissionDefinition>,
+}
+
+impl Default for LevelDefinition {
+    fn default() -> Self {
+        LevelDefinition {
+            terrain: None,
+            spawn_points: vec![],
+            respawn: None,
+            checkpoints: vec![],
+            launch_pads: vec![],
+            platforms: vec![],
+            random_platforms: true,
+            encounters: vec![],
+            puzzles: vec![],
+            targets: vec![],
+            pickups: vec![],
+            enemy_spawners: vec![],
+            lights: vec![],
+            props: vec![],
+            mission: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
+pub struct SpawnPointPlacement {
+    pub position: Vec3,
+    /// Degrees to turn left from facing -Z.
+    #[serde(default)]
+    pub yaw: f32,
+}
+
+/// A box that the player respawns in once they've walked into it, with `SpawnPolicy::Checkpoint`.
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
+pub struct CheckpointPlacement {
+    /// What saves know the checkpoint by. Each checkpoint in a level needs its own.
+    pub name: String,
+    pub position: Vec3,
+    /// Degrees to turn left from facing -Z.
+    #[serde(default)]
+    pub yaw: f32,
+    #[serde(default = "checkpoint_half_extents")]
+    pub half_extents: Vec3,
+}
+
+fn checkpoint_half_extents() -> Vec3 {
+    CheckpointVolume::default().half_extents
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
+pub struct LaunchPadPlacement {
+    pub position: Vec3,
+    pub impulse: Vec3,
+}
+
+/// A platform that follows `waypoints`, starting at the first one. A platform with a single waypoint stays put.
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
+pub struct PlatformPlacement {
+    /// Width, thickness and depth of the platform's box.
+    pub size: Vec3,
+    pub waypoints: Vec<Waypoint>,
+    #[serde(default)]
+    pub speed: f32,
+    #[serde(default)]
+    pub easing: Easing,
+    #[serde(default)]
+    pub mode: PathMode,
+    /// Spin about the up axis, in radians per second.
+    #[serde(default)]
+    pub yaw_speed: f32,
+}
+
+/// A group of targets, in a box centred on `position`.
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
+pub struct TargetsPlacement {
+    pub position: Vec3,
+    #[serde(default)]
+    pub targets: Targets,
+}
+
+/// Items lying about for the player to pick up.
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
+pub struct PickupPlacement {
+    /// The item's asset path, e.g. "items/health_potion.item.ron".
+    pub item: String,
+    #[serde(default = "one")]
+    pub count: u32,
+    pub position: Vec3,
+}
+
+fn one() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
+pub enum LightPlacement {
+    Point {
+        position: Vec3,
+        #[serde(default = "white")]
+        color: [f32; 3],
+        /// Lumens.
+        intensity: f32,
+        range: f32,
+        #[serde(default)]
+        shadows: bool,
+    },
+    Spot {
+        position: Vec3,
+        looking_at: Vec3,
+        #[serde(default = "white")]
+        color: [f32; 3],
+        /// Lumens.
+        intensity: f32,
+        range: f32,
+        /// Degrees from the middle of the beam to its edge.
+        angle: f32,
+        #[serde(default)]
+        shadows: bool,
+    },
+}
+
+fn white() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+/// A model placed in the world. glTF files are spawned as scenes; anything else, such as an STL, is taken to
+/// be a single mesh and drawn in `color`.
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
+pub struct PropPlacement {
+    /// The model's asset path.
+    pub model: String,
+    pub position: Vec3,
+    /// Degrees about the X, Y and Z axes, applied in that order.
+    #[serde(default)]
+    pub rotation: Vec3,
+    #[serde(default = "unscaled")]
+    pub scale: Vec3,
+    #[serde(default = "white")]
+    pub color: [f32; 3],
+    /// Without a collider, things pass through the prop.
+    #[serde(default)]
+    pub collider: Option<PropCollider>,
+}
+
+fn unscaled() -> Vec3 {
+    Vec3::ONE
+}
+
+/// A prop's collision shape, in its local space before scaling.
+#[derive(Debug, Clone, Serialize, Deserialize, Reflect)]
+pub enum PropCollider {
+    Cuboid { half_extents: Vec3 },
+    Ball { radius: f32 },
+}
+
+impl PropPlacement {
+    fn transform(&self) -> Transform {
+        let [x, y, z] = self.rotation.to_array().map(f32::to_radians);
+        Transform {
+            translation: self.position,
+            rotation: Quat::from_euler(EulerRot::XYZ, x, y, z),
+            scale: self.scale,
+        }
+    }
+
+    fn is_scene(&self) -> bool {
+        let path = self.model.split('#').next().unwrap_or_default();
+        path.ends_with(".gltf") || path.ends_with(".glb")
+    }
+}
+
+/// One of the things in a level that stands somewhere, and that the editor can move about.
+#[derive(Debug, Clone, Reflect)]
+pub enum Placement {
+    SpawnPoint(SpawnPointPlacement),
+    Checkpoint(CheckpointPlacement),
+    LaunchPad(LaunchPadPlacement),
+    Platform(PlatformPlacement),
+    Targets(TargetsPlacement),
+    Pickup(PickupPlacement),
+    EnemySpawner(EnemySpawnerDefinition),
+    Light(LightPlacement),
+    Prop(PropPlacement),
+}
+
+/// Tags an entity with the placement that it was spawned from. `order` keeps the level file's order when
+/// it's saved again, which matters for things like the first spawn point. The editor's inspector edits the
+/// placement, and the entity is spawned again from it.
+#[derive(Component, Debug, Clone, Reflect)]
+#[reflect(Component)]
+pub struct Placed {
+    pub placement: Placement,
+    pub order: usize,
+}
+
+impl Placement {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Placement::SpawnPoint(_) => "spawn point",
+            Placement::Checkpoint(_) => "checkpoint",
+            Placement::LaunchPad(_) => "launch pad",
+            Placement::Platform(_) => "platform",
+            Placement::Targets(_) => "targets",
+            Placement::Pickup(_) => "pickup",
+            Placement::EnemySpawner(_) => "enemy spawner",
+            Placement::Light(_) => "light",
+            Placement::Prop(_) => "prop",
+        }
+    }
+
+    /// Where the entity goes. A platform starts at its first waypoint.
+    pub fn transform(&self) -> Transform {
+        match self {
+            Placement::SpawnPoint(spawn_point) => Transform::from_translation(spawn_point.position)
+                .with_rotation(Quat::from_rotation_y(spawn_point.yaw.to_radians())),
+            Placement::Checkpoint(checkpoint) => Transform::from_translation(checkpoint.position)
+                .with_rotation(Quat::from_rotation_y(checkpoint.yaw.to_radians())),
+            Placement::LaunchPad(pad) => Transform::from_translation(pad.position),
+            Placement::Platform(platform) => Transform::from_translation(
+                platform
+                    .waypoints
+                    .first()
+                    .map(|waypoint| waypoint.position)
+                    .unwrap_or_default(),
+            ),
+            Placement::Targets(targets) => Transform::from_translation(targets.position),
+            Placement::Pickup(pickup) => Transform::from_translation(pickup.position),
+            Placement::EnemySpawner(spawner) => Transform::from_translation(spawner.position),
+            Placement::Light(LightPlacement::Point { position, .. }) => {
+                Transform::from_translation(*position)
+            }
+            Placement::Light(LightPlacement::Spot {
+                position,
+                looking_at,
+                ..
+            }) => Transform::from_translation(*position).looking_at(*looking_at, Vec3::Y),
+            Placement::Prop(prop) => prop.transform(),
+        }
+    }
+
+    /// Moves the placement to `transform`, keeping whatever about it a Transform doesn't cover. A platform's
+    /// whole path moves with it.
+    pub fn set_transform(&mut self, transform: &Transform) {
+        let translation = transform.translation;
+        match self {
+            Placement::SpawnPoint(spawn_point) => {
+                let (yaw, _, _) = transform.rotation.to_euler(EulerRot::YXZ);
+                spawn_point.position = translation;
+                spawn_point.yaw = yaw.to_degrees();
+            }
+            Placement::Checkpoint(checkpoint) => {
+                let (yaw, _, _) = transform.rotation.to_euler(EulerRot::YXZ);
+                checkpoint.position = translation;
+                checkpoint.yaw = yaw.to_degrees();
+            }
+            Placement::LaunchPad(pad) => pad.position = translation,
+            Placement::Platform(platform) => {
+                let Some(start) = platform.waypoints.first() else {
+                    return;
+                };
+                let offset = translation - start.position;
+                for waypoint in &mut platform.waypoints {
+                    waypoint.position += offset;
+                }
+            }
+            Placement::Targets(targets) => targets.position = translation,
+            Placement::Pickup(pickup) => pickup.position = translation,
+            Placement::EnemySpawner(spawner) => spawner.position = translation,
+            Placement::Light(LightPlacement::Point { position, .. }) => *position = translation,
+            Placement::Light(LightPlacement::Spot {
+                position,
+                looking_at,
+                ..
+            }) => {
+                let distance = position.distance(*looking_at).max(1.0);
+                *position = translation;
+                *looking_at = translation + transform.forward() * distance;
+            }
+            Placement::Prop(prop) => {
+                let (x, y, z) = transform.rotation.to_euler(EulerRot::XYZ);
+                prop.position = translation;
+                prop.rotation = Vec3::new(x.to_degrees(), y.to_degrees(), z.to_degrees());
+                prop.scale = transform.scale;
+            }
+        }
+    }
+
+    /// Roughly how far the placement reaches from its position, for picking it out with the cursor.
+    pub fn radius(&self) -> f32 {
+        match self {
+            Placement::Platform(platform) => platform.size.max_element() / 2.0,
+            Placement::Checkpoint(checkpoint) => checkpoint.half_extents.max_element(),
+            Placement::Targets(targets) => targets.targets.extent().max_element() / 2.0,
+            Placement::Prop(prop) => prop.scale.max_element() * 2.0,
+            Placement::LaunchPad(_) | Placement::EnemySpawner(_) => 2.0,
+            Placement::SpawnPoint(_) | Placement::Light(_) | Placement::Pickup(_) => 1.0,
+        }
+    }
+}
+
+impl LevelDefinition {
+    /// Everything that the level places, in the order that it's written in.
+    pub fn placements(&self) -> Vec<Placement> {
+        let platforms = self.platforms.iter().filter(|platform| {
+            if platform.waypoints.is_empty() {
+                warn!("Skipping a platform with no waypoints.");
+            }
+            !platform.waypoints.is_empty()
+        });
+        self.spawn_points
+            .iter()
+            .cloned()
+            .map(Placement::SpawnPoint)
+            .chain(self.checkpoints.iter().cloned().map(Placement::Checkpoint))
+            .chain(self.launch_pads.iter().cloned().map(Placement::LaunchPad))
+            .chain(platforms.cloned().map(Placement::Platform))
+            .chain(self.targets.iter().cloned().map(Placement::Targets))
+            .chain(self.pickups.iter().cloned().map(Placement::Pickup))
+            .chain(
+                self.enemy_spawners
+                    .iter()
+                    .cloned()
+                    .map(Placement::EnemySpawner),
+            )
+            .chain(self.lights.iter().cloned().map(Placement::Light))
+            .chain(self.props.iter().cloned().map(Placement::Prop))
+            .collect()
+    }
+
+    /// This level, with `placements` in place of whatever it placed before.
+    pub fn with_placements(&self, placements: impl IntoIterator<Item = Placement>) -> Self {
+        let mut level = LevelDefinition {
+            spawn_points: vec![],
+            checkpoints: vec![],
+            launch_pads: vec![],
+            platforms: vec![],
+            targets: vec![],
+            pickups: vec![],
+            enemy_spawners: vec![],
+            lights: vec![],
+            props: vec![],
+            ..self.clone()
+        };
+        for placement in placements {
+            match placement {
+                Placement::SpawnPoint(spawn_point) => level.spawn_points.push(spawn_point),
+                Placement::Checkpoint(checkpoint) => level.checkpoints.push(checkpoint),
+                Placement::LaunchPad(pad) => level.launch_pads.push(pad),
+                Placement::Platform(platform) => level.platforms.push(platform),
+                Placement::Targets(targets) => level.targets.push(targets),
+                Placement::Pickup(pickup) => level.pickups.push(pickup),
+                Placement::EnemySpawner(spawner) => level.enemy_spawners.push(spawner),
+                Placement::Light(light) => level.lights.push(light),
+                Placement::Prop(prop) => level.props.push(prop),
+            }
+        }
+        level
+    }
+}
+
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut App) {
+        let level = match load_level(&self.level_path) {
+            Ok(level) => {
+                info!("Loaded level from {:?}", self.level_path);
+                level
+            }
+            Err(e) => {
+                warn!("Couldn't load level {:?}: {e}", self.level_path);
+                LevelDefinition::default()
+            }
+        };
+        app.insert_resource(PlatformSettings {
+            random_spawner: level.random_platforms,
+        });
+        match (&self.terrain, &level.terrain) {
+            (Some(heightmap), terrain) => {
+                app.insert_resource(TerrainSettings {
+                    heightmap: heightmap.clone(),
+                    ..terrain.clone().unwrap_or_default()
+                });
+            }
+            (None, Some(terrain)) => {
+                app.insert_resource(terrain.clone());
+            }
+            // The WorldPlugin's stands.
+            (None, None) => {}
+        }
+        if let Some(respawn) = &level.respawn {
+            app.insert_resource(respawn.clone());
+        }
+        if let Some(mission) = &level.mission {
+            app.insert_resource(Mission::new(mission.clone()));
+        }
+        app.insert_resource(level);
+        app.insert_resource(LevelPath(self.level_path.clone()));
+        app.add_systems(Startup, spawn_level);
+    }
+}
+
+pub fn load_level<P: AsRef<Path>>(path: P) -> Result<LevelDefinition> {
+    let text = std::fs::read_to_string(path)?;
+    Ok(ron::from_str(&text)?)
+}
+
+/// Writes `level` out as RON. Comments in the file that it replaces are lost.
+pub fn save_level<P: AsRef<Path>>(path: P, level: &LevelDefinition) -> Result<()> {
+    let text = ron::ser::to_string_pretty(level, ron::ser::PrettyConfig::default())?;
+    std::fs::write(path, text)?;
+    Ok(())
+}
+
+fn spawn_level(
+    mut commands: Commands,
+    level: Res<LevelDefinition>,
+    assets: Res<AssetServer>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (order, placement) in level.placements().into_iter().enumerate() {
+        spawn_placed(
+            &mut commands,
+            &assets,
+            &mut materials,
+            Placed { placement, order },
+        );
+    }
+    // Numbered, so that saves can tell them apart.
+    for (index, encounter) in level.encounters.iter().enumerate() {
+        commands.spawn((
+            Name::new(format!("encounter {index}")),
+            Encounter::new(encounter.clone()),
+        ));
+    }
+    for definition in &level.puzzles {
+        let puzzle = commands
+            .spawn((
+                Name::new(format!("puzzle: {}", definition.name)),
+                LeylinePuzzle {
+                    name: definition.name.clone(),
+                    solved: false,
+                },
+            ))
+            .id();
+        for stone in &definition.stones {
+            if stone.positions.is_empty() {
+                warn!(
+                    "Skipping a leystone with no positions in {:?}.",
+                    definition.name
+                );
+                continue;
+            }
+            commands.spawn(stone.leystone(puzzle));
+        }
+    }
+}
+
+/// Spawns what `placed` describes, tagged with it so that the editor can find it again.
+pub fn spawn_placed(
+    commands: &mut Commands,
+    assets: &AssetServer,
+    materials: &mut Assets<StandardMaterial>,
+    placed: Placed,
+) -> Entity {
+    let transform = placed.placement.transform();
+    let mut entity = match &placed.placement {
+        Placement::SpawnPoint(_) => {
+            commands.spawn((Name::new("spawn_point"), SpawnPoint, transform))
+        }
+        Placement::Checkpoint(checkpoint) => commands.spawn((
+            CheckpointVolume {
+                name: checkpoint.name.clone(),
+                half_extents: checkpoint.half_extents,
+            },
+            transform,
+        )),
+        Placement::LaunchPad(pad) => commands.spawn((
+            LaunchPad {
+                impulse: pad.impulse,
+            },
+            transform,
+        )),
+        Placement::Platform(platform) => commands.spawn((
+            Platform {
+                angvel: Vec3::Y * platform.yaw_speed,
+                half_extents: platform.size / 2.0,
+                ..default()
+            },
+            PlatformPath::new(
+                platform.waypoints.clone(),
+                platform.speed,
+                platform.easing,
+                platform.mode,
+            ),
+            transform,
+        )),
+        Placement::Targets(targets) => commands.spawn((targets.targets.clone(), transform)),
+        Placement::Pickup(pickup) => commands.spawn((
+            Pickup {
+                item: assets.load(&pickup.item),
+                count: pickup.count,
+            },
+            transform,
+        )),
+        Placement::EnemySpawner(spawner) => commands.spawn((
+            Name::new(format!("enemy_spawner: {:?}", spawner.kind)),
+            EnemySpawner::new(spawner.clone()),
+            transform,
+        )),
+        Placement::Light(LightPlacement::Point {
+            color,
+            intensity,
+            range,
+            shadows,
+            ..
+        }) => commands.spawn((
+            Name::new("point_light"),
+            PointLightBundle {
+                point_light: PointLight {
+                    color: Color::srgb(color[0], color[1], color[2]),
+                    intensity: *intensity,
+                    range: *range,
+                    shadows_enabled: *shadows,
+                    ..default()
+                },
+                transform,
+                ..default()
+            },
+        )),
+        Placement::Light(LightPlacement::Spot {
+            color,
+            intensity,
+            range,
+            angle,
+            shadows,
+            ..
+        }) => commands.spawn((
+            Name::new("spot_light"),
+            SpotLightBundle {
+                spot_light: SpotLight {
+                    color: Color::srgb(color[0], color[1], color[2]),
+                    intensity: *intensity,
+                    range: *range,
+                    outer_angle: angle.to_radians(),
+                    inner_angle: angle.to_radians() * 0.8,
+                    shadows_enabled: *shadows,
+                    ..default()
+                },
+                transform,
+                ..default()
+            },
+        )),
+        Placement::Prop(prop) => {
+            let mut entity = if prop.is_scene() {
+                let path = if prop.model.contains('#') {
+                    prop.model.clone()
+                } else {
+                    format!("{}#Scene0", prop.model)
+                };
+                commands.spawn(SceneBundle {
+                    scene: assets.load(path),
+                    transform,
+                    ..default()
+                })
+            } else {
+                let [r, g, b] = prop.color;
+                commands.spawn(PbrBundle {
+                    mesh: assets.load(prop.model.clone()),
+                    material: materials.add(Color::srgb(r, g, b)),
+                    transform,
+                    ..default()
+                })
+            };
+            entity.insert(Name::new(format!("prop: {}", prop.model)));
+            match prop.collider {
+                Some(PropCollider::Cuboid { half_extents }) => {
+                    entity.insert((
+                        RigidBody::Fixed,
+                        Collider::cuboid(half_extents.x, half_extents.y, half_extents.z),
+                    ));
+                }
+                Some(PropCollider::Ball { radius }) => {
+                    entity.insert((RigidBody::Fixed, Collider::ball(radius)));
+                }
+                None => {}
+            }
+            entity
+        }
+    };
+    entity.insert(placed);
+    entity.id()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn level_files_parse() {
+        for entry in std::fs::read_dir("assets/levels").unwrap() {
+            let path = entry.unwrap().path();
+            if let Err(e) = load_level(&path) {
+                panic!("Couldn't parse {}: {e}", path.display());
+            }
+        }
+    }
+
+    #[test]
+    fn saved_levels_load_back_the_same() {
+        let level = load_level("assets/levels/canyon.ron").unwrap();
+        let resaved = level.with_placements(level.placements());
+        let path = std::env::temp_dir().join(format!("level_test_{}.ron", std::process::id()));
+        save_level(&path, &resaved).unwrap();
+        let loaded = load_level(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let text = |level: &LevelDefinition| ron::to_string(level).unwrap();
+        assert_eq!(text(&loaded), text(&level));
+    }
+
+    #[test]
+    fn a_terrain_from_the_command_line_beats_the_levels() {
+        let terrain = |override_path: Option<&str>| {
+            let mut app = App::new();
+            app.add_plugins(LevelPlugin {
+                level_path: "assets/levels/canyon.ron".into(),
+                terrain: override_path.map(PathBuf::from),
+            });
+            app.world().resource::<TerrainSettings>().clone()
+        };
+        let level = terrain(None);
+        assert_eq!(
+            level.heightmap,
+            PathBuf::from("assets/grand_canyon_small_heightmap.png")
+        );
+        let chosen = terrain(Some("assets/36_377_-112_445_11_8129_8129.png"));
+        assert_eq!(
+            chosen.heightmap,
+            PathBuf::from("assets/36_377_-112_445_11_8129_8129.png")
+        );
+        assert_eq!(chosen.scale, level.scale);
+    }
+
+    #[test]
+    fn moving_a_platform_moves_its_path() {
+        let mut placement = Placement::Platform(PlatformPlacement {
+            size: Vec3::ONE,
+            waypoints: vec![
+                Waypoint {
+                    position: Vec3::ZERO,
+                    wait: 0.0,
+                },
+                Waypoint {
+                    position: Vec3::new(10.0, 0.0, 0.0),
+                    wait: 0.0,
+                },
+            ],
+            speed: 1.0,
+            easing: Easing::Linear,
+            mode: PathMode::Loop,
+            yaw_speed: 0.0,
+        });
+        placement.set_transform(&Transform::from_xyz(0.0, 5.0, 1.0));
+        let Placement::Platform(platform) = &placement else {
+            unreachable!();
+        };
+        let path: Vec<Vec3> = platform.waypoints.iter().map(|w| w.position).collect();
+        assert_eq!(
+            path,
+            vec![Vec3::new(0.0, 5.0, 1.0), Vec3::new(10.0, 5.0, 1.0)]
+        );
+    }
+
+    #[test]
+    fn props_keep_their_transform() {
+        let mut placement = Placement::Prop(PropPlacement {
+            model: "torus1.stl".into(),
+            position: Vec3::ZERO,
+            rotation: Vec3::ZERO,
+            scale: Vec3::ONE,
+            color: [1.0; 3],
+            collider: None,
+        });
+        let transform = Transform {
+            translation: Vec3::new(1.0, 2.0, 3.0),
+            rotation: Quat::from_euler(EulerRot::XYZ, 0.5, 0.25, -1.0),
+            scale: Vec3::splat(2.0),
+        };
+        placement.set_transform(&transform);
+        let round_trip = placement.transform();
+        assert_eq!(round_trip.translation, transform.translation);
+        assert_eq!(round_trip.scale, transform.scale);
+        assert!(round_trip.rotation.angle_between(transform.rotation) < 1e-4);
+    }
+}
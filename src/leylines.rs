@@ -0,0 +1,560 @@
+use crate::{camera::FirstPersonCam, prelude::*};
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_rapier3d::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// Leystones, which the player turns and moves until magicka flows from the sources to every sink of their
+/// puzzle. Energy travels in beams between stones that face each other; see `compute_flow`.
+pub struct LeylinePlugin;
+
+impl Plugin for LeylinePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PuzzleSolved>();
+        app.add_systems(Startup, load_leyline_assets);
+        app.add_systems(PreUpdate, load_leystones);
+        app.add_systems(
+            Update,
+            (interact_with_leystones, place_leystones, update_leylines).chain(),
+        );
+    }
+}
+
+const STONE_HALF_EXTENTS: Vec3 = Vec3::new(0.5, 1.5, 0.5);
+/// How far from the middle of a beam a stone can be and still catch it.
+const STONE_CATCH_RADIUS: f32 = 0.6;
+// Beams run between the tops of the stones.
+const BEAM_HEIGHT: f32 = 1.0;
+const BEAM_RADIUS: f32 = 0.08;
+// How far away the player can turn or move a stone from.
+const INTERACT_RANGE: f32 = 6.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum LeystoneKind {
+    /// Always energized.
+    Source,
+    /// Passes on the energy it receives along its own facing.
+    #[default]
+    Relay,
+    /// Takes in energy, and doesn't pass it on. A puzzle is solved once all of its sinks are energized.
+    Sink,
+}
+
+/// A standing stone that sends energy along the way it faces. The player turns it through `orientations`
+/// with E and moves it through `positions` with R, until its puzzle is solved.
+#[derive(Component, Debug, Clone)]
+pub struct Leystone {
+    pub kind: LeystoneKind,
+    /// Yaw angles, in degrees, that the stone can face. At 0 it faces -Z. Empty for a stone that can't turn.
+    pub orientations: Vec<f32>,
+    pub orientation: usize,
+    /// Where the foot of the stone can stand. Empty for a stone that stays where it was spawned.
+    pub positions: Vec<Vec3>,
+    pub position: usize,
+    /// How far the stone's beam reaches.
+    pub range: f32,
+    pub puzzle: Option<Entity>,
+}
+
+impl Default for Leystone {
+    fn default() -> Self {
+        Leystone {
+            kind: LeystoneKind::default(),
+            orientations: vec![],
+            orientation: 0,
+            positions: vec![],
+            position: 0,
+            range: 40.,
+            puzzle: None,
+        }
+    }
+}
+
+impl Leystone {
+    pub fn yaw(&self) -> f32 {
+        self.orientations
+            .get(self.orientation)
+            .copied()
+            .unwrap_or_default()
+            .to_radians()
+    }
+
+    /// Turns and moves the stone back to where its puzzle starts it.
+    pub fn reset(&mut self) {
+        self.orientation = 0;
+        self.position = 0;
+    }
+
+    /// Whether the player can do anything with the stone.
+    pub fn adjustable(&self) -> bool {
+        self.orientations.len() > 1 || self.positions.len() > 1
+    }
+}
+
+/// Marks leystones that energy is flowing into.
+#[derive(Component, Debug, Default)]
+pub struct Energized;
+
+/// The stones that share a `puzzle` make up one leyline network. Solved puzzles are locked.
+#[derive(Component, Debug, Clone, Default)]
+pub struct LeylinePuzzle {
+    pub name: String,
+    pub solved: bool,
+}
+
+/// Every sink of the puzzle is energized, so the mission that it belongs to is complete.
+#[derive(Event, Debug, Clone)]
+pub struct PuzzleSolved {
+    pub puzzle: Entity,
+    pub name: String,
+}
+
+/// A puzzle, as laid out in a level file.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LeylinePuzzleDefinition {
+    pub name: String,
+    pub stones: Vec<LeystoneDefinition>,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LeystoneDefinition {
+    pub kind: LeystoneKind,
+    /// Where the foot of the stone can stand; it starts at the first.
+    pub positions: Vec<Vec3>,
+    /// Yaw angles in degrees; it starts facing the first.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub orientations: Vec<f32>,
+}
+
+impl LeystoneDefinition {
+    pub fn leystone(&self, puzzle: Entity) -> Leystone {
+        Leystone {
+            kind: self.kind,
+            orientations: self.orientations.clone(),
+            positions: self.positions.clone(),
+            puzzle: Some(puzzle),
+            ..default()
+        }
+    }
+}
+
+/// A leystone as far as energy is concerned. `position` is where its beam starts and ends.
+#[derive(Debug, Clone, Copy)]
+pub struct LeyNode {
+    pub kind: LeystoneKind,
+    pub position: Vec3,
+    /// Horizontal, and normalized.
+    pub facing: Vec3,
+    pub range: f32,
+}
+
+/// The energy flowing through a network of leystones, indexed like the nodes it was computed from.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LeyFlow {
+    pub energized: Vec<bool>,
+    /// Beams that carry energy, as (from, to) pairs.
+    pub beams: Vec<(usize, usize)>,
+}
+
+/// Energy starts at the sources. Each energized stone that isn't a sink sends a beam along its facing to the
+/// nearest stone in line with it, seen from above, and within its range. `blocked` says whether anything
+/// besides leystones is in the way of a beam between two points.
+pub fn compute_flow(nodes: &[LeyNode], blocked: impl Fn(Vec3, Vec3) -> bool) -> LeyFlow {
+    let mut energized = vec![false; nodes.len()];
+    let mut queue: VecDeque<usize> = (0..nodes.len())
+        .filter(|i| nodes[*i].kind == LeystoneKind::Source)
+        .collect();
+    for i in &queue {
+        energized[*i] = true;
+    }
+    let mut beams = vec![];
+    while let Some(from) = queue.pop_front() {
+        if nodes[from].kind == LeystoneKind::Sink {
+            continue;
+        }
+        let Some(to) = beam_target(nodes, from) else {
+            continue;
+        };
+        if blocked(nodes[from].position, nodes[to].position) {
+            continue;
+        }
+        beams.push((from, to));
+        if !energized[to] {
+            energized[to] = true;
+            queue.push_back(to);
+        }
+    }
+    LeyFlow { energized, beams }
+}
+
+fn beam_target(nodes: &[LeyNode], from: usize) -> Option<usize> {
+    let source = nodes[from];
+    let facing = source.facing.xz();
+    nodes
+        .iter()
+        .enumerate()
+        .filter(|(to, _)| *to != from)
+        .filter_map(|(to, node)| {
+            let offset = (node.position - source.position).xz();
+            let along = offset.dot(facing);
+            let across = (offset - facing * along).length();
+            (along > 0.0 && along <= source.range && across <= STONE_CATCH_RADIUS)
+                .then_some((to, along))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(to, _)| to)
+}
+
+#[derive(Resource)]
+struct LeylineAssets {
+    stone_mesh: Handle<Mesh>,
+    eye_mesh: Handle<Mesh>,
+    beam_mesh: Handle<Mesh>,
+    beam_material: Handle<StandardMaterial>,
+    dormant: HashMap<LeystoneKind, Handle<StandardMaterial>>,
+    energized: HashMap<LeystoneKind, Handle<StandardMaterial>>,
+}
+
+// A beam of energy between two stones. Kept stretched between them by `update_leylines`.
+#[derive(Component)]
+struct LeyBeam {
+    from: Entity,
+    to: Entity,
+}
+
+fn load_leyline_assets(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    let mut dormant = HashMap::new();
+    let mut energized = HashMap::new();
+    for (kind, color) in [
+        (LeystoneKind::Source, Palette::Yellow),
+        (LeystoneKind::Relay, Palette::Blue),
+        (LeystoneKind::Sink, Palette::Red),
+    ] {
+        let color = color.to_color();
+        dormant.insert(
+            kind,
+            materials.add(StandardMaterial {
+                base_color: color.darker(0.3),
+                perceptual_roughness: 0.9,
+                ..default()
+            }),
+        );
+        energized.insert(
+            kind,
+            materials.add(StandardMaterial {
+                base_color: color,
+                emissive: LinearRgba::from(color) * 4.0,
+                ..default()
+            }),
+        );
+    }
+    commands.insert_resource(LeylineAssets {
+        stone_mesh: meshes.add(Cuboid::from_size(STONE_HALF_EXTENTS * 2.0)),
+        eye_mesh: meshes.add(Sphere::new(0.15)),
+        beam_mesh: meshes.add(Cylinder::new(BEAM_RADIUS, 1.0)),
+        beam_material: materials.add(StandardMaterial {
+            base_color: Palette::Blue.to_color(),
+            emissive: LinearRgba::rgb(1.0, 6.0, 8.0),
+            unlit: true,
+            ..default()
+        }),
+        dormant,
+        energized,
+    });
+}
+
+fn load_leystones(
+    mut commands: Commands,
+    stones: Query<(Entity, &Leystone, Option<&Transform>), Added<Leystone>>,
+    assets: Res<LeylineAssets>,
+) {
+    for (entity, stone, transform) in &stones {
+        commands
+            .entity(entity)
+            .insert((
+                Name::new("leystone"),
+                PbrBundle {
+                    mesh: assets.stone_mesh.clone(),
+                    material: assets.dormant[&stone.kind].clone(),
+                    // `place_leystones` puts it in place.
+                    transform: transform.copied().unwrap_or_default(),
+                    ..default()
+                },
+                RigidBody::Fixed,
+                Collider::cuboid(
+                    STONE_HALF_EXTENTS.x,
+                    STONE_HALF_EXTENTS.y,
+                    STONE_HALF_EXTENTS.z,
+                ),
+            ))
+            .with_children(|stone| {
+                // Shows which way the stone faces.
+                stone.spawn(PbrBundle {
+                    mesh: assets.eye_mesh.clone(),
+                    material: assets.beam_material.clone(),
+                    transform: Transform::from_xyz(0.0, BEAM_HEIGHT, -STONE_HALF_EXTENTS.z),
+                    ..default()
+                });
+            });
+    }
+}
+
+fn interact_with_leystones(
+    state: Res<State<GameState>>,
+    keyboard: Res<ButtonInput<KeyCode>>,
+    player: Query<Entity, With<Player>>,
+    camera: Query<&GlobalTransform, With<FirstPersonCam>>,
+    rapier_context: Res<RapierContext>,
+    mut stones: Query<&mut Leystone>,
+    puzzles: Query<&LeylinePuzzle>,
+) {
+    let turn = keyboard.just_pressed(KeyCode::KeyE);
+    let slide = keyboard.just_pressed(KeyCode::KeyR);
+    if *state.get() != GameState::InGame || !(turn || slide) {
+        return;
+    }
+    let (Ok(player), Ok(aim)) = (player.get_single(), camera.get_single()) else {
+        return;
+    };
+    let Some((target, _)) = rapier_context.cast_ray(
+        aim.translation(),
+        *aim.forward(),
+        INTERACT_RANGE,
+        true,
+        QueryFilter::default().exclude_collider(player),
+    ) else {
+        return;
+    };
+    let Ok(mut stone) = stones.get_mut(target) else {
+        return;
+    };
+    let locked = stone
+        .puzzle
+        .and_then(|puzzle| puzzles.get(puzzle).ok())
+        .is_some_and(|puzzle| puzzle.solved);
+    if locked {
+        return;
+    }
+    if turn && stone.orientations.len() > 1 {
+        stone.orientation = (stone.orientation + 1) % stone.orientations.len();
+    }
+    if slide && stone.positions.len() > 1 {
+        stone.position = (stone.position + 1) % stone.positions.len();
+    }
+}
+
+fn place_leystones(mut stones: Query<(&Leystone, &mut Transform), Changed<Leystone>>) {
+    for (stone, mut transform) in &mut stones {
+        if let Some(foot) = stone.positions.get(stone.position) {
+            transform.translation = *foot + Vec3::Y * STONE_HALF_EXTENTS.y;
+        }
+        transform.rotation = Quat::from_rotation_y(stone.yaw());
+    }
+}
+
+fn update_leylines(
+    mut commands: Commands,
+    rapier_context: Res<RapierContext>,
+    assets: Res<LeylineAssets>,
+    mut stones: Query<
+        (
+            Entity,
+            &Leystone,
+            &Transform,
+            Has<Energized>,
+            &mut Handle<StandardMaterial>,
+        ),
+        Without<LeyBeam>,
+    >,
+    mut beams: Query<(Entity, &LeyBeam, &mut Transform), Without<Leystone>>,
+    mut puzzles: Query<(Entity, &mut LeylinePuzzle)>,
+    mut solved: EventWriter<PuzzleSolved>,
+) {
+    let mut networks: HashMap<Option<Entity>, Vec<(Entity, LeyNode)>> = HashMap::new();
+    for (entity, stone, transform, ..) in &stones {
+        networks.entry(stone.puzzle).or_default().push((
+            entity,
+            LeyNode {
+                kind: stone.kind,
+                position: transform.translation + Vec3::Y * BEAM_HEIGHT,
+                facing: Quat::from_rotation_y(stone.yaw()) * Vec3::NEG_Z,
+                range: stone.range,
+            },
+        ));
+    }
+    let all_stones: HashSet<Entity> = stones.iter().map(|(entity, ..)| entity).collect();
+    let blocked = |from: Vec3, to: Vec3| {
+        let offset = to - from;
+        rapier_context
+            .cast_ray(
+                from,
+                offset.normalize_or_zero(),
+                offset.length(),
+                true,
+                QueryFilter::only_fixed().predicate(&|entity| !all_stones.contains(&entity)),
+            )
+            .is_some()
+    };
+
+    let mut connections = HashSet::new();
+    for (puzzle, network) in &networks {
+        let nodes: Vec<LeyNode> = network.iter().map(|(_, node)| *node).collect();
+        let flow = compute_flow(&nodes, blocked);
+        for (from, to) in flow.beams {
+            connections.insert((network[from].0, network[to].0));
+        }
+        for ((entity, node), energized) in network.iter().zip(&flow.energized) {
+            let Ok((_, _, _, was_energized, mut material)) = stones.get_mut(*entity) else {
+                continue;
+            };
+            if *energized == was_energized {
+                continue;
+            }
+            *material = if *energized {
+                commands.entity(*entity).insert(Energized);
+                assets.energized[&node.kind].clone()
+            } else {
+                commands.entity(*entity).remove::<Energized>();
+                assets.dormant[&node.kind].clone()
+            };
+        }
+
+        let Some(puzzle) = puzzle else {
+            continue;
+        };
+        let Ok((_, mut state)) = puzzles.get_mut(*puzzle) else {
+            continue;
+        };
+        let mut sinks = network
+            .iter()
+            .zip(&flow.energized)
+            .filter(|((_, node), _)| node.kind == LeystoneKind::Sink)
+            .peekable();
+        let complete = sinks.peek().is_some() && sinks.all(|(_, energized)| *energized);
+        if complete && !state.solved {
+            info!("Solved leyline puzzle {:?}", state.name);
+            state.solved = true;
+            solved.send(PuzzleSolved {
+                puzzle: *puzzle,
+                name: state.name.clone(),
+            });
+        }
+    }
+
+    // Keep the beams that still carry energy, and stretch them between their stones.
+    for (entity, beam, mut transform) in &mut beams {
+        let ends = (stones.get(beam.from), stones.get(beam.to));
+        let (Ok((_, _, from, ..)), Ok((_, _, to, ..))) = ends else {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        };
+        if !connections.remove(&(beam.from, beam.to)) {
+            commands.entity(entity).despawn_recursive();
+            continue;
+        }
+        *transform = beam_transform(from.translation, to.translation);
+    }
+    for (from, to) in connections {
+        let (Ok((_, _, start, ..)), Ok((_, _, end, ..))) = (stones.get(from), stones.get(to))
+        else {
+            continue;
+        };
+        commands.spawn((
+            Name::new("ley_beam"),
+            PbrBundle {
+                mesh: assets.beam_mesh.clone(),
+                material: assets.beam_material.clone(),
+                transform: beam_transform(start.translation, end.translation),
+                ..default()
+            },
+            LeyBeam { from, to },
+        ));
+    }
+}
+
+// Stretches the unit cylinder between the tops of two stones.
+fn beam_transform(from: Vec3, to: Vec3) -> Transform {
+    let (from, to) = (from + Vec3::Y * BEAM_HEIGHT, to + Vec3::Y * BEAM_HEIGHT);
+    let beam = to - from;
+    Transform {
+        translation: from + beam / 2.0,
+        rotation: Quat::from_rotation_arc(Vec3::Y, beam.try_normalize().unwrap_or(Vec3::Y)),
+        scale: Vec3::new(1.0, beam.length(), 1.0),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn node(kind: LeystoneKind, x: f32, z: f32, facing: Vec3) -> LeyNode {
+        LeyNode {
+            kind,
+            position: Vec3::new(x, 0., z),
+            facing,
+            range: 40.,
+        }
+    }
+
+    fn unblocked(_: Vec3, _: Vec3) -> bool {
+        false
+    }
+
+    #[test]
+    fn energy_flows_along_aligned_stones() {
+        let nodes = [
+            node(LeystoneKind::Source, 0., 0., Vec3::X),
+            node(LeystoneKind::Relay, 10., 0., Vec3::NEG_Z),
+            node(LeystoneKind::Sink, 10., -10., Vec3::X),
+        ];
+        let flow = compute_flow(&nodes, unblocked);
+        assert_eq!(flow.energized, vec![true, true, true]);
+        assert_eq!(flow.beams, vec![(0, 1), (1, 2)]);
+    }
+
+    #[test]
+    fn a_misaligned_relay_breaks_the_line() {
+        let nodes = [
+            node(LeystoneKind::Source, 0., 0., Vec3::X),
+            node(LeystoneKind::Relay, 10., 0., Vec3::Z),
+            node(LeystoneKind::Sink, 10., -10., Vec3::X),
+        ];
+        let flow = compute_flow(&nodes, unblocked);
+        assert_eq!(flow.energized, vec![true, true, false]);
+        assert_eq!(flow.beams, vec![(0, 1)]);
+    }
+
+    #[test]
+    fn beams_stop_at_the_nearest_stone() {
+        let nodes = [
+            node(LeystoneKind::Source, 0., 0., Vec3::X),
+            node(LeystoneKind::Sink, 20., 0.3, Vec3::X),
+            node(LeystoneKind::Sink, 10., 0., Vec3::X),
+            node(LeystoneKind::Sink, 50., 0., Vec3::X),
+        ];
+        let flow = compute_flow(&nodes, unblocked);
+        assert_eq!(flow.energized, vec![true, false, true, false]);
+    }
+
+    #[test]
+    fn obstacles_block_beams() {
+        let nodes = [
+            node(LeystoneKind::Source, 0., 0., Vec3::X),
+            node(LeystoneKind::Sink, 10., 0., Vec3::X),
+        ];
+        let flow = compute_flow(&nodes, |_, _| true);
+        assert_eq!(flow.energized, vec![true, false]);
+        assert!(flow.beams.is_empty());
+    }
+}
@@ -0,0 +1,59 @@
+#![feature(f16)]
+#![feature(trait_alias)]
+#![feature(iter_array_chunks)]
+#![feature(array_chunks)]
+use bevy::prelude::*;
+
+pub mod asset_cache;
+pub mod bevy_rtin;
+pub mod camera;
+pub mod components;
+#[cfg(feature = "serde")]
+pub mod editor;
+pub mod encounters;
+pub mod enemy;
+pub mod geometry;
+pub mod hitpoints;
+pub mod items;
+#[cfg(feature = "serde")]
+pub mod level;
+pub mod leylines;
+pub mod mana;
+pub mod missions;
+pub mod navigation;
+#[cfg(feature = "serde")]
+pub mod net;
+pub mod objects;
+pub mod palette;
+pub mod physics;
+pub mod player;
+pub mod player_hud;
+pub mod prelude;
+pub mod progression;
+pub mod respawn;
+pub mod routes;
+pub mod rtin;
+#[cfg(feature = "serde")]
+pub mod save;
+pub mod status;
+pub mod world;
+
+#[derive(States, Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub enum GameState {
+    #[default]
+    StartingUp,
+    DevMode,
+    // Flycam / exhibition mode where the player can click to respawn
+    Prespawn,
+    // Transitory state where we are setting up entities, such as the player entity
+    // after the user has initiated respawn
+    Spawning,
+    InGame,
+}
+
+// #[derive(SubStates, Clone, PartialEq, Eq, Hash, Debug, Default)]
+// #[source(GameState = GameState::InGame)]
+// enum InGameState {
+//     Playing,
+//     Spawning
+// }
@@ -10,33 +10,31 @@ use bevy::{
 };
 use bevy_firework::plugin::ParticleSystemPlugin;
 use clap::Parser;
+use mipo::{
+    asset_cache, camera, components, encounters, enemy, hitpoints, items, leylines, mana, missions,
+    navigation, objects, player, player_hud, progression, respawn, routes, status, world,
+    GameState,
+};
+#[cfg(feature = "serde")]
+use mipo::{editor, level, net, save};
+#[cfg(feature = "serde")]
+use std::net::SocketAddr;
 use std::path::PathBuf;
 // // Preprocess an image for rtin meshing.
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    terrain: PathBuf,
+    terrain: Option<PathBuf>,
+    /// Join the game served at this address instead of playing alone.
+    #[cfg(feature = "serde")]
+    #[arg(long)]
+    connect: Option<SocketAddr>,
+    /// Serve this game to other players on this address, e.g. 127.0.0.1:5757.
+    #[cfg(feature = "serde")]
+    #[arg(long, conflicts_with = "connect")]
+    host: Option<SocketAddr>,
 }
 
-mod asset_cache;
-mod bevy_rtin;
-mod camera;
-mod components;
-mod enemy;
-mod geometry;
-mod hitpoints;
-mod items;
-mod mana;
-mod objects;
-mod palette;
-mod physics;
-mod player;
-mod player_hud;
-mod prelude;
-mod routes;
-mod rtin;
-mod world;
-
 use bevy::log::LogPlugin;
 // use bevy_inspector_egui;
 use bevy_lunex;
@@ -44,26 +42,6 @@ use bevy_rapier3d::prelude::*;
 use bevy_stl;
 use smooth_bevy_cameras;
 
-#[derive(States, Clone, PartialEq, Eq, Hash, Debug, Default)]
-pub enum GameState {
-    #[default]
-    StartingUp,
-    DevMode,
-    // Flycam / exhibition mode where the player can click to respawn
-    Prespawn,
-    // Transitory state where we are setting up entities, such as the player entity
-    // after the user has initiated respawn
-    Spawning,
-    InGame,
-}
-
-// #[derive(SubStates, Clone, PartialEq, Eq, Hash, Debug, Default)]
-// #[source(GameState = GameState::InGame)]
-// enum InGameState {
-//     Playing,
-//     Spawning
-// }
-
 fn finish_setup(mut game_state: ResMut<NextState<GameState>>) {
     game_state.set(GameState::Prespawn)
 }
@@ -72,9 +50,10 @@ fn finish_setup(mut game_state: ResMut<NextState<GameState>>) {
 // cargo run assets/grand_canyon_small_heightmap.png
 // cargo run assets/36_377_-112_445_11_8129_8129.png
 fn main() {
-    // let args = Args::parse();
+    let args = Args::parse();
 
-    App::new()
+    let mut app = App::new();
+    app
         // Enable ambiguity warnings for the Update schedule
         // .edit_schedule(Update, |schedule| {
         //     schedule.set_build_settings(ScheduleBuildSettings {
@@ -86,7 +65,7 @@ fn main() {
         .add_plugins((
             DefaultPlugins
                 .set(LogPlugin {
-                    filter: "info,wgpu_core=warn,wgpu_hal=warn,main=debug".into(),
+                    filter: "info,wgpu_core=warn,wgpu_hal=warn,main=debug,mipo=debug".into(),
                     level: bevy::log::Level::DEBUG,
                     ..default()
                 })
@@ -120,7 +99,10 @@ fn main() {
             smooth_bevy_cameras::controllers::unreal::UnrealCameraPlugin::default(),
             WireframePlugin,
             world::WorldPlugin {
-                terrain_path: "assets/grand_canyon_small_heightmap.png".into(),
+                terrain_path: args
+                    .terrain
+                    .clone()
+                    .unwrap_or_else(|| "assets/grand_canyon_small_heightmap.png".into()),
             },
             // ThirdPersonCameraPlugin,
             bevy_inspector_egui::quick::WorldInspectorPlugin::new(),
@@ -139,19 +121,49 @@ fn main() {
         .add_systems(Startup, startup)
         .add_plugins((
             hitpoints::HpPlugin,
+            enemy::EnemyPlugin,
+            encounters::EncounterPlugin,
+            navigation::NavigationPlugin,
+            leylines::LeylinePlugin,
+            missions::MissionPlugin,
             player_hud::PlayerHudPlugin,
             objects::TargetsPlugin,
             items::ItemsPlugin,
             routes::RoutesPlugin,
             bevy_lunex::UiPlugin,
-            crate::mana::ManaPlugin, // diegetic ui system
+            mana::ManaPlugin, // diegetic ui system
+            respawn::RespawnPlugin,
+            objects::CheckpointsPlugin,
+            status::StatusPlugin,
         ))
+        .add_plugins(progression::ProgressionPlugin)
         .insert_resource(ClearColor(Color::srgb(0.53, 0.53, 0.53)))
         .insert_resource(WireframeConfig {
             global: false,
             ..default()
-        })
-        .run();
+        });
+    #[cfg(feature = "serde")]
+    match args.connect {
+        // The server runs the level and replicates it, so a client only brings its own player.
+        Some(server) => {
+            app.insert_resource(items::PlatformSettings {
+                random_spawner: false,
+            })
+            .add_plugins(net::ClientPlugin { server });
+        }
+        None => {
+            app.add_plugins(level::LevelPlugin {
+                level_path: "assets/levels/canyon.ron".into(),
+                terrain: args.terrain.clone(),
+            })
+            .add_plugins(save::SavePlugin)
+            .add_plugins(editor::EditorPlugin);
+            if let Some(addr) = args.host {
+                app.add_plugins(net::ServerPlugin { addr });
+            }
+        }
+    }
+    app.run();
 }
 
 fn startup(mut commands: Commands) {
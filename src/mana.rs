@@ -18,6 +18,12 @@ pub struct Mana {
     pub max: u32,
 }
 
+impl Mana {
+    pub fn restore(&mut self) {
+        self.current = self.max;
+    }
+}
+
 #[derive(Component, Default)]
 pub struct ManaRegen {
     pub regen_mana_timer: Timer, // Time should tick every time more mana should be given
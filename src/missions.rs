@@ -0,0 +1,682 @@
+use crate::{
+    encounters::{Encounter, EncounterMember, WaveCleared, WaveFailed},
+    hitpoints::Dead,
+    leylines::{LeylinePuzzle, Leystone, PuzzleSolved},
+    objects::Target,
+    player::PlayerInput,
+    prelude::*,
+};
+use bevy::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The level's `Mission`, if it has one: its objectives, their progress on the HUD, and a summary once it's
+/// over. Each time the player spawns, the mission starts over from its briefing, with its encounters re-armed
+/// and its leyline puzzles unsolved.
+pub struct MissionPlugin;
+
+impl Plugin for MissionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_sub_state::<MissionState>();
+        app.add_event::<ObjectiveCompleted>();
+        app.enable_state_scoped_entities::<MissionState>();
+        app.observe(count_destroyed_targets);
+        app.add_systems(OnEnter(GameState::InGame), spawn_objective_text);
+        app.add_systems(OnEnter(MissionState::Briefing), start_briefing);
+        app.add_systems(OnEnter(MissionState::Succeeded), spawn_summary);
+        app.add_systems(OnEnter(MissionState::Failed), spawn_summary);
+        app.add_systems(
+            Update,
+            (
+                end_briefing.run_if(in_state(MissionState::Briefing)),
+                track_objectives.run_if(in_state(MissionState::Active)),
+                update_objective_text.run_if(in_state(GameState::InGame)),
+                replay_mission.run_if(
+                    in_state(MissionState::Succeeded).or_else(in_state(MissionState::Failed)),
+                ),
+            )
+                .run_if(resource_exists::<Mission>),
+        );
+    }
+}
+
+/// How long the mission's name and description are shown before it starts.
+const BRIEFING_SECONDS: f32 = 3.0;
+
+#[derive(SubStates, Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+#[source(GameState = GameState::InGame)]
+pub enum MissionState {
+    /// Showing what the mission is about. Progress, and the encounters and puzzles that it depends on, are
+    /// reset on entering it.
+    #[default]
+    Briefing,
+    Active,
+    Succeeded,
+    Failed,
+}
+
+/// A mission, as laid out in a level file. It succeeds once every objective is complete.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MissionDefinition {
+    pub name: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub description: String,
+    pub objectives: Vec<Objective>,
+    /// Seconds that the player has to complete the mission.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub time_limit: Option<f32>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Objective {
+    DestroyTargets {
+        count: u32,
+    },
+    /// Clear this many encounter waves. Failing a wave fails the mission.
+    SurviveWaves {
+        count: u32,
+    },
+    ReachLocation {
+        name: String,
+        position: Vec3,
+        radius: f32,
+    },
+    /// Solve the leyline puzzle with this name.
+    AlignLeyline {
+        puzzle: String,
+    },
+}
+
+impl Objective {
+    /// How much progress completes the objective.
+    pub fn required(&self) -> u32 {
+        match self {
+            Objective::DestroyTargets { count } | Objective::SurviveWaves { count } => *count,
+            Objective::ReachLocation { .. } | Objective::AlignLeyline { .. } => 1,
+        }
+    }
+
+    pub fn describe(&self, progress: u32) -> String {
+        let progress = progress.min(self.required());
+        match self {
+            Objective::DestroyTargets { count } => format!("Destroy targets ({progress}/{count})"),
+            Objective::SurviveWaves { count } => format!("Survive waves ({progress}/{count})"),
+            Objective::ReachLocation { name, .. } => format!("Reach {name}"),
+            Objective::AlignLeyline { puzzle } => format!("Align the leyline at {puzzle}"),
+        }
+    }
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct Mission {
+    pub definition: MissionDefinition,
+    /// Progress toward each of the definition's objectives, in order.
+    pub progress: Vec<u32>,
+    /// Seconds since the mission became active.
+    pub elapsed: f32,
+    briefing: Timer,
+}
+
+impl Mission {
+    pub fn new(definition: MissionDefinition) -> Self {
+        Mission {
+            progress: vec![0; definition.objectives.len()],
+            definition,
+            elapsed: 0.0,
+            briefing: Timer::from_seconds(BRIEFING_SECONDS, TimerMode::Once),
+        }
+    }
+
+    fn reset(&mut self) {
+        *self = Mission::new(self.definition.clone());
+    }
+
+    pub fn objective_complete(&self, objective: usize) -> bool {
+        self.progress[objective] >= self.definition.objectives[objective].required()
+    }
+
+    pub fn complete(&self) -> bool {
+        (0..self.definition.objectives.len()).all(|objective| self.objective_complete(objective))
+    }
+
+    /// Progresses each objective that `matches` by one, and returns the ones that this completed.
+    fn advance(&mut self, matches: impl Fn(&Objective) -> bool) -> Vec<ObjectiveCompleted> {
+        let mut completed = vec![];
+        for (i, (objective, progress)) in self
+            .definition
+            .objectives
+            .iter()
+            .zip(&mut self.progress)
+            .enumerate()
+        {
+            if matches(objective) && *progress < objective.required() {
+                *progress += 1;
+                if *progress == objective.required() {
+                    completed.push(ObjectiveCompleted(i));
+                }
+            }
+        }
+        completed
+    }
+
+    /// One line for each objective, ticked off once it's complete.
+    pub fn objective_text(&self) -> String {
+        self.definition
+            .objectives
+            .iter()
+            .enumerate()
+            .map(|(i, objective)| {
+                let mark = if self.objective_complete(i) {
+                    "[x]"
+                } else {
+                    "[ ]"
+                };
+                format!("{mark} {}", objective.describe(self.progress[i]))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// Sent when one of the mission's objectives is completed, with its index.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct ObjectiveCompleted(pub usize);
+
+/// How the last mission went, for the summary screen.
+#[derive(Resource, Debug, Clone)]
+pub struct MissionSummary {
+    pub name: String,
+    pub succeeded: bool,
+    /// Why the mission failed.
+    pub reason: Option<String>,
+    pub elapsed: f32,
+    pub objectives: String,
+}
+
+#[derive(Component)]
+struct ObjectiveText;
+
+fn start_briefing(
+    mut commands: Commands,
+    mission: Option<ResMut<Mission>>,
+    mut encounters: Query<&mut Encounter>,
+    members: Query<Entity, With<EncounterMember>>,
+    mut puzzles: Query<&mut LeylinePuzzle>,
+    mut stones: Query<&mut Leystone>,
+) {
+    let Some(mut mission) = mission else {
+        return;
+    };
+    mission.reset();
+    // The objectives can only be completed again if the world that they're about starts over too.
+    for mut encounter in &mut encounters {
+        encounter.rearm();
+    }
+    for member in &members {
+        commands.entity(member).despawn_recursive();
+    }
+    for mut puzzle in &mut puzzles {
+        puzzle.solved = false;
+    }
+    for mut stone in &mut stones {
+        if stone.puzzle.is_some() {
+            stone.reset();
+        }
+    }
+    info!("Briefing for mission {:?}", mission.definition.name);
+    commands
+        .spawn((
+            Name::new("mission_briefing"),
+            StateScoped(MissionState::Briefing),
+            overlay(),
+        ))
+        .with_children(|briefing| {
+            briefing.spawn(TextBundle::from_section(
+                mission.definition.name.clone(),
+                TextStyle {
+                    font_size: 60.0,
+                    color: Palette::Yellow.to_color(),
+                    ..default()
+                },
+            ));
+            briefing.spawn(TextBundle::from_section(
+                mission.definition.description.clone(),
+                TextStyle {
+                    font_size: 28.0,
+                    ..default()
+                },
+            ));
+        });
+}
+
+fn end_briefing(
+    time: Res<Time>,
+    mut mission: ResMut<Mission>,
+    mut next_state: ResMut<NextState<MissionState>>,
+) {
+    if mission.briefing.tick(time.delta()).just_finished() {
+        next_state.set(MissionState::Active);
+    }
+}
+
+fn count_destroyed_targets(
+    trigger: Trigger<OnAdd, Dead>,
+    targets: Query<(), With<Target>>,
+    state: Option<Res<State<MissionState>>>,
+    mission: Option<ResMut<Mission>>,
+    mut completed: EventWriter<ObjectiveCompleted>,
+) {
+    if !state.is_some_and(|state| *state.get() == MissionState::Active) {
+        return;
+    }
+    let Some(mut mission) = mission else {
+        return;
+    };
+    if targets.contains(trigger.entity()) {
+        completed.send_batch(
+            mission.advance(|objective| matches!(objective, Objective::DestroyTargets { .. })),
+        );
+    }
+}
+
+fn track_objectives(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut mission: ResMut<Mission>,
+    mut next_state: ResMut<NextState<MissionState>>,
+    players: Query<&Transform, With<PlayerInput>>,
+    mut cleared: EventReader<WaveCleared>,
+    mut failed: EventReader<WaveFailed>,
+    mut solved: EventReader<PuzzleSolved>,
+    mut completed: EventWriter<ObjectiveCompleted>,
+) {
+    mission.elapsed += time.delta_seconds();
+    for _ in cleared.read() {
+        completed.send_batch(
+            mission.advance(|objective| matches!(objective, Objective::SurviveWaves { .. })),
+        );
+    }
+    for event in solved.read() {
+        completed.send_batch(mission.advance(
+            |objective| matches!(objective, Objective::AlignLeyline { puzzle } if *puzzle == event.name),
+        ));
+    }
+    // Any player getting there will do.
+    completed.send_batch(mission.advance(|objective| {
+        match objective {
+            Objective::ReachLocation {
+                position, radius, ..
+            } => players
+                .iter()
+                .any(|player| player.translation.distance(*position) <= *radius),
+            _ => false,
+        }
+    }));
+
+    let surviving = mission
+        .definition
+        .objectives
+        .iter()
+        .any(|objective| matches!(objective, Objective::SurviveWaves { .. }));
+    let out_of_time = mission
+        .definition
+        .time_limit
+        .is_some_and(|limit| mission.elapsed > limit);
+    let reason = if failed.read().count() > 0 && surviving {
+        Some("A wave overran you.")
+    } else if out_of_time {
+        Some("Out of time.")
+    } else {
+        None
+    };
+
+    if !mission.complete() && reason.is_none() {
+        return;
+    }
+    let succeeded = reason.is_none();
+    info!(
+        "Mission {:?} {}",
+        mission.definition.name,
+        if succeeded { "succeeded" } else { "failed" }
+    );
+    commands.insert_resource(MissionSummary {
+        name: mission.definition.name.clone(),
+        succeeded,
+        reason: reason.map(String::from),
+        elapsed: mission.elapsed,
+        objectives: mission.objective_text(),
+    });
+    next_state.set(if succeeded {
+        MissionState::Succeeded
+    } else {
+        MissionState::Failed
+    });
+}
+
+fn spawn_objective_text(mut commands: Commands) {
+    commands.spawn((
+        Name::new("mission_objectives"),
+        StateScoped(GameState::InGame),
+        ObjectiveText,
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 24.0,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(20.0),
+            left: Val::Px(20.0),
+            ..default()
+        }),
+    ));
+}
+
+fn update_objective_text(mission: Res<Mission>, mut text: Query<&mut Text, With<ObjectiveText>>) {
+    if !mission.is_changed() {
+        return;
+    }
+    for mut text in &mut text {
+        text.sections[0].value =
+            format!("{}\n{}", mission.definition.name, mission.objective_text());
+    }
+}
+
+fn spawn_summary(
+    mut commands: Commands,
+    state: Res<State<MissionState>>,
+    summary: Option<Res<MissionSummary>>,
+) {
+    let Some(summary) = summary else {
+        return;
+    };
+    let (title, color) = if summary.succeeded {
+        ("Mission complete", Palette::Blue)
+    } else {
+        ("Mission failed", Palette::Red)
+    };
+    let minutes = (summary.elapsed / 60.0).floor();
+    let seconds = summary.elapsed % 60.0;
+    let mut details = format!(
+        "{}\nTime: {minutes:.0}:{seconds:04.1}\n\n{}",
+        summary.name, summary.objectives
+    );
+    if let Some(reason) = &summary.reason {
+        details = format!("{reason}\n{details}");
+    }
+    commands
+        .spawn((
+            Name::new("mission_summary"),
+            StateScoped(*state.get()),
+            overlay(),
+        ))
+        .with_children(|screen| {
+            screen.spawn(TextBundle::from_section(
+                title,
+                TextStyle {
+                    font_size: 60.0,
+                    color: color.to_color(),
+                    ..default()
+                },
+            ));
+            screen.spawn(TextBundle::from_section(
+                details,
+                TextStyle {
+                    font_size: 28.0,
+                    ..default()
+                },
+            ));
+            screen.spawn(TextBundle::from_section(
+                "Press Enter to play again",
+                TextStyle {
+                    font_size: 20.0,
+                    ..default()
+                },
+            ));
+        });
+}
+
+fn replay_mission(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut next_state: ResMut<NextState<MissionState>>,
+) {
+    if keyboard.just_pressed(KeyCode::Enter) {
+        next_state.set(MissionState::Briefing);
+    }
+}
+
+// A dimmed, full screen column of centered text.
+fn overlay() -> NodeBundle {
+    let mut background = Color::BLACK;
+    background.set_alpha(0.6);
+    NodeBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            width: Val::Percent(100.0),
+            height: Val::Percent(100.0),
+            flex_direction: FlexDirection::Column,
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            row_gap: Val::Px(20.0),
+            ..default()
+        },
+        background_color: BackgroundColor(background),
+        ..default()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::encounters::{EncounterDefinition, EncounterState};
+    use bevy::{state::app::StatesPlugin, time::TimeUpdateStrategy};
+    use std::time::Duration;
+
+    fn definition() -> MissionDefinition {
+        MissionDefinition {
+            name: "Test".into(),
+            description: String::new(),
+            objectives: vec![
+                Objective::DestroyTargets { count: 2 },
+                Objective::ReachLocation {
+                    name: "the rim".into(),
+                    position: Vec3::new(100., 0., 0.),
+                    radius: 5.,
+                },
+            ],
+            time_limit: Some(60.),
+        }
+    }
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, StatesPlugin))
+            .init_state::<GameState>()
+            .add_event::<WaveCleared>()
+            .add_event::<WaveFailed>()
+            .add_event::<PuzzleSolved>()
+            .init_resource::<ButtonInput<KeyCode>>()
+            .add_plugins(MissionPlugin)
+            .insert_resource(Mission::new(definition()))
+            .insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+                100,
+            )));
+        app.world_mut()
+            .resource_mut::<NextState<GameState>>()
+            .set(GameState::InGame);
+        app.world_mut()
+            .spawn((Player, PlayerInput::default(), Transform::default()));
+        app
+    }
+
+    fn step(app: &mut App, updates: usize) {
+        for _ in 0..updates {
+            app.update();
+        }
+    }
+
+    fn mission_state(app: &App) -> MissionState {
+        *app.world().resource::<State<MissionState>>().get()
+    }
+
+    #[test]
+    fn describes_progress() {
+        let mut mission = Mission::new(definition());
+        mission.advance(|objective| matches!(objective, Objective::DestroyTargets { .. }));
+        assert_eq!(
+            mission.objective_text(),
+            "[ ] Destroy targets (1/2)\n[ ] Reach the rim"
+        );
+        mission.advance(|_| true);
+        assert!(mission.complete());
+        assert_eq!(
+            mission.objective_text(),
+            "[x] Destroy targets (2/2)\n[x] Reach the rim"
+        );
+    }
+
+    #[test]
+    fn succeeds_once_every_objective_is_complete() {
+        let mut app = app();
+        step(&mut app, 2);
+        assert_eq!(mission_state(&app), MissionState::Briefing);
+        step(&mut app, 40);
+        assert_eq!(mission_state(&app), MissionState::Active);
+
+        for _ in 0..2 {
+            app.world_mut()
+                .spawn(Target::default())
+                .insert(Dead::default());
+        }
+        let mut player = app
+            .world_mut()
+            .query_filtered::<&mut Transform, With<Player>>();
+        player.single_mut(app.world_mut()).translation = Vec3::new(98., 0., 0.);
+        step(&mut app, 2);
+        assert_eq!(mission_state(&app), MissionState::Succeeded);
+        let summary = app.world().resource::<MissionSummary>();
+        assert!(summary.succeeded);
+    }
+
+    #[test]
+    fn fails_when_time_runs_out() {
+        let mut app = app();
+        step(&mut app, 700);
+        assert_eq!(mission_state(&app), MissionState::Failed);
+        let summary = app.world().resource::<MissionSummary>();
+        assert_eq!(summary.reason.as_deref(), Some("Out of time."));
+    }
+
+    #[test]
+    fn replaying_rearms_encounters_and_unsolves_puzzles() {
+        let mut app = app();
+        let definition = MissionDefinition {
+            objectives: vec![
+                Objective::AlignLeyline {
+                    puzzle: "gate".into(),
+                },
+                Objective::SurviveWaves { count: 1 },
+            ],
+            ..definition()
+        };
+        app.insert_resource(Mission::new(definition.clone()));
+        let encounter = app
+            .world_mut()
+            .spawn(Encounter::new(EncounterDefinition {
+                position: Vec3::ZERO,
+                trigger_radius: 20.0,
+                spawn_points: vec![],
+                waves: vec![],
+                escalation: None,
+            }))
+            .id();
+        let puzzle = app
+            .world_mut()
+            .spawn(LeylinePuzzle {
+                name: "gate".into(),
+                solved: false,
+            })
+            .id();
+        let stone = app
+            .world_mut()
+            .spawn(Leystone {
+                orientations: vec![0., 90.],
+                puzzle: Some(puzzle),
+                ..default()
+            })
+            .id();
+        step(&mut app, 40);
+        assert_eq!(mission_state(&app), MissionState::Active);
+
+        // Solve the puzzle, then lose to a wave.
+        app.world_mut()
+            .get_mut::<Leystone>(stone)
+            .unwrap()
+            .orientation = 1;
+        app.world_mut()
+            .get_mut::<LeylinePuzzle>(puzzle)
+            .unwrap()
+            .solved = true;
+        app.world_mut().send_event(PuzzleSolved {
+            puzzle,
+            name: "gate".into(),
+        });
+        app.world_mut()
+            .get_mut::<Encounter>(encounter)
+            .unwrap()
+            .state = EncounterState::Failed;
+        let member = app.world_mut().spawn(EncounterMember { encounter }).id();
+        app.world_mut()
+            .send_event(WaveFailed { encounter, wave: 0 });
+        step(&mut app, 2);
+        assert_eq!(mission_state(&app), MissionState::Failed);
+
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::Enter);
+        step(&mut app, 2);
+        assert_eq!(mission_state(&app), MissionState::Briefing);
+        let world = app.world();
+        assert_eq!(
+            world.get::<Encounter>(encounter).unwrap().state,
+            EncounterState::Dormant
+        );
+        assert!(world.get_entity(member).is_none());
+        assert!(!world.get::<LeylinePuzzle>(puzzle).unwrap().solved);
+        assert_eq!(world.get::<Leystone>(stone).unwrap().orientation, 0);
+
+        // Both objectives can be completed again.
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .reset(KeyCode::Enter);
+        step(&mut app, 40);
+        assert_eq!(mission_state(&app), MissionState::Active);
+        app.world_mut().send_event(PuzzleSolved {
+            puzzle,
+            name: "gate".into(),
+        });
+        app.world_mut().send_event(WaveCleared {
+            encounter,
+            wave: 0,
+            last: true,
+        });
+        step(&mut app, 2);
+        assert_eq!(mission_state(&app), MissionState::Succeeded);
+    }
+
+    #[test]
+    fn replaying_starts_over() {
+        let mut app = app();
+        step(&mut app, 700);
+        app.world_mut()
+            .resource_mut::<ButtonInput<KeyCode>>()
+            .press(KeyCode::Enter);
+        step(&mut app, 2);
+        assert_eq!(mission_state(&app), MissionState::Briefing);
+        assert_eq!(app.world().resource::<Mission>().elapsed, 0.0);
+    }
+}
@@ -0,0 +1,521 @@
+use crate::world::Terrain;
+use bevy::{
+    prelude::*,
+    tasks::{block_on, futures_lite::future, AsyncComputeTaskPool, Task},
+};
+use std::{cmp::Ordering, collections::BinaryHeap, sync::Arc, time::Duration};
+
+/// Walkable ground for AI agents, built from the `Terrain` resource and rebuilt in the background whenever
+/// the terrain changes. Agents with a `NavAgent` get a path to their `destination`, worked out off the main
+/// thread.
+pub struct NavigationPlugin;
+
+impl Plugin for NavigationPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Navigation>();
+        app.add_systems(Update, rebuild_navigation);
+        app.add_systems(
+            FixedUpdate,
+            (request_paths, follow_paths).chain().in_set(NavigationSet),
+        );
+    }
+}
+
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NavigationSet;
+
+/// One heightmap pixel of the terrain, as scaled in `world`.
+const NAV_CELL_SIZE: f32 = 3.0;
+/// The steepest slope that characters can climb. Matches `max_slope_climb_angle` on the player's and enemies'
+/// character controllers.
+const MAX_SLOPE: f32 = 45.0;
+// How close an agent has to get to a waypoint before heading for the next one.
+const WAYPOINT_REACHED: f32 = 1.5;
+// An agent doesn't plan a new path more often than this, unless the terrain changes.
+const REPATH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The current navigation grid, if the terrain has been built.
+#[derive(Resource, Default)]
+pub struct Navigation {
+    pub grid: Option<Arc<NavGrid>>,
+    /// Goes up by one each time the grid is rebuilt, so that agents know to plan their paths again.
+    pub generation: u32,
+    rebuild: Option<Task<NavGrid>>,
+}
+
+/// Something that finds its own way to `destination` across the terrain. `next_waypoint` is where to head
+/// for now; it's `None` while a path is being planned, or if there's no way there.
+#[derive(Component, Debug)]
+pub struct NavAgent {
+    pub destination: Option<Vec3>,
+    path: Vec<Vec3>,
+    planned_for: Option<Vec3>,
+    generation: u32,
+    repath: Timer,
+    task: Option<Task<Option<Vec<Vec3>>>>,
+}
+
+impl Default for NavAgent {
+    fn default() -> Self {
+        NavAgent {
+            destination: None,
+            path: vec![],
+            planned_for: None,
+            generation: 0,
+            repath: Timer::new(REPATH_INTERVAL, TimerMode::Once),
+            task: None,
+        }
+    }
+}
+
+impl NavAgent {
+    pub fn next_waypoint(&self) -> Option<Vec3> {
+        self.path.first().copied()
+    }
+
+    /// The rest of the path, from the next waypoint to the destination.
+    pub fn path(&self) -> &[Vec3] {
+        &self.path
+    }
+}
+
+/// A height for each square cell of the terrain, seen from above, and which of its neighbours can be walked
+/// to. Cells that no triangle covers can't be walked on.
+#[derive(Debug, Clone)]
+pub struct NavGrid {
+    cell_size: f32,
+    /// World x and z of the corner of cell (0, 0).
+    origin: Vec2,
+    width: i32,
+    depth: i32,
+    heights: Vec<Option<f32>>,
+    /// The most that the ground may rise or fall per unit of horizontal distance.
+    max_climb: f32,
+}
+
+impl NavGrid {
+    /// Builds the grid from the terrain's triangles. `max_slope` is in degrees.
+    pub fn from_terrain(terrain: &Terrain, cell_size: f32, max_slope: f32) -> Self {
+        NavGrid::from_triangles(terrain.triangles(), cell_size, max_slope)
+    }
+
+    /// Builds the grid from triangles in world space, taking the highest surface over each cell's center.
+    /// `max_slope` is in degrees.
+    pub fn from_triangles(
+        triangles: impl IntoIterator<Item = [Vec3; 3]>,
+        cell_size: f32,
+        max_slope: f32,
+    ) -> Self {
+        let triangles: Vec<[Vec3; 3]> = triangles.into_iter().collect();
+        let (min, max) = triangles.iter().flatten().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), vertex| (min.min(vertex.xz()), max.max(vertex.xz())),
+        );
+        let origin = if triangles.is_empty() {
+            Vec2::ZERO
+        } else {
+            min
+        };
+        let size = ((max - origin) / cell_size).ceil().max(Vec2::ZERO);
+        let mut grid = NavGrid {
+            cell_size,
+            origin,
+            width: size.x as i32,
+            depth: size.y as i32,
+            heights: vec![None; size.x as usize * size.y as usize],
+            max_climb: max_slope.to_radians().tan(),
+        };
+        for triangle in &triangles {
+            grid.rasterize(triangle);
+        }
+        grid
+    }
+
+    fn rasterize(&mut self, triangle: &[Vec3; 3]) {
+        let corners = triangle.map(|vertex| vertex.xz());
+        let min = corners[0].min(corners[1]).min(corners[2]);
+        let max = corners[0].max(corners[1]).max(corners[2]);
+        // The cells whose centers fall inside the triangle's bounding box.
+        let first = ((min - self.origin) / self.cell_size - 0.5)
+            .ceil()
+            .as_ivec2();
+        let last = ((max - self.origin) / self.cell_size - 0.5)
+            .floor()
+            .as_ivec2();
+        let first = first.max(IVec2::ZERO);
+        let last = last.min(IVec2::new(self.width - 1, self.depth - 1));
+        for z in first.y..=last.y {
+            for x in first.x..=last.x {
+                let cell = IVec2::new(x, z);
+                let Some(weights) = barycentric(self.center_xz(cell), corners) else {
+                    continue;
+                };
+                let height = weights.x * triangle[0].y
+                    + weights.y * triangle[1].y
+                    + weights.z * triangle[2].y;
+                let index = self.index(cell).unwrap();
+                self.heights[index] = Some(self.heights[index].map_or(height, |h| h.max(height)));
+            }
+        }
+    }
+
+    fn index(&self, cell: IVec2) -> Option<usize> {
+        let inside = cell.x >= 0 && cell.y >= 0 && cell.x < self.width && cell.y < self.depth;
+        inside.then(|| (cell.y * self.width + cell.x) as usize)
+    }
+
+    fn cell_of(&self, position: Vec3) -> Option<IVec2> {
+        let cell = ((position.xz() - self.origin) / self.cell_size)
+            .floor()
+            .as_ivec2();
+        self.index(cell).map(|_| cell)
+    }
+
+    fn center_xz(&self, cell: IVec2) -> Vec2 {
+        self.origin + (cell.as_vec2() + 0.5) * self.cell_size
+    }
+
+    fn height(&self, cell: IVec2) -> Option<f32> {
+        self.index(cell).and_then(|index| self.heights[index])
+    }
+
+    /// The ground at the center of the cell under `position`, if it can be walked on.
+    pub fn ground(&self, position: Vec3) -> Option<Vec3> {
+        let cell = self.cell_of(position)?;
+        let center = self.center_xz(cell);
+        Some(Vec3::new(center.x, self.height(cell)?, center.y))
+    }
+
+    // Whether the ground between two cells is gentle enough to walk.
+    fn passable(&self, from: IVec2, to: IVec2) -> bool {
+        let (Some(a), Some(b)) = (self.height(from), self.height(to)) else {
+            return false;
+        };
+        let run = (to - from).as_vec2().length() * self.cell_size;
+        (a - b).abs() <= self.max_climb * run
+    }
+
+    // Steps to one of the eight neighbouring cells. Diagonal steps can't cut the corner of a cell that
+    // couldn't be walked onto.
+    fn can_step(&self, from: IVec2, to: IVec2) -> bool {
+        let delta = to - from;
+        if delta.x != 0 && delta.y != 0 {
+            self.passable(from, IVec2::new(to.x, from.y))
+                && self.passable(from, IVec2::new(from.x, to.y))
+                && self.passable(from, to)
+        } else {
+            self.passable(from, to)
+        }
+    }
+
+    /// A* across the grid, smoothed so that the path only turns where it has to. The path runs from `start`
+    /// to `goal`, both included, or is `None` if there's no walkable way there.
+    pub fn find_path(&self, start: Vec3, goal: Vec3) -> Option<Vec<Vec3>> {
+        let start_cell = self.cell_of(start)?;
+        let goal_cell = self.cell_of(goal)?;
+        self.height(start_cell)?;
+        self.height(goal_cell)?;
+        if start_cell == goal_cell {
+            return Some(vec![start, goal]);
+        }
+
+        let cells = self.heights.len();
+        let mut cost = vec![f32::INFINITY; cells];
+        let mut came_from: Vec<Option<IVec2>> = vec![None; cells];
+        let mut closed = vec![false; cells];
+        let mut open = BinaryHeap::new();
+        cost[self.index(start_cell)?] = 0.0;
+        open.push(OpenCell {
+            estimate: octile(start_cell, goal_cell),
+            cell: start_cell,
+        });
+
+        while let Some(OpenCell { cell, .. }) = open.pop() {
+            let index = self.index(cell)?;
+            if closed[index] {
+                continue;
+            }
+            closed[index] = true;
+            if cell == goal_cell {
+                break;
+            }
+            for offset in NEIGHBOURS {
+                let next = cell + offset;
+                if !self.can_step(cell, next) {
+                    continue;
+                }
+                let next_index = self.index(next)?;
+                let next_cost = cost[index] + offset.as_vec2().length();
+                if closed[next_index] || next_cost >= cost[next_index] {
+                    continue;
+                }
+                cost[next_index] = next_cost;
+                came_from[next_index] = Some(cell);
+                open.push(OpenCell {
+                    estimate: next_cost + octile(next, goal_cell),
+                    cell: next,
+                });
+            }
+        }
+
+        if !closed[self.index(goal_cell)?] {
+            return None;
+        }
+        let mut points = vec![goal];
+        let mut cell = goal_cell;
+        while let Some(previous) = came_from[self.index(cell)?] {
+            cell = previous;
+            let center = self.center_xz(cell);
+            points.push(Vec3::new(center.x, self.height(cell)?, center.y));
+        }
+        // The start cell's center is replaced by the start itself.
+        points.pop();
+        points.push(start);
+        points.reverse();
+        Some(self.smooth(&points))
+    }
+
+    // Skips every waypoint that can be walked past in a straight line.
+    fn smooth(&self, points: &[Vec3]) -> Vec<Vec3> {
+        let mut smoothed = vec![points[0]];
+        let mut anchor = 0;
+        while anchor < points.len() - 1 {
+            let mut furthest = anchor + 1;
+            while furthest + 1 < points.len()
+                && self.walkable_line(points[anchor], points[furthest + 1])
+            {
+                furthest += 1;
+            }
+            smoothed.push(points[furthest]);
+            anchor = furthest;
+        }
+        smoothed
+    }
+
+    /// Whether the straight line from `from` to `to`, seen from above, only crosses walkable ground.
+    pub fn walkable_line(&self, from: Vec3, to: Vec3) -> bool {
+        let Some(mut previous) = self.cell_of(from) else {
+            return false;
+        };
+        // Short enough steps that no more than one cell boundary is crossed in each direction at a time.
+        let steps = (from.xz().distance(to.xz()) / (self.cell_size * 0.25))
+            .ceil()
+            .max(1.0) as usize;
+        for step in 1..=steps {
+            let Some(cell) = self.cell_of(from.lerp(to, step as f32 / steps as f32)) else {
+                return false;
+            };
+            if cell != previous {
+                if !self.can_step(previous, cell) {
+                    return false;
+                }
+                previous = cell;
+            }
+        }
+        true
+    }
+}
+
+const NEIGHBOURS: [IVec2; 8] = [
+    IVec2::new(1, 0),
+    IVec2::new(-1, 0),
+    IVec2::new(0, 1),
+    IVec2::new(0, -1),
+    IVec2::new(1, 1),
+    IVec2::new(1, -1),
+    IVec2::new(-1, 1),
+    IVec2::new(-1, -1),
+];
+
+// The shortest distance between two cells, in cells, moving in eight directions.
+fn octile(a: IVec2, b: IVec2) -> f32 {
+    let delta = (a - b).abs().as_vec2();
+    delta.max_element() + (std::f32::consts::SQRT_2 - 1.0) * delta.min_element()
+}
+
+// Weights of the triangle's corners at `point`, or `None` if the point is outside it.
+fn barycentric(point: Vec2, [a, b, c]: [Vec2; 3]) -> Option<Vec3> {
+    let (ab, ac, ap) = (b - a, c - a, point - a);
+    let area = ab.perp_dot(ac);
+    if area.abs() < f32::EPSILON {
+        return None;
+    }
+    let v = ap.perp_dot(ac) / area;
+    let w = ab.perp_dot(ap) / area;
+    let u = 1.0 - v - w;
+    const EDGE: f32 = -1e-5;
+    (u >= EDGE && v >= EDGE && w >= EDGE).then_some(Vec3::new(u, v, w))
+}
+
+// A cell waiting to be visited. Ordered so that `BinaryHeap` pops the lowest estimate first.
+#[derive(PartialEq)]
+struct OpenCell {
+    estimate: f32,
+    cell: IVec2,
+}
+
+impl Eq for OpenCell {}
+
+impl Ord for OpenCell {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+impl PartialOrd for OpenCell {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+fn rebuild_navigation(terrain: Option<Res<Terrain>>, mut navigation: ResMut<Navigation>) {
+    if let Some(terrain) = terrain.filter(|terrain| terrain.is_changed()) {
+        info!("Building navigation grid");
+        let terrain = Terrain::clone(&terrain);
+        // Replacing an unfinished rebuild cancels it.
+        navigation.rebuild = Some(
+            AsyncComputeTaskPool::get()
+                .spawn(async move { NavGrid::from_terrain(&terrain, NAV_CELL_SIZE, MAX_SLOPE) }),
+        );
+    }
+    let Some(task) = navigation.bypass_change_detection().rebuild.as_mut() else {
+        return;
+    };
+    if let Some(grid) = block_on(future::poll_once(task)) {
+        info!("Built a {}x{} navigation grid", grid.width, grid.depth);
+        navigation.grid = Some(Arc::new(grid));
+        navigation.generation += 1;
+        navigation.rebuild = None;
+    }
+}
+
+fn request_paths(
+    time: Res<Time>,
+    navigation: Res<Navigation>,
+    mut agents: Query<(&Transform, &mut NavAgent)>,
+) {
+    for (transform, mut agent) in &mut agents {
+        agent.repath.tick(time.delta());
+        let Some(destination) = agent.destination else {
+            agent.path.clear();
+            agent.planned_for = None;
+            continue;
+        };
+        let Some(grid) = navigation.grid.clone() else {
+            continue;
+        };
+        if agent.task.is_some() {
+            continue;
+        }
+        let terrain_changed = agent.generation != navigation.generation;
+        let destination_moved = !agent
+            .planned_for
+            .is_some_and(|planned| planned.distance(destination) <= grid.cell_size);
+        if !terrain_changed && !(destination_moved && agent.repath.finished()) {
+            continue;
+        }
+        let start = transform.translation;
+        agent.task = Some(
+            AsyncComputeTaskPool::get().spawn(async move { grid.find_path(start, destination) }),
+        );
+        agent.planned_for = Some(destination);
+        agent.generation = navigation.generation;
+        agent.repath.reset();
+    }
+}
+
+fn follow_paths(mut agents: Query<(&Transform, &mut NavAgent)>) {
+    for (transform, mut agent) in &mut agents {
+        if let Some(task) = agent.task.as_mut() {
+            if let Some(path) = block_on(future::poll_once(task)) {
+                agent.task = None;
+                // The first waypoint is where the agent was when it asked.
+                agent.path = path.map(|path| path[1..].to_vec()).unwrap_or_default();
+            }
+        }
+        let position = transform.translation.xz();
+        while agent
+            .next_waypoint()
+            .is_some_and(|waypoint| waypoint.xz().distance(position) < WAYPOINT_REACHED)
+        {
+            agent.path.remove(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // A square of terrain with a vertex every meter, `size` meters across.
+    fn heightfield(size: i32, height: impl Fn(i32, i32) -> f32) -> Vec<[Vec3; 3]> {
+        let vertex = |x: i32, z: i32| Vec3::new(x as f32, height(x, z), z as f32);
+        let mut triangles = vec![];
+        for z in 0..size {
+            for x in 0..size {
+                let (a, b, c, d) = (
+                    vertex(x, z),
+                    vertex(x + 1, z),
+                    vertex(x, z + 1),
+                    vertex(x + 1, z + 1),
+                );
+                triangles.push([a, b, c]);
+                triangles.push([b, d, c]);
+            }
+        }
+        triangles
+    }
+
+    #[test]
+    fn flat_ground_is_a_straight_line() {
+        let grid = NavGrid::from_triangles(heightfield(50, |_, _| 0.0), 1.0, MAX_SLOPE);
+        let (start, goal) = (Vec3::new(2.5, 0., 2.5), Vec3::new(45.5, 0., 31.5));
+        assert_eq!(grid.find_path(start, goal), Some(vec![start, goal]));
+    }
+
+    #[test]
+    fn goes_around_a_cliff() {
+        // A wall across most of the field, with a gap at the far end.
+        let wall = |x: i32, z: i32| {
+            if (20..=22).contains(&x) && z < 40 {
+                50.0
+            } else {
+                0.0
+            }
+        };
+        let grid = NavGrid::from_triangles(heightfield(50, wall), 1.0, MAX_SLOPE);
+        let path = grid
+            .find_path(Vec3::new(5.5, 0., 10.5), Vec3::new(40.5, 0., 10.5))
+            .unwrap();
+        assert!(path.len() > 2, "{path:?}");
+        assert!(path.iter().any(|point| point.z > 40.0), "{path:?}");
+        for leg in path.windows(2) {
+            assert!(grid.walkable_line(leg[0], leg[1]), "{path:?}");
+        }
+    }
+
+    #[test]
+    fn climbs_gentle_slopes() {
+        // A 30 degree ramp up to a plateau.
+        let ramp = |x: i32, _: i32| x.clamp(10, 30) as f32 * 30f32.to_radians().tan();
+        let grid = NavGrid::from_triangles(heightfield(40, ramp), 1.0, MAX_SLOPE);
+        assert!(grid
+            .find_path(Vec3::new(2.5, 0., 5.5), Vec3::new(35.5, 0., 5.5))
+            .is_some());
+    }
+
+    #[test]
+    fn refuses_slopes_too_steep_to_climb() {
+        // A mesa whose sides are all steeper than 45 degrees.
+        let mesa = |x: i32, z: i32| {
+            let from_edge = (x - 5).min(35 - x).min(z - 5).min(35 - z).clamp(0, 5);
+            from_edge as f32 * 60f32.to_radians().tan()
+        };
+        let grid = NavGrid::from_triangles(heightfield(40, mesa), 1.0, MAX_SLOPE);
+        let top = Vec3::new(20.5, 0., 20.5);
+        assert!(grid.ground(top).unwrap().y > 5.0);
+        assert_eq!(grid.find_path(Vec3::new(1.5, 0., 1.5), top), None);
+        // It's all walkable from up there.
+        assert!(grid.find_path(Vec3::new(12.5, 0., 14.5), top).is_some());
+    }
+}
@@ -0,0 +1,596 @@
+use crate::{
+    hitpoints::{Dead, Hp},
+    items::{
+        cast_spells, fire_hitscan, player_cast_input, player_fire_input, CastSpell, FireHitscan,
+        HitscanWeapon, Platform,
+    },
+    mana::Mana,
+    net::{ClientId, ClientMessage, NetId, SequencedInput, ServerMessage, Snapshot, Socket},
+    player::{MovementSet, PlayerInput},
+    prelude::*,
+};
+use bevy::{
+    app::AppExit,
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_rapier3d::{control::MoveShapeOptions, prelude::*};
+use std::{
+    collections::VecDeque,
+    net::{Ipv4Addr, SocketAddr},
+    time::Duration,
+};
+
+// How often the client says hello: until it's welcomed, and after that to keep the connection alive.
+const HELLO_INTERVAL: Duration = Duration::from_millis(500);
+// Unacknowledged inputs kept for replaying. Two seconds of ticks is far more than a round trip should take.
+const MAX_PREDICTIONS: usize = 128;
+// Each input message resends at most this many of the newest unacknowledged inputs.
+const MAX_INPUTS_PER_MESSAGE: usize = 16;
+// How far the predicted player can be from where the replay puts them before they're moved there.
+const RECONCILE_TOLERANCE: f32 = 0.05;
+
+/// Joins the game served at `server`. The local player keeps moving as soon as it's told to, and is moved
+/// back into line whenever that disagrees with the server's character for it: the server's position is
+/// taken, and the inputs that the server hasn't applied yet are replayed on top of it. Everything else that
+/// the server replicates shows up here as a `Replica`.
+pub struct ClientPlugin {
+    pub server: SocketAddr,
+}
+
+impl Plugin for ClientPlugin {
+    fn build(&self, app: &mut App) {
+        let socket = match Socket::bind((Ipv4Addr::UNSPECIFIED, 0)) {
+            Ok(socket) => socket,
+            Err(e) => return error!("Couldn't open a socket to join {}: {e}", self.server),
+        };
+        info!("Joining {}", self.server);
+        socket.send(self.server, &ClientMessage::Hello);
+        app.insert_resource(NetClient::new(socket, self.server))
+            .init_resource::<Replicas>()
+            .add_event::<CastSpell>()
+            .add_event::<FireHitscan>()
+            .add_event::<SnapshotReceived>()
+            .add_systems(
+                PreUpdate,
+                (receive_server_messages, reconcile_player, sync_replicas).chain(),
+            )
+            .add_systems(
+                Update,
+                (
+                    keep_in_touch,
+                    load_replicas,
+                    move_replicas,
+                    forward_casts.after(player_cast_input).before(cast_spells),
+                    forward_shots.after(player_fire_input).before(fire_hitscan),
+                ),
+            )
+            .add_systems(
+                FixedUpdate,
+                (
+                    send_input
+                        .after(MovementSet::Input)
+                        .before(MovementSet::Abilities),
+                    record_translation.after(MovementSet::Integrate),
+                ),
+            )
+            .add_systems(Last, say_goodbye.run_if(on_event::<AppExit>()));
+    }
+}
+
+#[derive(Resource)]
+pub struct NetClient {
+    socket: Socket,
+    server: SocketAddr,
+    /// Given by the server when it welcomes us.
+    pub client: Option<ClientId>,
+    /// The server's character for the local player.
+    pub controlled: Option<NetId>,
+    /// The newest of our inputs that the server has applied.
+    pub acknowledged: u32,
+    next_sequence: u32,
+    predictions: VecDeque<Prediction>,
+    newest_tick: u32,
+    hello_timer: Timer,
+}
+
+/// An input that the local player has already been moved by, but that the server hasn't acknowledged.
+#[derive(Debug, Clone, Copy)]
+struct Prediction {
+    sequence: u32,
+    input: PlayerInput,
+    /// What the input asked of the character controller.
+    translation: Vec3,
+}
+
+impl NetClient {
+    fn new(socket: Socket, server: SocketAddr) -> Self {
+        NetClient {
+            socket,
+            server,
+            client: None,
+            controlled: None,
+            acknowledged: 0,
+            next_sequence: 0,
+            predictions: VecDeque::new(),
+            newest_tick: 0,
+            hello_timer: Timer::new(HELLO_INTERVAL, TimerMode::Repeating),
+        }
+    }
+
+    fn send(&self, message: &ClientMessage) {
+        self.socket.send(self.server, message);
+    }
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct SnapshotReceived(pub Snapshot);
+
+/// The client's entity for each `NetId` that the server replicates.
+#[derive(Resource, Debug, Default, Deref, DerefMut)]
+pub struct Replicas(HashMap<NetId, Entity>);
+
+/// A stand-in for something that the server simulates. It's moved to where each snapshot has it, and on at
+/// its last known velocity in between. Replicated platforms are `Platform`s too, which `update_platforms`
+/// moves along in between, so that the local player can ride them.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct Replica {
+    pub kind: ReplicaKind,
+    pub velocity: Vec3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplicaKind {
+    Player,
+    Projectile { radius: f32 },
+    Target,
+    Platform,
+}
+
+fn receive_server_messages(
+    mut client: ResMut<NetClient>,
+    mut snapshots: EventWriter<SnapshotReceived>,
+) {
+    let server = client.server;
+    for (from, message) in client.socket.receive::<ServerMessage>() {
+        if from != server {
+            continue;
+        }
+        match message {
+            ServerMessage::Welcome { client: id } => {
+                if client.client.is_none() {
+                    info!("Joined {server} as client {}", id.0);
+                }
+                client.client = Some(id);
+            }
+            ServerMessage::Snapshot(snapshot) => {
+                // Snapshots that arrive after a newer one are stale.
+                if snapshot.tick <= client.newest_tick {
+                    continue;
+                }
+                client.newest_tick = snapshot.tick;
+                snapshots.send(SnapshotReceived(snapshot));
+            }
+        }
+    }
+}
+
+/// Says hello, and asks for a character whenever the local player is alive without one.
+fn keep_in_touch(
+    time: Res<Time<Real>>,
+    mut client: ResMut<NetClient>,
+    player: Query<(), With<Player>>,
+) {
+    if !client.hello_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    client.send(&ClientMessage::Hello);
+    if client.client.is_none() || client.controlled.is_some() {
+        return;
+    }
+    if !player.is_empty() {
+        client.send(&ClientMessage::Spawn);
+    }
+}
+
+/// Numbers the local player's input for this tick, remembers it for replaying, and sends it to the server
+/// along with the ones before it that the server hasn't acknowledged.
+fn send_input(mut client: ResMut<NetClient>, player: Query<&PlayerInput, With<Player>>) {
+    let Ok(input) = player.get_single() else {
+        return;
+    };
+    if client.client.is_none() {
+        return;
+    }
+    client.next_sequence += 1;
+    let sequence = client.next_sequence;
+    client.predictions.push_back(Prediction {
+        sequence,
+        input: *input,
+        translation: Vec3::ZERO,
+    });
+    if client.predictions.len() > MAX_PREDICTIONS {
+        client.predictions.pop_front();
+    }
+    let skip = client
+        .predictions
+        .len()
+        .saturating_sub(MAX_INPUTS_PER_MESSAGE);
+    let inputs = client
+        .predictions
+        .iter()
+        .skip(skip)
+        .map(|prediction| SequencedInput {
+            sequence: prediction.sequence,
+            input: prediction.input,
+        })
+        .collect();
+    client.send(&ClientMessage::Input { inputs });
+}
+
+/// Remembers what this tick's input asked of the local player's character controller.
+fn record_translation(
+    mut client: ResMut<NetClient>,
+    player: Query<&KinematicCharacterController, With<Player>>,
+) {
+    let Ok(controller) = player.get_single() else {
+        return;
+    };
+    if let Some(prediction) = client.predictions.back_mut() {
+        prediction.translation = controller.translation.unwrap_or_default();
+    }
+}
+
+/// Where a character controller at `start` ends up after making each of `translations` in turn.
+/// Reconciliation replays the inputs that the server hasn't applied yet this way, from where the server
+/// has the character, to find where the local player should be by now.
+pub fn replay(
+    rapier_context: &mut RapierContext,
+    entity: Entity,
+    collider: &Collider,
+    controller: &KinematicCharacterController,
+    rotation: Quat,
+    start: Vec3,
+    translations: impl IntoIterator<Item = Vec3>,
+) -> Vec3 {
+    let options = MoveShapeOptions {
+        up: controller.up,
+        offset: controller.offset,
+        slide: controller.slide,
+        autostep: controller.autostep,
+        max_slope_climb_angle: controller.max_slope_climb_angle,
+        min_slope_slide_angle: controller.min_slope_slide_angle,
+        // Anything the player pushed was pushed when the input was first predicted.
+        apply_impulse_to_dynamic_bodies: false,
+        snap_to_ground: controller.snap_to_ground,
+        normal_nudge_factor: controller.normal_nudge_factor,
+    };
+    let filter = QueryFilter::default()
+        .exclude_rigid_body(entity)
+        .exclude_collider(entity);
+    let mass = controller.custom_mass.unwrap_or(1.0);
+    translations
+        .into_iter()
+        .fold(start, |position, translation| {
+            let output = rapier_context.move_shape(
+                translation,
+                collider,
+                position,
+                rotation,
+                mass,
+                &options,
+                filter,
+                |_| {},
+            );
+            position + output.effective_translation
+        })
+}
+
+#[allow(clippy::type_complexity)]
+fn reconcile_player(
+    mut commands: Commands,
+    mut client: ResMut<NetClient>,
+    mut snapshots: EventReader<SnapshotReceived>,
+    mut rapier_context: ResMut<RapierContext>,
+    mut player: Query<
+        (
+            Entity,
+            &mut Transform,
+            &KinematicCharacterController,
+            &Collider,
+            &mut Hp,
+            &mut Mana,
+        ),
+        With<Player>,
+    >,
+) {
+    let Some(SnapshotReceived(snapshot)) = snapshots.read().last() else {
+        return;
+    };
+    let had_character = client.controlled.is_some();
+    client.controlled = snapshot.controlled;
+    client.acknowledged = snapshot.acknowledged;
+    client
+        .predictions
+        .retain(|prediction| prediction.sequence > snapshot.acknowledged);
+    let Ok((entity, mut transform, controller, collider, mut hp, mut mana)) =
+        player.get_single_mut()
+    else {
+        return;
+    };
+    let Some(id) = snapshot.controlled else {
+        if had_character {
+            info!("The server's character for the player died");
+            commands.entity(entity).insert(Dead::default());
+        }
+        return;
+    };
+    let Some(state) = snapshot.players.iter().find(|player| player.id == id) else {
+        return;
+    };
+    hp.current = state.hp;
+    hp.max = state.max_hp;
+    mana.current = state.mana;
+    mana.max = state.max_mana;
+
+    let predicted = replay(
+        &mut rapier_context,
+        entity,
+        collider,
+        controller,
+        transform.rotation,
+        state.position,
+        client
+            .predictions
+            .iter()
+            .map(|prediction| prediction.translation),
+    );
+    let error = predicted.distance(transform.translation);
+    if error > RECONCILE_TOLERANCE {
+        debug!("Reconciling the player with the server, {error} off");
+        transform.translation = predicted;
+    }
+}
+
+/// Spawns, moves and despawns replicas to match the newest snapshot.
+fn sync_replicas(
+    mut commands: Commands,
+    mut snapshots: EventReader<SnapshotReceived>,
+    mut replicas: ResMut<Replicas>,
+    mut existing: Query<(
+        &mut Transform,
+        &mut Replica,
+        Option<&mut Hp>,
+        Option<&mut Platform>,
+    )>,
+) {
+    let Some(SnapshotReceived(snapshot)) = snapshots.read().last() else {
+        return;
+    };
+    let players = snapshot
+        .players
+        .iter()
+        .filter(|player| Some(player.id) != snapshot.controlled)
+        .map(|player| {
+            (
+                player.id,
+                Transform::from_translation(player.position)
+                    .with_rotation(Quat::from_axis_angle(Vec3::Y, player.yaw.to_radians())),
+                Replica {
+                    kind: ReplicaKind::Player,
+                    velocity: Vec3::ZERO,
+                },
+                Some(Hp {
+                    current: player.hp,
+                    max: player.max_hp,
+                }),
+                None,
+            )
+        });
+    let projectiles = snapshot.projectiles.iter().map(|projectile| {
+        (
+            projectile.id,
+            Transform::from_translation(projectile.position),
+            Replica {
+                kind: ReplicaKind::Projectile {
+                    radius: projectile.radius,
+                },
+                velocity: projectile.velocity,
+            },
+            None,
+            None,
+        )
+    });
+    let targets = snapshot.targets.iter().map(|target| {
+        (
+            target.id,
+            Transform::from_translation(target.position).with_rotation(target.rotation),
+            Replica {
+                kind: ReplicaKind::Target,
+                velocity: Vec3::ZERO,
+            },
+            Some(Hp {
+                current: target.hp,
+                max: target.max_hp,
+            }),
+            None,
+        )
+    });
+    let platforms = snapshot.platforms.iter().map(|platform| {
+        let transform =
+            Transform::from_translation(platform.position).with_rotation(platform.rotation);
+        (
+            platform.id,
+            transform,
+            Replica {
+                kind: ReplicaKind::Platform,
+                velocity: Vec3::ZERO,
+            },
+            None,
+            Some(Platform {
+                linvel: platform.linvel,
+                angvel: platform.angvel,
+                half_extents: platform.half_extents,
+                previous: transform,
+            }),
+        )
+    });
+
+    let mut seen = HashSet::new();
+    for (id, transform, replica, hp, platform) in
+        players.chain(projectiles).chain(targets).chain(platforms)
+    {
+        seen.insert(id);
+        let current = replicas
+            .get(&id)
+            .and_then(|entity| existing.get_mut(*entity).ok());
+        match current {
+            Some((mut current_transform, mut current_replica, current_hp, current_platform)) => {
+                *current_transform = transform;
+                *current_replica = replica;
+                if let (Some(mut current_hp), Some(hp)) = (current_hp, hp) {
+                    *current_hp = hp;
+                }
+                // Keep `previous`: update_platforms moves it along with the platform.
+                if let (Some(mut current_platform), Some(platform)) = (current_platform, platform) {
+                    current_platform.linvel = platform.linvel;
+                    current_platform.angvel = platform.angvel;
+                }
+            }
+            None => {
+                let mut entity = commands.spawn((transform, replica, id));
+                if let Some(hp) = hp {
+                    entity.insert(hp);
+                }
+                if let Some(platform) = platform {
+                    entity.insert(platform);
+                }
+                replicas.insert(id, entity.id());
+            }
+        }
+    }
+    replicas.retain(|id, entity| {
+        if seen.contains(id) {
+            return true;
+        }
+        if let Some(mut replica) = commands.get_entity(*entity) {
+            replica.despawn_recursive();
+        }
+        false
+    });
+}
+
+/// Gives new replicas something to look at. Platforms are built by `load_platforms`, like any other.
+fn load_replicas(
+    mut commands: Commands,
+    replicas: Query<(Entity, &Replica, &Transform), Added<Replica>>,
+    assets: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (entity, replica, transform) in &replicas {
+        let mut entity = commands.entity(entity);
+        match replica.kind {
+            ReplicaKind::Player => {
+                entity.insert((
+                    Name::new("remote player"),
+                    SceneBundle {
+                        scene: assets.load("Player.gltf#Scene0"),
+                        transform: *transform,
+                        ..default()
+                    },
+                ));
+            }
+            ReplicaKind::Projectile { radius } => {
+                entity.insert((
+                    Name::new("remote fireball"),
+                    PbrBundle {
+                        mesh: meshes.add(Sphere::new(radius)),
+                        material: materials.add(StandardMaterial {
+                            emissive: LinearRgba::new(4.0, 1.2, 0.3, 1.0),
+                            ..default()
+                        }),
+                        transform: *transform,
+                        ..default()
+                    },
+                ));
+            }
+            ReplicaKind::Target => {
+                entity.insert((
+                    Name::new("remote target"),
+                    PbrBundle {
+                        mesh: meshes.add(Sphere::new(1.0)),
+                        material: materials.add(StandardMaterial::from(Palette::Blue.to_color())),
+                        transform: *transform,
+                        ..default()
+                    },
+                ));
+            }
+            ReplicaKind::Platform => {}
+        }
+    }
+}
+
+fn move_replicas(time: Res<Time>, mut replicas: Query<(&mut Transform, &Replica)>) {
+    for (mut transform, replica) in &mut replicas {
+        transform.translation += replica.velocity * time.delta_seconds();
+    }
+}
+
+/// The server casts the local player's spells, so they're sent there instead of being cast here.
+fn forward_casts(client: Res<NetClient>, mut casts: ResMut<Events<CastSpell>>) {
+    for cast in casts.drain() {
+        client.send(&ClientMessage::Cast {
+            slot: cast.slot,
+            direction: *cast.direction,
+        });
+    }
+}
+
+/// Shots are fired by the server too. The weapon still cools down here, so that it isn't asked to fire
+/// faster than it can.
+fn forward_shots(
+    client: Res<NetClient>,
+    mut shots: ResMut<Events<FireHitscan>>,
+    mut weapons: Query<&mut HitscanWeapon>,
+) {
+    for shot in shots.drain() {
+        if let Ok(mut weapon) = weapons.get_mut(shot.shooter) {
+            weapon.restart();
+        }
+        client.send(&ClientMessage::Fire {
+            direction: *shot.direction,
+        });
+    }
+}
+
+fn say_goodbye(client: Res<NetClient>) {
+    client.send(&ClientMessage::Goodbye);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn replay_adds_up_unacknowledged_translations() {
+        let mut rapier_context = RapierContext::default();
+        let controller = KinematicCharacterController {
+            snap_to_ground: None,
+            ..default()
+        };
+        let end = replay(
+            &mut rapier_context,
+            Entity::PLACEHOLDER,
+            &Collider::ball(0.5),
+            &controller,
+            Quat::IDENTITY,
+            Vec3::new(1.0, 2.0, 3.0),
+            [Vec3::X, Vec3::new(0.0, 0.5, -1.0), Vec3::Z * 0.25],
+        );
+        assert!(
+            end.distance(Vec3::new(2.0, 2.5, 2.25)) < 1e-3,
+            "replayed to {end}"
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub mod protocol;
+pub use protocol::*;
+
+pub mod transport;
+pub use transport::*;
+
+pub mod server;
+pub use server::*;
+
+pub mod client;
+pub use client::*;
+
+/// Where the dedicated server listens unless it's told otherwise.
+pub const DEFAULT_SERVER_ADDR: &str = "127.0.0.1:5757";
+
+/// Names a replicated entity the same way on the server and on every client. The server hands these out.
+#[derive(Component, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NetId(pub u32);
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        hitpoints::Hp,
+        items::{Platform, SpellDefinition},
+        mana::Mana,
+        player::{Player, PlayerInput},
+    };
+    use bevy_rapier3d::prelude::*;
+    use std::{thread, time::Duration};
+
+    fn headless_app() -> App {
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<Mesh>()
+            .init_asset::<StandardMaterial>()
+            .init_asset::<Scene>()
+            .init_asset::<SpellDefinition>()
+            .init_resource::<RapierContext>();
+        app
+    }
+
+    fn server_app() -> App {
+        let mut app = headless_app();
+        app.add_plugins(ServerPlugin {
+            addr: "127.0.0.1:0".parse().unwrap(),
+        });
+        app
+    }
+
+    fn client_app(server: &App) -> App {
+        let mut app = headless_app();
+        app.add_plugins(ClientPlugin {
+            server: server.world().resource::<NetServer>().local_addr(),
+        });
+        app
+    }
+
+    fn spawn_local_player(app: &mut App, position: Vec3) -> Entity {
+        app.world_mut()
+            .spawn((
+                Player,
+                PlayerInput::default(),
+                TransformBundle::from_transform(Transform::from_translation(position)),
+                KinematicCharacterController::default(),
+                Collider::ball(0.5),
+                Hp {
+                    current: 100,
+                    max: 100,
+                },
+                Mana {
+                    current: 100,
+                    max: 100,
+                },
+            ))
+            .id()
+    }
+
+    /// Updates the server and then every client until `done`, or fails after a few seconds.
+    fn run_until(
+        server: &mut App,
+        clients: &mut [App],
+        mut done: impl FnMut(&mut App, &mut [App]) -> bool,
+    ) {
+        for _ in 0..1000 {
+            server.update();
+            for client in clients.iter_mut() {
+                client.update();
+            }
+            if done(server, clients) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        panic!("gave up waiting on the network");
+    }
+
+    fn controlled(client: &App) -> Option<NetId> {
+        client.world().resource::<NetClient>().controlled
+    }
+
+    /// The server's entity for a `NetId`.
+    fn server_entity(server: &mut App, id: NetId) -> Entity {
+        server
+            .world_mut()
+            .query::<(Entity, &NetId)>()
+            .iter(server.world())
+            .find_map(|(entity, net_id)| (*net_id == id).then_some(entity))
+            .unwrap()
+    }
+
+    fn replicas(client: &mut App, kind: ReplicaKind) -> Vec<Entity> {
+        client
+            .world_mut()
+            .query::<(Entity, &Replica)>()
+            .iter(client.world())
+            .filter_map(|(entity, replica)| (replica.kind == kind).then_some(entity))
+            .collect()
+    }
+
+    #[test]
+    fn two_clients_play_together() {
+        let mut server = server_app();
+        let mut clients = [client_app(&server), client_app(&server)];
+        let a = spawn_local_player(&mut clients[0], Vec3::new(0.0, 10.0, 0.0));
+        let b = spawn_local_player(&mut clients[1], Vec3::new(5.0, 10.0, 0.0));
+
+        // Both join, get a character each, and see the other's.
+        run_until(&mut server, &mut clients, |_, clients| {
+            clients.iter_mut().all(|client| {
+                controlled(client).is_some() && replicas(client, ReplicaKind::Player).len() == 1
+            })
+        });
+        let mut characters = server.world_mut().query::<(&NetId, &PlayerInput)>();
+        assert_eq!(characters.iter(server.world()).count(), 2);
+        let a_id = controlled(&clients[0]).unwrap();
+        let b_id = controlled(&clients[1]).unwrap();
+        assert_ne!(a_id, b_id);
+
+        // Inputs reach the server's character and are acknowledged.
+        clients[0]
+            .world_mut()
+            .get_mut::<PlayerInput>(a)
+            .unwrap()
+            .yaw = 90.0;
+        let a_character = server_entity(&mut server, a_id);
+        run_until(&mut server, &mut clients, |server, clients| {
+            server.world().get::<PlayerInput>(a_character).unwrap().yaw == 90.0
+                && clients[0].world().resource::<NetClient>().acknowledged > 0
+        });
+
+        // Damage on the server shows on the player that took it, and on the other client's replica of them.
+        let b_character = server_entity(&mut server, b_id);
+        server
+            .world_mut()
+            .get_mut::<Hp>(b_character)
+            .unwrap()
+            .current = 40;
+        server
+            .world_mut()
+            .get_mut::<Mana>(b_character)
+            .unwrap()
+            .current = 15;
+        run_until(&mut server, &mut clients, |_, clients| {
+            let replica = replicas(&mut clients[0], ReplicaKind::Player)[0];
+            clients[1].world().get::<Hp>(b).unwrap().current == 40
+                && clients[1].world().get::<Mana>(b).unwrap().current == 15
+                && clients[0].world().get::<Hp>(replica).unwrap().current == 40
+        });
+
+        // Where the server moves a character, reconciliation moves the player after it.
+        server
+            .world_mut()
+            .get_mut::<Transform>(a_character)
+            .unwrap()
+            .translation = Vec3::new(-20.0, 10.0, 0.0);
+        run_until(&mut server, &mut clients, |_, clients| {
+            let position = clients[0].world().get::<Transform>(a).unwrap().translation;
+            position.distance(Vec3::new(-20.0, 10.0, 0.0)) < 0.01
+        });
+    }
+
+    #[test]
+    fn platforms_come_and_go_with_the_server() {
+        let mut server = server_app();
+        let mut clients = [client_app(&server)];
+        let platform = server
+            .world_mut()
+            .spawn((
+                Platform {
+                    linvel: Vec3::X,
+                    half_extents: Vec3::new(4.0, 0.5, 4.0),
+                    ..default()
+                },
+                TransformBundle::from_transform(Transform::from_xyz(1.0, 2.0, 3.0)),
+            ))
+            .id();
+        run_until(&mut server, &mut clients, |_, clients| {
+            replicas(&mut clients[0], ReplicaKind::Platform).len() == 1
+        });
+        let replica = replicas(&mut clients[0], ReplicaKind::Platform)[0];
+        let copy = clients[0].world().get::<Platform>(replica).unwrap();
+        assert_eq!(copy.half_extents, Vec3::new(4.0, 0.5, 4.0));
+        assert_eq!(copy.linvel, Vec3::X);
+
+        server.world_mut().despawn(platform);
+        run_until(&mut server, &mut clients, |_, clients| {
+            replicas(&mut clients[0], ReplicaKind::Platform).is_empty()
+        });
+    }
+}
@@ -0,0 +1,151 @@
+use crate::{net::NetId, player::PlayerInput};
+use anyhow::Result;
+use bevy::prelude::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Identifies a client for as long as it's connected to the server.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientId(pub u32);
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ClientMessage {
+    /// Asks to join, and keeps the connection alive once joined. Answered with `Welcome`.
+    Hello,
+    /// Asks for a character for the client's freshly spawned player. The server picks where it spawns, and
+    /// the player is moved there. Resent until a snapshot says that the client controls a character.
+    Spawn,
+    /// The client's inputs that the server hasn't acknowledged yet, oldest first. Every input is resent
+    /// until it's acknowledged, so a lost packet doesn't lose a jump.
+    Input {
+        inputs: Vec<SequencedInput>,
+    },
+    /// Asks for the client's character to cast a spell, aimed along `direction` from the character's eyes.
+    /// See `CastSpell`.
+    Cast {
+        slot: usize,
+        direction: Vec3,
+    },
+    /// Asks for the client's character to fire its hitscan weapon along `direction` from its eyes. See
+    /// `FireHitscan`.
+    Fire {
+        direction: Vec3,
+    },
+    Goodbye,
+}
+
+/// A `PlayerInput` numbered in the order that the client sampled it.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct SequencedInput {
+    pub sequence: u32,
+    pub input: PlayerInput,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum ServerMessage {
+    Welcome { client: ClientId },
+    Snapshot(Snapshot),
+}
+
+/// Everything replicated, as the server had it at the end of a frame.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    /// Counts up with every snapshot the server sends, so that clients can drop ones that arrive late.
+    pub tick: u32,
+    /// The character that the receiving client controls, if it has one.
+    pub controlled: Option<NetId>,
+    /// The receiving client's newest input that the server has applied.
+    pub acknowledged: u32,
+    pub players: Vec<PlayerState>,
+    pub projectiles: Vec<ProjectileState>,
+    pub targets: Vec<TargetState>,
+    pub platforms: Vec<PlatformState>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PlayerState {
+    pub id: NetId,
+    pub position: Vec3,
+    /// Heading in degrees, as in `PlayerInput`.
+    pub yaw: f32,
+    pub hp: u32,
+    pub max_hp: u32,
+    pub mana: u32,
+    pub max_mana: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ProjectileState {
+    pub id: NetId,
+    pub position: Vec3,
+    pub velocity: Vec3,
+    pub radius: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct TargetState {
+    pub id: NetId,
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub hp: u32,
+    pub max_hp: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct PlatformState {
+    pub id: NetId,
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub linvel: Vec3,
+    pub angvel: Vec3,
+    pub half_extents: Vec3,
+}
+
+pub fn encode<T: Serialize>(message: &T) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    ciborium::into_writer(message, &mut bytes)?;
+    Ok(bytes)
+}
+
+pub fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+    Ok(ciborium::from_reader(bytes)?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn messages_survive_the_wire() {
+        let input = ClientMessage::Input {
+            inputs: vec![SequencedInput {
+                sequence: 7,
+                input: PlayerInput {
+                    movement: Vec3::new(0.0, 1.0, -1.0),
+                    yaw: 90.0,
+                    sprint: true,
+                    ..default()
+                },
+            }],
+        };
+        let decoded: ClientMessage = decode(&encode(&input).unwrap()).unwrap();
+        assert_eq!(decoded, input);
+
+        let snapshot = ServerMessage::Snapshot(Snapshot {
+            tick: 3,
+            controlled: Some(NetId(1)),
+            acknowledged: 7,
+            platforms: vec![PlatformState {
+                id: NetId(2),
+                position: Vec3::Y,
+                rotation: Quat::from_rotation_y(1.0),
+                linvel: Vec3::X,
+                angvel: Vec3::ZERO,
+                half_extents: Vec3::splat(2.0),
+            }],
+            ..default()
+        });
+        let decoded: ServerMessage = decode(&encode(&snapshot).unwrap()).unwrap();
+        assert_eq!(decoded, snapshot);
+        assert!(decode::<ServerMessage>(&[0xff, 0x00]).is_err());
+    }
+}
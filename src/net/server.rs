@@ -0,0 +1,456 @@
+use crate::{
+    hitpoints::Hp,
+    items::{CastSpell, FireHitscan, Fireball, Platform},
+    mana::Mana,
+    net::{
+        ClientId, ClientMessage, NetId, PlatformState, PlayerState, ProjectileState,
+        SequencedInput, ServerMessage, Snapshot, Socket, TargetState,
+    },
+    objects::Target,
+    player::{character_bundle, fallback_spawn, MovementSet, PlayerInput, SpawnPoint, EYE_OFFSET},
+    prelude::*,
+    progression::Progression,
+    respawn::{choose_spawn, Checkpoint, RespawnSettings},
+};
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier3d::prelude::*;
+use std::{collections::VecDeque, net::SocketAddr, time::Duration};
+
+// Clients that haven't been heard from for this long are dropped, along with their characters.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+// When a client's inputs pile up faster than the server's ticks apply them, the oldest are skipped until
+// this many are left.
+const MAX_INPUT_BACKLOG: usize = 8;
+
+/// Serves the game to clients that connect to `addr`. Each client's player gets a character here that is
+/// moved by the inputs the client sends, and every client is sent a `Snapshot` of the replicated entities
+/// each frame. The dedicated `server` binary runs this headless; a game that hosts runs it next to its own
+/// player, who is replicated like everyone else.
+pub struct ServerPlugin {
+    pub addr: SocketAddr,
+}
+
+impl Plugin for ServerPlugin {
+    fn build(&self, app: &mut App) {
+        let socket = match Socket::bind(self.addr) {
+            Ok(socket) => socket,
+            Err(e) => return error!("Couldn't serve on {}: {e}", self.addr),
+        };
+        info!("Serving on {}", self.addr);
+        app.insert_resource(NetServer::new(socket))
+            .init_resource::<RespawnSettings>()
+            .init_resource::<Checkpoint>()
+            .add_event::<CastSpell>()
+            .add_event::<FireHitscan>()
+            .add_systems(PreUpdate, (receive_client_messages, tag_replicated).chain())
+            .add_systems(FixedUpdate, apply_client_inputs.in_set(MovementSet::Input))
+            .add_systems(
+                PostUpdate,
+                (drop_idle_clients, send_snapshots)
+                    .chain()
+                    .after(PhysicsSet::Writeback),
+            );
+    }
+}
+
+/// A character that the server runs on behalf of a client.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct RemoteCharacter(pub ClientId);
+
+#[derive(Resource)]
+pub struct NetServer {
+    socket: Socket,
+    clients: HashMap<SocketAddr, Connection>,
+    next_client: u32,
+    next_id: u32,
+    tick: u32,
+}
+
+struct Connection {
+    id: ClientId,
+    character: Option<Entity>,
+    /// Inputs that have arrived but haven't been applied yet, oldest first.
+    inputs: VecDeque<SequencedInput>,
+    /// The newest input that was applied, or dropped for want of a character to apply it to.
+    acknowledged: u32,
+    last_heard: Duration,
+}
+
+impl NetServer {
+    fn new(socket: Socket) -> Self {
+        NetServer {
+            socket,
+            clients: HashMap::default(),
+            next_client: 0,
+            next_id: 0,
+            tick: 0,
+        }
+    }
+
+    /// Where the server is listening, e.g. to find the port it was given when bound to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.socket
+            .local_addr()
+            .expect("The server's socket should be bound.")
+    }
+}
+
+fn allocate_id(next_id: &mut u32) -> NetId {
+    *next_id += 1;
+    NetId(*next_id)
+}
+
+/// Handles what clients ask for. Clients only say what their players want to do: where characters spawn,
+/// how far an input can move them and where spells and shots come from is up to the server.
+#[allow(clippy::too_many_arguments)]
+fn receive_client_messages(
+    mut commands: Commands,
+    mut server: ResMut<NetServer>,
+    time: Res<Time<Real>>,
+    assets: Res<AssetServer>,
+    settings: Res<RespawnSettings>,
+    checkpoint: Res<Checkpoint>,
+    spawn_points: Query<&Transform, With<SpawnPoint>>,
+    transforms: Query<&Transform>,
+    characters: Query<(), With<PlayerInput>>,
+    mut casts: EventWriter<CastSpell>,
+    mut fire: EventWriter<FireHitscan>,
+) {
+    let now = time.elapsed();
+    let NetServer {
+        socket,
+        clients,
+        next_client,
+        next_id,
+        ..
+    } = &mut *server;
+    for (from, message) in socket.receive::<ClientMessage>() {
+        if message == ClientMessage::Hello {
+            let connection = clients.entry(from).or_insert_with(|| {
+                *next_client += 1;
+                info!("Client {next_client} joined from {from}");
+                Connection {
+                    id: ClientId(*next_client),
+                    character: None,
+                    inputs: VecDeque::new(),
+                    acknowledged: 0,
+                    last_heard: now,
+                }
+            });
+            socket.send(
+                from,
+                &ServerMessage::Welcome {
+                    client: connection.id,
+                },
+            );
+        }
+        // Everything else is only heard from clients that have said hello.
+        let Some(connection) = clients.get_mut(&from) else {
+            continue;
+        };
+        connection.last_heard = now;
+        match message {
+            ClientMessage::Hello => {}
+            ClientMessage::Spawn => {
+                // Spawn requests are resent until they're answered, so one might arrive after the character.
+                if connection
+                    .character
+                    .is_some_and(|character| characters.contains(character))
+                {
+                    continue;
+                }
+                let id = allocate_id(next_id);
+                // Everyone shares the checkpoint that any of them reached last.
+                let points: Vec<Transform> = spawn_points.iter().copied().collect();
+                let checkpoint = checkpoint.0.and_then(|entity| transforms.get(entity).ok());
+                let transform = choose_spawn(
+                    settings.policy,
+                    &points,
+                    checkpoint.copied(),
+                    None,
+                    &mut rand::thread_rng(),
+                )
+                .unwrap_or_else(fallback_spawn);
+                // Clients' progression isn't replicated, so their characters have starting stats.
+                let character = commands
+                    .spawn((
+                        Name::new(format!("client {}", connection.id.0)),
+                        SpatialBundle::from_transform(transform),
+                        character_bundle(&assets, &Progression::default().stats()),
+                        RemoteCharacter(connection.id),
+                        id,
+                    ))
+                    .id();
+                info!("Spawned a character for client {}", connection.id.0);
+                connection.character = Some(character);
+                connection.inputs.clear();
+            }
+            ClientMessage::Input { inputs } => {
+                let newest = connection
+                    .inputs
+                    .back()
+                    .map_or(connection.acknowledged, |input| input.sequence);
+                connection.inputs.extend(
+                    inputs
+                        .into_iter()
+                        .filter(|input| input.sequence > newest)
+                        .map(|input| SequencedInput {
+                            input: within_reach(input.input),
+                            ..input
+                        }),
+                );
+            }
+            ClientMessage::Cast { slot, direction } => {
+                let Some((caster, eye, direction)) = aim(connection, &transforms, direction) else {
+                    continue;
+                };
+                casts.send(CastSpell {
+                    caster,
+                    slot,
+                    origin: eye,
+                    direction,
+                });
+            }
+            ClientMessage::Fire { direction } => {
+                let Some((shooter, eye, direction)) = aim(connection, &transforms, direction)
+                else {
+                    continue;
+                };
+                fire.send(FireHitscan {
+                    shooter,
+                    origin: eye,
+                    direction,
+                });
+            }
+            ClientMessage::Goodbye => {
+                info!("Client {} left", connection.id.0);
+                if let Some(mut character) = connection
+                    .character
+                    .and_then(|character| commands.get_entity(character))
+                {
+                    character.despawn_recursive();
+                }
+                clients.remove(&from);
+            }
+        }
+    }
+}
+
+/// The client's character, where its eyes are, and `direction` if it is one.
+fn aim(
+    connection: &Connection,
+    transforms: &Query<&Transform>,
+    direction: Vec3,
+) -> Option<(Entity, Vec3, Dir3)> {
+    let character = connection.character?;
+    let eye = transforms.get(character).ok()?.transform_point(EYE_OFFSET);
+    Some((character, eye, Dir3::new(direction).ok()?))
+}
+
+/// Limits an input to what the keyboard could have asked for: at most a unit of walking, and at most one
+/// jump. Anything that isn't a number at all is dropped.
+fn within_reach(input: PlayerInput) -> PlayerInput {
+    let movement = if input.movement.is_finite() {
+        input.movement
+    } else {
+        Vec3::ZERO
+    };
+    let walk = movement.xz().clamp_length_max(1.0);
+    PlayerInput {
+        movement: Vec3::new(walk.x, movement.y.clamp(0.0, 1.0), walk.y),
+        yaw: if input.yaw.is_finite() {
+            input.yaw
+        } else {
+            0.0
+        },
+        ..input
+    }
+}
+
+/// Gives everything that clients should see a `NetId` as it appears. Clients' characters are given theirs
+/// when they're spawned.
+#[allow(clippy::type_complexity)]
+fn tag_replicated(
+    mut commands: Commands,
+    mut server: ResMut<NetServer>,
+    added: Query<
+        Entity,
+        (
+            Without<NetId>,
+            Or<(
+                Added<Player>,
+                Added<Fireball>,
+                Added<Target>,
+                Added<Platform>,
+            )>,
+        ),
+    >,
+) {
+    for entity in &added {
+        commands
+            .entity(entity)
+            .insert(allocate_id(&mut server.next_id));
+    }
+}
+
+/// Moves each client's character by the client's next input: one input per tick, as the client sampled
+/// them.
+fn apply_client_inputs(
+    mut server: ResMut<NetServer>,
+    mut characters: Query<(&mut PlayerInput, &mut Transform), With<RemoteCharacter>>,
+) {
+    for connection in server.clients.values_mut() {
+        let character = connection
+            .character
+            .and_then(|character| characters.get_mut(character).ok());
+        let Some((mut input, mut transform)) = character else {
+            // The character died, or hasn't been asked for yet. The client still needs to hear that its
+            // inputs have been dealt with, or it would keep replaying them.
+            connection.character = None;
+            if let Some(newest) = connection.inputs.back() {
+                connection.acknowledged = newest.sequence;
+            }
+            connection.inputs.clear();
+            continue;
+        };
+        while connection.inputs.len() > MAX_INPUT_BACKLOG {
+            connection.inputs.pop_front();
+        }
+        *input = match connection.inputs.pop_front() {
+            Some(next) => {
+                connection.acknowledged = next.sequence;
+                next.input
+            }
+            // The client's next input is late: keep walking the same way, without jumping or dashing again.
+            None => PlayerInput {
+                movement: input.movement.with_y(0.0),
+                dash: false,
+                ..*input
+            },
+        };
+        transform.rotation = Quat::from_axis_angle(Vec3::Y, input.yaw.to_radians());
+    }
+}
+
+fn drop_idle_clients(mut commands: Commands, mut server: ResMut<NetServer>, time: Res<Time<Real>>) {
+    let now = time.elapsed();
+    server.clients.retain(|addr, connection| {
+        if now.saturating_sub(connection.last_heard) < CLIENT_TIMEOUT {
+            return true;
+        }
+        info!("Client {} at {addr} timed out", connection.id.0);
+        if let Some(mut character) = connection
+            .character
+            .and_then(|character| commands.get_entity(character))
+        {
+            character.despawn_recursive();
+        }
+        false
+    });
+}
+
+#[allow(clippy::type_complexity)]
+fn send_snapshots(
+    mut server: ResMut<NetServer>,
+    players: Query<(&NetId, &Transform, &PlayerInput, &Hp, &Mana)>,
+    projectiles: Query<(&NetId, &Transform, Option<&Velocity>, &Collider), With<Fireball>>,
+    targets: Query<(&NetId, &GlobalTransform, &Hp), With<Target>>,
+    platforms: Query<(&NetId, &Transform, &Platform)>,
+    ids: Query<&NetId>,
+) {
+    if server.clients.is_empty() {
+        return;
+    }
+    server.tick += 1;
+    let mut snapshot = Snapshot {
+        tick: server.tick,
+        players: players
+            .iter()
+            .map(|(id, transform, input, hp, mana)| PlayerState {
+                id: *id,
+                position: transform.translation,
+                yaw: input.yaw,
+                hp: hp.current,
+                max_hp: hp.max,
+                mana: mana.current,
+                max_mana: mana.max,
+            })
+            .collect(),
+        projectiles: projectiles
+            .iter()
+            .map(|(id, transform, velocity, collider)| ProjectileState {
+                id: *id,
+                position: transform.translation,
+                velocity: velocity.map_or(Vec3::ZERO, |velocity| velocity.linvel),
+                radius: collider.as_ball().map_or(0.5, |ball| ball.radius()),
+            })
+            .collect(),
+        targets: targets
+            .iter()
+            .map(|(id, transform, hp)| {
+                let (_, rotation, position) = transform.to_scale_rotation_translation();
+                TargetState {
+                    id: *id,
+                    position,
+                    rotation,
+                    hp: hp.current,
+                    max_hp: hp.max,
+                }
+            })
+            .collect(),
+        platforms: platforms
+            .iter()
+            .map(|(id, transform, platform)| PlatformState {
+                id: *id,
+                position: transform.translation,
+                rotation: transform.rotation,
+                linvel: platform.linvel,
+                angvel: platform.angvel,
+                half_extents: platform.half_extents,
+            })
+            .collect(),
+        ..default()
+    };
+    for (addr, connection) in &server.clients {
+        snapshot.controlled = connection
+            .character
+            .and_then(|character| ids.get(character).ok())
+            .copied();
+        snapshot.acknowledged = connection.acknowledged;
+        server
+            .socket
+            .send(*addr, &ServerMessage::Snapshot(snapshot.clone()));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn inputs_are_kept_within_reach() {
+        let cheat = PlayerInput {
+            movement: Vec3::new(30.0, 50.0, -40.0),
+            yaw: 45.0,
+            sprint: true,
+            ..default()
+        };
+        let input = within_reach(cheat);
+        assert!((input.movement.xz().length() - 1.0).abs() < 1e-5);
+        assert_eq!(input.movement.y, 1.0);
+        assert_eq!(input.yaw, 45.0);
+        assert!(input.sprint);
+
+        let falling = within_reach(PlayerInput {
+            movement: Vec3::new(0.5, -3.0, 0.0),
+            ..default()
+        });
+        assert_eq!(falling.movement, Vec3::new(0.5, 0.0, 0.0));
+        let garbage = within_reach(PlayerInput {
+            movement: Vec3::NAN,
+            yaw: f32::INFINITY,
+            ..default()
+        });
+        assert_eq!(garbage, PlayerInput::default());
+    }
+}
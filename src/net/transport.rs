@@ -0,0 +1,66 @@
+use crate::net::protocol::{decode, encode};
+use bevy::log::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    io::{self, ErrorKind},
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+// Comfortably over the largest UDP payload.
+const RECEIVE_BUFFER: usize = 1 << 16;
+
+/// A non-blocking UDP socket that speaks the `protocol` messages. Datagrams are unreliable and unordered:
+/// anything that has to arrive is resent until the other side acknowledges it.
+pub struct Socket {
+    socket: UdpSocket,
+    buffer: Vec<u8>,
+}
+
+impl Socket {
+    pub fn bind(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+        Ok(Socket {
+            socket,
+            buffer: vec![0; RECEIVE_BUFFER],
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Sends `message` to `to`. A lost message is as good as a dropped one, so failures are only logged.
+    pub fn send<T: Serialize>(&self, to: SocketAddr, message: &T) {
+        let bytes = match encode(message) {
+            Ok(bytes) => bytes,
+            Err(e) => return error!("Couldn't encode a message for {to}: {e}"),
+        };
+        if let Err(e) = self.socket.send_to(&bytes, to) {
+            warn!("Couldn't send {} bytes to {to}: {e}", bytes.len());
+        }
+    }
+
+    /// Everything that has arrived since the last call, with who sent it. Datagrams that don't decode are
+    /// skipped.
+    pub fn receive<T: DeserializeOwned>(&mut self) -> Vec<(SocketAddr, T)> {
+        let mut messages = vec![];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut self.buffer) {
+                Ok(received) => received,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                // On some platforms an unreachable peer shows up as an error on the next receive.
+                Err(e) if e.kind() == ErrorKind::ConnectionReset => continue,
+                Err(e) => {
+                    warn!("Couldn't receive: {e}");
+                    break;
+                }
+            };
+            match decode(&self.buffer[..len]) {
+                Ok(message) => messages.push((from, message)),
+                Err(e) => warn!("Dropping a malformed message from {from}: {e}"),
+            }
+        }
+        messages
+    }
+}
@@ -0,0 +1,186 @@
+use crate::{
+    player::{character_sensor, PlayerInput},
+    prelude::*,
+    respawn::Checkpoint,
+};
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+pub struct CheckpointsPlugin;
+
+impl Plugin for CheckpointsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<CheckpointMaterials>();
+        app.add_systems(PreUpdate, load_checkpoints);
+        app.add_systems(Update, (reach_checkpoint_volumes, show_checkpoints).chain());
+    }
+}
+
+const BEACON_RADIUS: f32 = 0.3;
+
+/// A box that becomes the player's respawn point when they walk into it. Levels place it with a Transform;
+/// `load_checkpoints` fills the box with a sensor and stands a beacon through the middle of it, which lights
+/// up once it's reached. The player respawns at the Transform, facing along its forward.
+///
+/// Saves match checkpoints up by `name`, so names should be unique within a level.
+#[derive(Component, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CheckpointVolume {
+    pub name: String,
+    pub half_extents: Vec3,
+}
+
+impl Default for CheckpointVolume {
+    fn default() -> Self {
+        CheckpointVolume {
+            name: "checkpoint".into(),
+            half_extents: Vec3::new(4.0, 3.0, 4.0),
+        }
+    }
+}
+
+/// Added to a `CheckpointVolume` the first time the player reaches it.
+#[derive(Component, Debug, Default)]
+pub struct CheckpointReached;
+
+/// What a checkpoint's beacon looks like: not reached yet, reached before, and the one the player will
+/// respawn at.
+#[derive(Resource)]
+struct CheckpointMaterials {
+    unreached: Handle<StandardMaterial>,
+    reached: Handle<StandardMaterial>,
+    current: Handle<StandardMaterial>,
+}
+
+impl FromWorld for CheckpointMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.resource_mut::<Assets<StandardMaterial>>();
+        let yellow = Palette::Yellow.to_color();
+        CheckpointMaterials {
+            unreached: materials.add(StandardMaterial {
+                base_color: Palette::Blue.to_color().with_alpha(0.4),
+                alpha_mode: AlphaMode::Blend,
+                ..default()
+            }),
+            reached: materials.add(yellow.with_alpha(0.6)),
+            current: materials.add(StandardMaterial {
+                base_color: yellow,
+                emissive: LinearRgba::from(yellow) * 20.0,
+                ..default()
+            }),
+        }
+    }
+}
+
+fn load_checkpoints(
+    mut commands: Commands,
+    checkpoints: Query<(Entity, &CheckpointVolume, Option<&Transform>), Added<CheckpointVolume>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    materials: Res<CheckpointMaterials>,
+) {
+    for (entity, checkpoint, transform) in &checkpoints {
+        let transform = transform.copied().unwrap_or_default();
+        info!(
+            "Spawning checkpoint {:?} at {}",
+            checkpoint.name, transform.translation
+        );
+        let half = checkpoint.half_extents;
+        commands.entity(entity).insert((
+            Name::new(format!("checkpoint: {}", checkpoint.name)),
+            PbrBundle {
+                mesh: meshes.add(Cylinder::new(BEACON_RADIUS, half.y * 2.0)),
+                material: materials.unreached.clone(),
+                transform,
+                ..default()
+            },
+            Collider::cuboid(half.x, half.y, half.z),
+            character_sensor(),
+        ));
+    }
+}
+
+fn reach_checkpoint_volumes(
+    mut commands: Commands,
+    mut collisions: EventReader<CollisionEvent>,
+    checkpoints: Query<&CheckpointVolume>,
+    players: Query<(), With<PlayerInput>>,
+    mut checkpoint: ResMut<Checkpoint>,
+) {
+    for event in collisions.read() {
+        let CollisionEvent::Started(a, b, _) = event else {
+            continue;
+        };
+        let (entity, volume, other) = match (checkpoints.get(*a), checkpoints.get(*b)) {
+            (Ok(volume), _) => (*a, volume, *b),
+            (_, Ok(volume)) => (*b, volume, *a),
+            _ => continue,
+        };
+        if !players.contains(other) || checkpoint.0 == Some(entity) {
+            continue;
+        }
+        info!("Reached checkpoint {:?}", volume.name);
+        checkpoint.0 = Some(entity);
+        commands.entity(entity).insert(CheckpointReached);
+    }
+}
+
+fn show_checkpoints(
+    checkpoint: Res<Checkpoint>,
+    materials: Res<CheckpointMaterials>,
+    mut beacons: Query<
+        (
+            Entity,
+            Has<CheckpointReached>,
+            &mut Handle<StandardMaterial>,
+        ),
+        With<CheckpointVolume>,
+    >,
+) {
+    for (entity, reached, mut material) in &mut beacons {
+        let wanted = if checkpoint.0 == Some(entity) {
+            &materials.current
+        } else if reached {
+            &materials.reached
+        } else {
+            &materials.unreached
+        };
+        if *material != *wanted {
+            *material = wanted.clone();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use bevy_rapier3d::rapier::geometry::CollisionEventFlags;
+
+    #[test]
+    fn only_players_reach_checkpoints() {
+        let mut world = World::new();
+        world.init_resource::<Events<CollisionEvent>>();
+        world.init_resource::<Checkpoint>();
+        let player = world.spawn((Player, PlayerInput::default())).id();
+        let enemy = world.spawn_empty().id();
+        let first = world.spawn(CheckpointVolume::default()).id();
+        let second = world.spawn(CheckpointVolume::default()).id();
+        let touch = |world: &mut World, a, b| {
+            world.send_event(CollisionEvent::Started(a, b, CollisionEventFlags::SENSOR));
+            world.run_system_once(reach_checkpoint_volumes);
+            // Every run_system_once starts a fresh reader, which would read this touch again next time.
+            world.resource_mut::<Events<CollisionEvent>>().clear();
+        };
+
+        touch(&mut world, enemy, first);
+        assert_eq!(world.resource::<Checkpoint>().0, None);
+        touch(&mut world, first, player);
+        assert_eq!(world.resource::<Checkpoint>().0, Some(first));
+        touch(&mut world, player, second);
+        assert_eq!(world.resource::<Checkpoint>().0, Some(second));
+        assert!(world.get::<CheckpointReached>(first).is_some());
+        assert!(world.get::<CheckpointReached>(second).is_some());
+    }
+}
@@ -1,2 +1,8 @@
 pub mod targets;
 pub use targets::*;
+
+pub mod target_range;
+pub use target_range::*;
+
+pub mod checkpoints;
+pub use checkpoints::*;
@@ -0,0 +1,412 @@
+use crate::{
+    hitpoints::{DamageSet, Dead},
+    items::HitscanShot,
+    objects::{Target, Targets},
+    prelude::*,
+};
+use bevy::prelude::*;
+use bevy_firework::{
+    core::{BlendMode, ParticleSpawnerBundle, ParticleSpawnerSettings},
+    emission_shape::EmissionShape,
+};
+use bevy_utilitarian::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::f32::consts::PI;
+
+/// Scoring for target practice: a session starts when targets appear and ends once the last one is broken,
+/// at which point it goes in the local high-score table.
+pub struct TargetRangePlugin;
+
+impl Plugin for TargetRangePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<TargetRangeCommand>();
+        app.init_resource::<TargetRange>();
+        app.insert_resource(HighScores::load());
+        app.observe(score_broken_target);
+        app.add_systems(OnEnter(GameState::InGame), spawn_score_text);
+        app.add_systems(
+            Update,
+            (
+                run_commands,
+                start_session,
+                count_shots,
+                finish_session.after(DamageSet),
+                update_score_text,
+                update_shards,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Where the high-score table is kept, relative to the working directory.
+#[cfg(feature = "serde")]
+const HIGH_SCORES_PATH: &str = "target_range_scores.ron";
+const MAX_HIGH_SCORES: usize = 10;
+const SHARDS_LIFETIME: f32 = 1.0;
+
+/// Sent by the HUD's buttons.
+#[derive(Event, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetRangeCommand {
+    /// Removes every target and abandons the session.
+    Clear,
+    /// Puts every group of targets back where it started and starts a new session.
+    Reset,
+}
+
+/// The session in progress.
+#[derive(Resource, Debug, Default)]
+pub struct TargetRange {
+    pub running: bool,
+    pub score: u32,
+    /// Hitscan shots, and how many of them hit a target. Spells aren't counted, since where a projectile
+    /// lands isn't known when it's thrown.
+    pub shots: u32,
+    pub hits: u32,
+    pub broken: u32,
+    pub elapsed: f32,
+    /// Where the last finished session placed in `HighScores`, if it made the table.
+    pub last_rank: Option<usize>,
+}
+
+impl TargetRange {
+    /// The fraction of hitscan shots that hit a target.
+    pub fn accuracy(&self) -> f32 {
+        if self.shots == 0 {
+            return 0.0;
+        }
+        self.hits as f32 / self.shots as f32
+    }
+
+    fn restart(&mut self) {
+        *self = TargetRange {
+            running: true,
+            last_rank: self.last_rank,
+            ..default()
+        };
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HighScore {
+    pub score: u32,
+    /// Hitscan accuracy, from 0 to 1.
+    pub accuracy: f32,
+    /// Seconds taken to break every target.
+    pub time: f32,
+}
+
+/// The best finished sessions, best first. Ties on score go to the quicker session.
+#[derive(Resource, Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HighScores {
+    pub entries: Vec<HighScore>,
+}
+
+impl HighScores {
+    /// Adds `entry` to the table, returning its place (from 0) if it made the cut.
+    pub fn insert(&mut self, entry: HighScore) -> Option<usize> {
+        let rank = self
+            .entries
+            .iter()
+            .position(|other| {
+                entry.score > other.score || (entry.score == other.score && entry.time < other.time)
+            })
+            .unwrap_or(self.entries.len());
+        if rank >= MAX_HIGH_SCORES {
+            return None;
+        }
+        self.entries.insert(rank, entry);
+        self.entries.truncate(MAX_HIGH_SCORES);
+        Some(rank)
+    }
+
+    pub fn best(&self) -> Option<&HighScore> {
+        self.entries.first()
+    }
+
+    #[cfg(feature = "serde")]
+    fn load() -> Self {
+        let Ok(text) = std::fs::read_to_string(HIGH_SCORES_PATH) else {
+            return default();
+        };
+        ron::from_str(&text).unwrap_or_else(|e| {
+            warn!("Couldn't read {HIGH_SCORES_PATH}: {e}");
+            default()
+        })
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn load() -> Self {
+        default()
+    }
+
+    #[cfg(feature = "serde")]
+    fn save(&self) {
+        let result = ron::ser::to_string_pretty(self, default())
+            .map_err(|e| e.to_string())
+            .and_then(|text| std::fs::write(HIGH_SCORES_PATH, text).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            warn!("Couldn't save {HIGH_SCORES_PATH}: {e}");
+        }
+    }
+
+    #[cfg(not(feature = "serde"))]
+    fn save(&self) {}
+}
+
+#[derive(Component)]
+struct Shards(Timer);
+
+#[derive(Component)]
+struct ScoreText;
+
+fn run_commands(
+    mut commands: Commands,
+    mut events: EventReader<TargetRangeCommand>,
+    groups: Query<(Entity, &Targets, &Transform)>,
+    mut range: ResMut<TargetRange>,
+) {
+    for event in events.read() {
+        for (entity, _, _) in &groups {
+            commands.entity(entity).despawn_recursive();
+        }
+        match event {
+            TargetRangeCommand::Clear => {
+                info!("Clearing targets");
+                range.running = false;
+            }
+            TargetRangeCommand::Reset => {
+                info!("Resetting targets");
+                let mut respawned = false;
+                for (_, targets, transform) in &groups {
+                    commands.spawn((targets.clone(), *transform));
+                    respawned = true;
+                }
+                if !respawned {
+                    commands.spawn(Targets::default());
+                }
+                range.restart();
+            }
+        }
+    }
+}
+
+fn start_session(added: Query<(), Added<Target>>, mut range: ResMut<TargetRange>) {
+    if !range.running && !added.is_empty() {
+        range.restart();
+    }
+}
+
+fn count_shots(
+    mut shots: EventReader<HitscanShot>,
+    targets: Query<(), With<Target>>,
+    mut range: ResMut<TargetRange>,
+    time: Res<Time>,
+) {
+    if !range.running {
+        shots.clear();
+        return;
+    }
+    range.elapsed += time.delta_seconds();
+    for shot in shots.read() {
+        range.shots += 1;
+        if shot.target.is_some_and(|target| targets.contains(target)) {
+            range.hits += 1;
+        }
+    }
+}
+
+fn score_broken_target(
+    trigger: Trigger<OnAdd, Dead>,
+    mut commands: Commands,
+    targets: Query<(&Target, &GlobalTransform)>,
+    mut range: ResMut<TargetRange>,
+) {
+    let Ok((target, transform)) = targets.get(trigger.entity()) else {
+        return;
+    };
+    if range.running {
+        range.score += target.points;
+        range.broken += 1;
+    }
+    spawn_shards(&mut commands, transform.translation());
+}
+
+fn finish_session(
+    targets: Query<(), With<Target>>,
+    mut range: ResMut<TargetRange>,
+    mut high_scores: ResMut<HighScores>,
+) {
+    if !range.running || !targets.is_empty() {
+        return;
+    }
+    range.running = false;
+    // Every target was cleared away rather than broken.
+    if range.broken == 0 {
+        return;
+    }
+    let entry = HighScore {
+        score: range.score,
+        accuracy: range.accuracy(),
+        time: range.elapsed,
+    };
+    info!("Target range finished: {entry:?}");
+    range.last_rank = high_scores.insert(entry);
+    if range.last_rank.is_some() {
+        high_scores.save();
+    }
+}
+
+fn spawn_shards(commands: &mut Commands, position: Vec3) {
+    commands.spawn((
+        Name::new("target_shards"),
+        ParticleSpawnerBundle::from_settings(ParticleSpawnerSettings {
+            one_shot: true,
+            rate: 400.0,
+            emission_shape: EmissionShape::Sphere(1.0),
+            lifetime: RandF32 { min: 0.3, max: 0.8 },
+            initial_velocity: RandVec3 {
+                magnitude: RandF32 { min: 4., max: 12. },
+                direction: Vec3::Y,
+                spread: PI,
+            },
+            initial_scale: RandF32 {
+                min: 0.05,
+                max: 0.15,
+            },
+            scale_curve: ParamCurve::constant(1.),
+            acceleration: Vec3::new(0., -9.8, 0.),
+            color: Gradient::linear(vec![
+                (0., LinearRgba::new(0.6, 0.8, 1.0, 1.)),
+                (1., LinearRgba::new(0.2, 0.3, 0.6, 0.)),
+            ]),
+            blend_mode: BlendMode::Blend,
+            linear_drag: 0.5,
+            pbr: false,
+            ..default()
+        }),
+        SpatialBundle::from_transform(Transform::from_translation(position)),
+        Shards(Timer::from_seconds(SHARDS_LIFETIME, TimerMode::Once)),
+    ));
+}
+
+fn update_shards(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut shards: Query<(Entity, &mut Shards)>,
+) {
+    for (entity, mut shards) in &mut shards {
+        if shards.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
+
+fn spawn_score_text(mut commands: Commands) {
+    commands.spawn((
+        Name::new("target_range_score"),
+        StateScoped(GameState::InGame),
+        ScoreText,
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 24.0,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(20.0),
+            left: Val::Px(20.0),
+            ..default()
+        }),
+    ));
+}
+
+fn update_score_text(
+    range: Res<TargetRange>,
+    high_scores: Res<HighScores>,
+    targets: Query<(), With<Target>>,
+    mut text: Query<(&mut Text, &mut Visibility), With<ScoreText>>,
+) {
+    let Ok((mut text, mut visibility)) = text.get_single_mut() else {
+        return;
+    };
+    let shown = range.running || !targets.is_empty() || range.last_rank.is_some();
+    *visibility = if shown {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    if !shown || !(range.is_changed() || high_scores.is_changed()) {
+        return;
+    }
+    let mut lines = vec![
+        format!("Score: {}", range.score),
+        format!(
+            "Hitscan accuracy: {:.0}% ({}/{})",
+            range.accuracy() * 100.0,
+            range.hits,
+            range.shots
+        ),
+        format!("Time: {:.1}s", range.elapsed),
+    ];
+    if let Some(best) = high_scores.best() {
+        lines.push(format!("Best: {} in {:.1}s", best.score, best.time));
+    }
+    if let (false, Some(rank)) = (range.running, range.last_rank) {
+        lines.push(format!("New high score! #{}", rank + 1));
+    }
+    text.sections[0].value = lines.join("\n");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(score: u32, time: f32) -> HighScore {
+        HighScore {
+            score,
+            accuracy: 1.0,
+            time,
+        }
+    }
+
+    #[test]
+    fn high_scores_are_ordered_by_score_then_time() {
+        let mut scores = HighScores::default();
+        assert_eq!(scores.insert(entry(300, 20.0)), Some(0));
+        assert_eq!(scores.insert(entry(500, 30.0)), Some(0));
+        assert_eq!(scores.insert(entry(300, 10.0)), Some(1));
+        assert_eq!(scores.insert(entry(100, 5.0)), Some(3));
+        let order: Vec<_> = scores.entries.iter().map(|e| (e.score, e.time)).collect();
+        assert_eq!(
+            order,
+            vec![(500, 30.0), (300, 10.0), (300, 20.0), (100, 5.0)]
+        );
+    }
+
+    #[test]
+    fn high_scores_keep_only_the_best() {
+        let mut scores = HighScores::default();
+        for score in 1..=MAX_HIGH_SCORES as u32 {
+            scores.insert(entry(score * 100, 10.0));
+        }
+        assert_eq!(scores.insert(entry(50, 10.0)), None);
+        assert_eq!(scores.insert(entry(150, 10.0)), Some(MAX_HIGH_SCORES - 1));
+        assert_eq!(scores.entries.len(), MAX_HIGH_SCORES);
+        assert_eq!(scores.entries.last().unwrap().score, 150);
+    }
+
+    #[test]
+    fn accuracy_counts_hits_over_shots() {
+        let mut range = TargetRange::default();
+        assert_eq!(range.accuracy(), 0.0);
+        range.shots = 4;
+        range.hits = 3;
+        assert_eq!(range.accuracy(), 0.75);
+    }
+}
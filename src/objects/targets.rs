@@ -1,24 +1,42 @@
 use bevy::{
-    input::{common_conditions::*, mouse::MouseButton},
     prelude::*,
     render::{
         camera,
         render_asset::RenderAssetUsages,
         render_resource::{Extent3d, TextureDimension, TextureFormat},
     },
-    window::PrimaryWindow,
 };
 use bevy_rapier3d::prelude::*;
 
+use crate::asset_cache;
+use crate::hitpoints::Hp;
+use crate::objects::TargetRangePlugin;
 use crate::prelude::*;
-use crate::{asset_cache, camera::FirstPersonCam};
 use rand::{self, Rng};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
-#[derive(Component)]
+/// A group of targets spread through a box of `extent`. Spawn it bare, optionally with a Transform to say
+/// where the box goes, and `load_targets` fills it.
+#[derive(Component, Clone, Reflect)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
 pub struct Targets {
     number: u32,
     name: String,
     extent: Vec3,
+    pub motion: TargetMotion,
+    /// Points for breaking each target.
+    pub points: u32,
+    /// Where to put each target, relative to the middle of the volume. When empty, `number` targets are
+    /// placed at random.
+    pub positions: Vec<Vec3>,
+}
+
+impl Targets {
+    /// The size of the box that the targets are spread through.
+    pub fn extent(&self) -> Vec3 {
+        self.extent
+    }
 }
 
 impl Default for Targets {
@@ -27,27 +45,107 @@ impl Default for Targets {
             number: 5,
             name: "Targets".into(),
             extent: (50., 50., 50.).into(),
+            motion: TargetMotion::Brownian {
+                speed: 4.,
+                jitter: 10.,
+            },
+            points: 100,
+            positions: vec![],
         }
     }
 }
 
+/// How targets drift about their spawn volume. Targets are dynamic bodies that steer toward the drift, so
+/// launch pads, blasts and shots still knock them about.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Reflect)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TargetMotion {
+    #[default]
+    Static,
+    /// A random walk: the target's velocity is nudged randomly by up to `jitter` per second, and kept
+    /// under `speed`. Targets bounce off the sides of the volume, and head back in if knocked out of it.
+    Brownian { speed: f32, jitter: f32 },
+    /// Circles its spawn point in the horizontal plane.
+    Orbital { radius: f32, speed: f32 },
+}
+
 #[derive(Component, Default)]
 pub struct Target {
+    /// Spin, in radians per second about each axis.
     rotation_velocity: Vec3,
+    pub points: u32,
+    motion: TargetMotion,
+    home: Vec3,
+    /// Half the size of the volume that the target stays in, centered on its parent.
+    bounds: Vec3,
+    velocity: Vec3,
+    phase: f32,
 }
 
 impl Target {
-    fn from_rotations(rotation_velocity: Vec3) -> Self {
-        Target { rotation_velocity }
+    fn new(
+        motion: TargetMotion,
+        home: Vec3,
+        bounds: Vec3,
+        points: u32,
+        rng: &mut impl Rng,
+    ) -> Self {
+        Target {
+            rotation_velocity: Vec3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            ),
+            points,
+            motion,
+            home,
+            bounds,
+            velocity: Vec3::ZERO,
+            phase: rng.gen_range(0.0..std::f32::consts::TAU),
+        }
+    }
+
+    /// The velocity that the target wants to drift at from `translation` over the next `delta` seconds.
+    fn drift(&mut self, translation: Vec3, delta: f32, rng: &mut impl Rng) -> Vec3 {
+        match self.motion {
+            TargetMotion::Static => Vec3::ZERO,
+            TargetMotion::Brownian { speed, jitter } => {
+                let kick = Vec3::new(
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                    rng.gen_range(-1.0..1.0),
+                );
+                self.velocity = (self.velocity + kick * jitter * delta).clamp_length_max(speed);
+                let next = translation + self.velocity * delta;
+                for axis in 0..3 {
+                    let outward = next[axis].signum() == self.velocity[axis].signum();
+                    if next[axis].abs() > self.bounds[axis] && outward {
+                        self.velocity[axis] = -self.velocity[axis];
+                    }
+                }
+                self.velocity
+            }
+            TargetMotion::Orbital { radius, speed } => {
+                self.phase += speed * delta;
+                let next = self.home + Vec3::new(self.phase.cos(), 0.0, self.phase.sin()) * radius;
+                ((next - translation) / delta).clamp_length_max(radius * speed.abs() + RETURN_SPEED)
+            }
+        }
     }
 }
 
+/// How quickly, as a fraction per second, a target's velocity turns to its drift. Knocks fade at this rate.
+const STEERING: f32 = 2.0;
+/// How much faster than its orbit an orbiting target goes to get back on it.
+const RETURN_SPEED: f32 = 10.0;
+
 pub struct TargetsPlugin;
 
 impl Plugin for TargetsPlugin {
     fn build(&self, app: &mut App) {
+        app.add_plugins(TargetRangePlugin);
         app.add_systems(PreUpdate, load_targets);
-        app.add_systems(Update, cast_ray.run_if(input_pressed(MouseButton::Left)));
+        app.add_systems(FixedUpdate, drift_targets);
 
         // app.add_systems(PreUpdate, despawn_targets);
     }
@@ -55,7 +153,7 @@ impl Plugin for TargetsPlugin {
 
 fn load_targets(
     mut commands: Commands,
-    query: Query<(Entity, &Targets), Added<Targets>>,
+    query: Query<(Entity, &Targets, Option<&Transform>), Added<Targets>>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     asset_cache: Res<asset_cache::AssetCache>,
@@ -68,10 +166,11 @@ fn load_targets(
     let collider = Collider::ball(1.0);
     let mut rng = rand::thread_rng();
 
-    for (entity_id, targets) in &query {
+    for (entity_id, targets, transform) in &query {
         info!("Detected Targets addition. Spawning...");
-        let mut position = targets.extent.clone();
-        position.y += 30.0;
+        let position = transform.map_or(targets.extent + Vec3::Y * 30.0, |transform| {
+            transform.translation
+        });
         let _boundary_cube = meshes.add(Cuboid::from_size(targets.extent));
 
         let mut entity = commands.entity(entity_id);
@@ -81,7 +180,7 @@ fn load_targets(
         entity.insert((
             Name::new(targets.name.clone()),
             SpatialBundle::from_transform(Transform::from_translation(position)), // Transform::from_translation(position),
-            Leash(1000.),
+            OutOfBounds::Despawn,
         ));
         // This spawns a transparent boundary cube to show the volume where targets can possibly spawn.
         // .insert(PbrBundle {
@@ -95,24 +194,43 @@ fn load_targets(
         //     ..default()
         // });
         entity.with_children(|children| {
-            for i in 0..targets.number {
-                let translation: Vec3 = (
-                    rng.gen_range(-targets.extent.x / 2.0..targets.extent.x / 2.0),
-                    rng.gen_range(-targets.extent.y / 2.0..targets.extent.y / 2.0),
-                    rng.gen_range(-targets.extent.z / 2.0..targets.extent.z / 2.0),
-                )
-                    .into();
+            let positions = if targets.positions.is_empty() {
+                (0..targets.number)
+                    .map(|_| {
+                        Vec3::new(
+                            rng.gen_range(-targets.extent.x / 2.0..targets.extent.x / 2.0),
+                            rng.gen_range(-targets.extent.y / 2.0..targets.extent.y / 2.0),
+                            rng.gen_range(-targets.extent.z / 2.0..targets.extent.z / 2.0),
+                        )
+                    })
+                    .collect()
+            } else {
+                targets.positions.clone()
+            };
+            for (i, translation) in positions.into_iter().enumerate() {
                 children.spawn((
-                    Target::from_rotations(translation.clone()),
+                    Target::new(
+                        targets.motion,
+                        translation,
+                        targets.extent / 2.0,
+                        targets.points,
+                        &mut rng,
+                    ),
                     Name::new(format!("target{i}")),
+                    // One hit from anything breaks a target.
+                    Hp {
+                        current: 10,
+                        max: 10,
+                    },
                     RigidBody::Dynamic,
                     GravityScale(0.0),
+                    // Steering toward the drift does the linear damping.
                     Damping {
-                        linear_damping: 1.0,
+                        linear_damping: 0.0,
                         angular_damping: 0.1,
-                        ..default()
                     },
                     ColliderMassProperties::Density(0.1),
+                    Velocity::default(),
                     collider.clone(),
                     PbrBundle {
                         transform: Transform::from_translation(translation),
@@ -126,82 +244,106 @@ fn load_targets(
     }
 }
 
-fn rotate(mut query: Query<(&Target, &mut Transform), With<Target>>, time: Res<Time>) {
-    for (target, mut transform) in &mut query {
-        transform.rotate_x(time.delta_seconds() / target.rotation_velocity.x);
-        transform.rotate_y(time.delta_seconds() / target.rotation_velocity.y);
-        transform.rotate_z(time.delta_seconds() / target.rotation_velocity.z);
+fn drift_targets(mut targets: Query<(&mut Target, &Transform, &mut Velocity)>, time: Res<Time>) {
+    let delta = time.delta_seconds();
+    if delta <= 0.0 {
+        return;
+    }
+    let mut rng = rand::thread_rng();
+    let steering = (STEERING * delta).min(1.0);
+    for (mut target, transform, mut velocity) in &mut targets {
+        let drift = target.drift(transform.translation, delta, &mut rng);
+        velocity.linvel = velocity.linvel.lerp(drift, steering);
+        velocity.angvel = velocity.angvel.lerp(target.rotation_velocity, steering);
     }
 }
 
-pub fn viewport_to_ndc(logical_viewport_size: Vec2, mut screen_coordinates: Vec2) -> Vec2 {
-    // Flip the Y co-ordinate origin from the top to the bottom.
-    screen_coordinates.y = logical_viewport_size.y - screen_coordinates.y;
-    screen_coordinates * 2. / logical_viewport_size - Vec2::ONE
-}
-
-pub fn cast_ray(
-    mut commands: Commands,
-    windows: Query<&Window, With<PrimaryWindow>>,
-    rapier_context: Res<RapierContext>,
-    cameras: Query<(&Camera, &GlobalTransform, &Transform), With<FirstPersonCam>>,
-    mut gizmos: Gizmos,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    // keyboard: Res<ButtonInput<KeyCode>>,
-    // enable: Res<EnablePlayerControl>,
-    // mut movement: ResMut<MovementInput>,
-    // mut look: ResMut<LookInput>,
-    mut mouse: Res<ButtonInput<MouseButton>>,
-) {
-    let window = windows.single();
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::time::Duration;
 
-    let Some(cursor_position) = window.cursor_position() else {
-        return;
-    };
-
-    if !mouse.pressed(MouseButton::Left) {
-        return;
-    }
+    const DT: f32 = 1.0 / 64.0;
 
-    // We will color in red the colliders hovered by the mouse.
-    for (camera, camera_global_transform, camera_transform) in &cameras {
-        // First, compute a ray from the mouse position.
-        let Some(ray) = camera.viewport_to_world(camera_global_transform, cursor_position) else {
-            warn!("no ray");
-            return;
+    #[test]
+    fn brownian_targets_stay_in_bounds() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let bounds = Vec3::new(5.0, 2.0, 5.0);
+        let motion = TargetMotion::Brownian {
+            speed: 20.0,
+            jitter: 200.0,
         };
+        let mut target = Target::new(motion, Vec3::ZERO, bounds, 100, &mut rng);
+        let mut position = Vec3::ZERO;
+        let mut furthest: f32 = 0.0;
+        for _ in 0..2000 {
+            position += target.drift(position, DT, &mut rng) * DT;
+            // It turns back within a tick of reaching a side.
+            assert!(
+                position.abs().cmple(bounds + 20.0 * DT).all(),
+                "{position} left the volume"
+            );
+            assert!(target.velocity.length() <= 20.0 + 1e-3);
+            furthest = furthest.max(position.length());
+        }
+        assert!(furthest > 1.0, "the target should wander");
+    }
 
-        let logical_viewport_size = match camera.logical_viewport_size() {
-            Some(size) => size,
-            None => return warn!("no viewport"),
+    #[test]
+    fn orbiting_targets_keep_their_radius() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let home = Vec3::new(1.0, 2.0, 3.0);
+        let motion = TargetMotion::Orbital {
+            radius: 4.0,
+            speed: 1.0,
         };
+        let mut target = Target::new(motion, home, Vec3::splat(25.0), 100, &mut rng);
+        let mut position = home + Vec3::new(target.phase.cos(), 0.0, target.phase.sin()) * 4.0;
+        for _ in 0..100 {
+            position += target.drift(position, 0.1, &mut rng) * 0.1;
+            assert!(((position - home).length() - 4.0).abs() < 1e-4);
+            assert_eq!(position.y, home.y);
+        }
+    }
 
-        let mut _cursor_ndc = viewport_to_ndc(logical_viewport_size, cursor_position).extend(1.0);
-
-        let _near = camera
-            .ndc_to_world(camera_global_transform, camera_transform.translation)
-            .unwrap();
-
-        // gizmos.ray(near, ray.get_point(25.0), Palette::Red);
+    #[test]
+    fn static_targets_stay_put() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut target = Target::new(TargetMotion::Static, Vec3::ONE, Vec3::ONE, 100, &mut rng);
+        assert_eq!(target.drift(Vec3::ONE, 1.0, &mut rng), Vec3::ZERO);
+    }
 
-        // Because of the query filter, only colliders attached to a dynamic body
-        // will get an event.
-        let hit = rapier_context.cast_ray(
-            ray.origin,
-            ray.direction.into(),
-            f32::MAX,
-            true,
-            QueryFilter::only_dynamic(),
-        );
+    #[test]
+    fn knocks_outlast_the_drift_for_a_while() {
+        let mut world = World::new();
+        let mut time = Time::<()>::default();
+        time.advance_by(Duration::from_secs_f32(DT));
+        world.insert_resource(time);
+        let mut rng = StdRng::seed_from_u64(7);
+        let motion = TargetMotion::Orbital {
+            radius: 4.0,
+            speed: 1.0,
+        };
+        let home = Vec3::new(0.0, 0.0, -4.0);
+        let target = world
+            .spawn((
+                Target::new(motion, home, Vec3::splat(25.0), 100, &mut rng),
+                Transform::default(),
+                Velocity::linear(Vec3::Y * 30.0),
+            ))
+            .id();
+        let linvel = |world: &World| world.get::<Velocity>(target).unwrap().linvel;
 
-        if let Some((entity, _toi)) = hit {
-            let highlight = materials.add(StandardMaterial {
-                base_color: Palette::Red.to_color().into(),
-                alpha_mode: AlphaMode::Blend,
-                ..default()
-            });
+        world.run_system_once(drift_targets);
+        assert!(linvel(&world).y > 25.0, "{}", linvel(&world));
 
-            commands.entity(entity).insert(highlight);
+        // Hold the target still, and it settles into its drift.
+        for _ in 0..640 {
+            world.run_system_once(drift_targets);
         }
+        assert!(linvel(&world).y.abs() < 0.1, "{}", linvel(&world));
+        assert!(linvel(&world).length() > 1.0, "{}", linvel(&world));
     }
 }
@@ -4,9 +4,14 @@ use crate::{
     asset_cache::AssetCache,
     camera::FirstPersonCam,
     hitpoints::{Hp, HpRegen},
-    items::{FireballAbility, Platform},
+    items::{
+        carry_yaw, lateral_push, within_footprint, DashAbility, Equipment, HitscanWeapon,
+        Inventory, JetpackAbility, LaunchPad, Platform, SlideAbility, Spellbook, WallRunAbility,
+    },
     mana::{Mana, ManaRegen},
     prelude::*,
+    progression::{CharacterStats, Progression},
+    respawn::{choose_spawn, Checkpoint, LastDeath, RespawnSettings},
     GameState,
 };
 use bevy::{
@@ -17,17 +22,25 @@ use bevy::{
     prelude::*,
 };
 use bevy_rapier3d::{control::KinematicCharacterController, prelude::*};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 const MOUSE_SENSITIVITY: f32 = 0.3;
+/// Where the first person camera sits on a character, and so where the character aims from.
+pub const EYE_OFFSET: Vec3 = Vec3::new(0.0, 0.7, -1.0);
 // if the user has been grounded within x seconds and hasn't jumped within that time, he's grounded.
 const GROUND_TIMER: f32 = 0.5;
-// If the player has been on a platform within this amount of time and has not jumped, we impart the platform's
-// linvel to the player.
+// If the player has been on a platform within this amount of time, has not jumped and is still over it, the
+// platform keeps carrying the player. This covers the ticks that Rapier misses the contact.
 const ON_PLATFORM_TIMER: f32 = 0.5;
-const MOVEMENT_SPEED: f32 = 8.0;
-const JUMP_SPEED: f32 = 40.0;
-const GRAVITY: f32 = -9.81;
-const AIR_JUMPS: u32 = 10;
+// How far below the player's feet a platform is still considered to be under the player.
+const PLATFORM_PROBE_DISTANCE: f32 = 0.5;
+const PLAYER_HALF_HEIGHT: f32 = 1.0;
+const PLAYER_HALF_WIDTH: f32 = 0.3;
+pub(crate) const GRAVITY: f32 = -9.81;
+// Fraction of the external velocity (launch pads, dashes, slides) that is lost per second.
+const AIR_DRAG: f32 = 0.3;
+const GROUND_FRICTION: f32 = 6.0;
 
 #[derive(Default)]
 pub struct Player;
@@ -43,6 +56,74 @@ impl Component for Player {
     }
 }
 
+/// Where the player can spawn, facing along the transform's forward. Levels place these; without one the
+/// player drops in over the canyon. `RespawnSettings` decides which one is used.
+#[derive(Component, Debug, Clone, Copy, Default)]
+pub struct SpawnPoint;
+
+/// Each character's `PlayerInput` for the tick is settled in `Input`. Movement abilities run in `Abilities`
+/// and write into `PlayerMotion`, which `player_movement` turns into the character controller's translation
+/// in `Integrate`.
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MovementSet {
+    Input,
+    Abilities,
+    Integrate,
+}
+
+/// Tuning for the player's basic movement.
+#[derive(Component, Debug, Clone)]
+pub struct MovementProfile {
+    pub walk_speed: f32,
+    pub sprint_multiplier: f32,
+    pub jump_speed: f32,
+    pub air_jumps: u32,
+}
+
+impl Default for MovementProfile {
+    fn default() -> Self {
+        MovementProfile {
+            walk_speed: 8.0,
+            sprint_multiplier: 2.0,
+            jump_speed: 40.0,
+            air_jumps: 10,
+        }
+    }
+}
+
+/// The player's movement state, carried between FixedUpdate ticks.
+#[derive(Component, Debug, Clone)]
+pub struct PlayerMotion {
+    pub vertical_speed: f32,
+    pub grounded_timer: f32,
+    pub air_jumps_left: u32,
+    /// World space velocity that doesn't come from walking: launch pads, dashes, slides and wall runs.
+    /// It decays with AIR_DRAG or GROUND_FRICTION.
+    pub external_velocity: Vec3,
+    /// Abilities set these every tick that they're active; player_movement resets them to 1.
+    pub gravity_scale: f32,
+    pub walk_scale: f32,
+    /// The platform the player is standing on, and how much longer it carries the player without
+    /// being seen underfoot again. Stepping off its edge ends the ride straight away.
+    pub riding: Option<Entity>,
+    pub riding_timer: f32,
+}
+
+impl Default for PlayerMotion {
+    fn default() -> Self {
+        PlayerMotion {
+            vertical_speed: 0.0,
+            grounded_timer: 0.0,
+            air_jumps_left: 0,
+            external_velocity: Vec3::ZERO,
+            gravity_scale: 1.0,
+            walk_scale: 1.0,
+            riding: None,
+            riding_timer: 0.0,
+        }
+    }
+}
+
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
@@ -50,29 +131,71 @@ impl Plugin for PlayerPlugin {
         info!("Installing PlayerPlugin");
         app.init_resource::<MovementInput>()
             .init_resource::<LookInput>()
+            .init_resource::<MovementActions>()
+            .configure_sets(
+                FixedUpdate,
+                (
+                    MovementSet::Input,
+                    MovementSet::Abilities,
+                    MovementSet::Integrate,
+                )
+                    .chain(),
+            )
             .add_systems(OnEnter(GameState::Spawning), spawn_player)
             .add_systems(PreUpdate, handle_input.after(InputSystem))
             .add_systems(Update, player_look)
-            .add_systems(FixedUpdate, player_movement);
+            .add_systems(
+                FixedUpdate,
+                (
+                    sample_input.in_set(MovementSet::Input),
+                    player_movement.in_set(MovementSet::Integrate),
+                ),
+            );
     }
 }
 
 /// Keyboard input vector
 #[derive(Default, Resource, Deref, DerefMut)]
-struct MovementInput(Vec3);
+pub(crate) struct MovementInput(Vec3);
 
 /// Mouse input vector
 #[derive(Default, Resource, Deref, DerefMut)]
-struct LookInput(Vec2); // Degrees that the user has turned since last update.
+pub(crate) struct LookInput(Vec2); // Degrees that the user has turned since last update.
+
+/// Movement keys other than WASD and jump.
+#[derive(Default, Resource, Debug)]
+pub(crate) struct MovementActions {
+    pub sprint: bool, // held
+    pub crouch: bool, // held
+    pub thrust: bool, // held
+    pub dash: bool,   // latched until a movement tick consumes it
+}
+
+/// One movement tick of a character's input. `sample_input` fills the local player's from the keyboard and
+/// mouse; the network server fills its clients' characters' from the inputs they send.
+#[derive(Component, Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PlayerInput {
+    /// Walking direction in the character's frame, with `y` set to jump.
+    pub movement: Vec3,
+    /// The character's heading, in degrees.
+    pub yaw: f32,
+    pub sprint: bool,
+    pub crouch: bool,
+    pub thrust: bool,
+    pub dash: bool,
+}
 
 fn handle_input(
     keyboard: Res<ButtonInput<KeyCode>>,
     mut movement: ResMut<MovementInput>,
     mut look: ResMut<LookInput>,
+    mut actions: ResMut<MovementActions>,
     mut mouse_events: EventReader<MouseMotion>,
     state: Res<State<GameState>>,
 ) {
     if *state.get() != GameState::InGame {
+        *actions = MovementActions::default();
         return;
     }
     if keyboard.pressed(KeyCode::KeyW) {
@@ -88,12 +211,15 @@ fn handle_input(
         movement.x += 1.0
     }
     **movement = movement.normalize_or_zero();
-    if keyboard.pressed(KeyCode::ShiftLeft) {
-        **movement *= 2.0;
-    }
     if keyboard.just_pressed(KeyCode::Space) {
         movement.y = 1.0;
     }
+    actions.sprint = keyboard.pressed(KeyCode::ShiftLeft);
+    actions.crouch = keyboard.pressed(KeyCode::ControlLeft);
+    actions.thrust = keyboard.pressed(KeyCode::KeyF);
+    if keyboard.just_pressed(KeyCode::KeyQ) {
+        actions.dash = true;
+    }
 
     for event in mouse_events.read() {
         look.x -= event.delta.x * MOUSE_SENSITIVITY;
@@ -102,94 +228,245 @@ fn handle_input(
     }
 }
 
-fn player_movement(
-    time: Res<Time>,
-    mut input: ResMut<MovementInput>,
-    mut player: Query<
-        (
-            Entity,
-            &mut Transform,
-            &GlobalTransform,
-            &mut KinematicCharacterController,
-            Option<&KinematicCharacterControllerOutput>,
-        ),
-        With<Player>,
-    >,
-    mut vertical_movement: Local<f32>,
-    mut grounded_timer: Local<f32>,
-    mut grounded_platform_timer: Local<f32>,
-    mut grounded_platform_linvel: Local<Vec3>,
-    mut air_jumps_left: Local<u32>,
-    platforms: Query<(Entity, &GlobalTransform, &Platform), Without<Player>>,
-    rapier_context: Res<RapierContext>,
+/// Hands the input gathered since the last tick to the local player, and starts gathering afresh.
+fn sample_input(
+    mut movement: ResMut<MovementInput>,
+    mut actions: ResMut<MovementActions>,
+    look: Res<LookInput>,
+    mut player: Query<&mut PlayerInput, With<Player>>,
 ) {
-    let Ok((player_entity, player_transform, player_global_transform, mut controller, output)) =
-        player.get_single_mut()
-    else {
+    let Ok(mut input) = player.get_single_mut() else {
         return;
     };
+    *input = PlayerInput {
+        movement: **movement,
+        yaw: look.x,
+        sprint: actions.sprint,
+        crouch: actions.crouch,
+        thrust: actions.thrust,
+        dash: actions.dash,
+    };
+    **movement = Vec3::ZERO;
+    actions.dash = false;
+}
+
+pub(crate) fn player_movement(
+    time: Res<Time>,
+    mut look: ResMut<LookInput>,
+    mut characters: Query<(
+        Entity,
+        &Transform,
+        &PlayerInput,
+        &mut KinematicCharacterController,
+        Option<&KinematicCharacterControllerOutput>,
+        &MovementProfile,
+        &mut PlayerMotion,
+        Option<&mut JetpackAbility>,
+        Has<Player>,
+    )>,
+    platforms: Query<(Entity, &Transform, &Platform), Without<PlayerInput>>,
+    launch_pads: Query<&LaunchPad>,
+    rapier_context: Res<RapierContext>,
+) {
     let delta_time = time.delta_seconds();
+    for (
+        player_entity,
+        player_transform,
+        input,
+        mut controller,
+        output,
+        profile,
+        mut motion,
+        jetpack,
+        is_local,
+    ) in &mut characters
+    {
+        let carried_yaw = move_character(
+            delta_time,
+            player_entity,
+            player_transform,
+            input,
+            &mut controller,
+            output,
+            profile,
+            &mut motion,
+            jetpack.map(|jetpack| jetpack.into_inner()),
+            &platforms,
+            &launch_pads,
+            &rapier_context,
+        );
+        if let Some(yaw) = carried_yaw.filter(|_| is_local) {
+            look.x += yaw;
+        }
+    }
+}
+
+/// Works out one character's translation for this tick. Returns how far, in degrees, the platform that
+/// the character rides turned them, for the look input to follow.
+#[allow(clippy::too_many_arguments)]
+fn move_character(
+    delta_time: f32,
+    player_entity: Entity,
+    player_transform: &Transform,
+    input: &PlayerInput,
+    controller: &mut KinematicCharacterController,
+    output: Option<&KinematicCharacterControllerOutput>,
+    profile: &MovementProfile,
+    motion: &mut PlayerMotion,
+    jetpack: Option<&mut JetpackAbility>,
+    platforms: &Query<(Entity, &Transform, &Platform), Without<PlayerInput>>,
+    launch_pads: &Query<&LaunchPad>,
+    rapier_context: &RapierContext,
+) -> Option<f32> {
+    let mut carried_yaw = None;
     // Retrieve input
-    let mut movement = Vec3::new(input.x, 0.0, input.z) * MOVEMENT_SPEED;
+    let mut speed = profile.walk_speed * motion.walk_scale;
+    if input.sprint {
+        speed *= profile.sprint_multiplier;
+    }
+    let mut movement = Vec3::new(input.movement.x, 0.0, input.movement.z) * speed;
     // Is the player jumping?
-    let jump_speed = input.y * JUMP_SPEED;
-    // Clear input
-    **input = Vec3::ZERO;
+    let jump_speed = input.movement.y * profile.jump_speed;
+    // Find the platform underfoot. Rapier doesn't reliably report the contact between the character
+    // controller and a kinematic platform on every tick, so also look for one just below the player's feet.
+    let player_position = player_transform.translation;
+    let below = rapier_context
+        .cast_ray(
+            player_position,
+            Vec3::NEG_Y,
+            PLAYER_HALF_HEIGHT + PLATFORM_PROBE_DISTANCE,
+            true,
+            QueryFilter::default().exclude_rigid_body(player_entity),
+        )
+        .map(|(entity, _toi)| entity);
+    let touched = output.filter(|o| o.grounded).and_then(|o| {
+        o.collisions
+            .iter()
+            .map(|collision| collision.entity)
+            .find(|entity| platforms.contains(*entity))
+    });
+    // A platform we're rising away from isn't holding us up.
+    let support = below
+        .filter(|entity| platforms.contains(*entity))
+        .or(touched)
+        .filter(|_| motion.vertical_speed <= 0.0);
     // Check physics ground check
-    if output.map(|o| o.grounded).unwrap_or(false) {
-        *grounded_timer = GROUND_TIMER;
-        *air_jumps_left = AIR_JUMPS;
-        *vertical_movement = 0.0;
+    let grounded = output.map(|o| o.grounded).unwrap_or(false) || support.is_some();
+    if grounded {
+        motion.grounded_timer = GROUND_TIMER;
+        motion.air_jumps_left = profile.air_jumps;
+        motion.vertical_speed = 0.0;
+    }
+
+    // Touching a launch pad overrides both the vertical and the horizontal velocity.
+    let launch_pad = output.and_then(|o| {
+        o.collisions
+            .iter()
+            .find_map(|collision| launch_pads.get(collision.entity).ok())
+    });
+    if let Some(pad) = launch_pad {
+        motion.vertical_speed = pad.impulse.y;
+        motion.external_velocity = Vec3::new(pad.impulse.x, 0.0, pad.impulse.z);
+        // Don't let the player jump off the pad's surface, and don't let the ground check cancel the launch.
+        motion.grounded_timer = 0.0;
+        motion.air_jumps_left = profile.air_jumps;
     }
 
     // If we are grounded we can jump
-    if *grounded_timer > 0.0 {
-        *grounded_timer -= delta_time;
+    if motion.grounded_timer > 0.0 {
+        motion.grounded_timer -= delta_time;
         // If we jump we clear the grounded tolerance
         if jump_speed > 0.0 {
-            *vertical_movement = jump_speed;
+            motion.vertical_speed = jump_speed;
             // Unground me.
-            *grounded_timer = 0.0;
+            motion.grounded_timer = 0.0;
         }
     } else {
         if jump_speed > 0.0 {
-            if *air_jumps_left > 0 {
-                *air_jumps_left -= 1;
-                *vertical_movement += jump_speed;
+            if motion.air_jumps_left > 0 {
+                motion.air_jumps_left -= 1;
+                motion.vertical_speed += jump_speed;
             }
         }
     }
-    movement.y = *vertical_movement;
-    *vertical_movement += GRAVITY * delta_time * controller.custom_mass.unwrap_or(1.0);
-    let mut translation = player_transform.rotation * movement;
-
-    for (platform_entity, platform_global_transform, platform) in &platforms {
-        if let Some(_contact_pair) = rapier_context.contact_pair(player_entity, platform_entity) {
-            // TODO: Figure transform the platform linvel (in world coordinates) to local player coordinates.'
-            let global_platform_position = platform_global_transform.translation();
-            let global_player_position = player_global_transform.translation();
-            if global_player_position.y > global_platform_position.y + 3.0 {
-                // player is standing on the platform.
-                *grounded_platform_timer = ON_PLATFORM_TIMER;
-                *grounded_platform_linvel = platform.linvel;
-                // Note:  has_any_active_contacts() always returns false, because the kinematic character controller keeps the player very slightly floating.
-                // we are sort of faking this by just checking whether the player is very close to the platform.
-            }
+    if let Some(jetpack) = jetpack {
+        jetpack.thrusting = input.thrust && jetpack.fuel > 0.0;
+        if jetpack.thrusting {
+            motion.vertical_speed = jetpack.burn(motion.vertical_speed, delta_time);
+            // Thrusting off the ground shouldn't leave a window for a grounded jump.
+            motion.grounded_timer = 0.0;
+        } else if grounded {
+            jetpack.refuel(delta_time);
         }
     }
-    // Rapier is not reliably returning a collision between the player and the platform on every frame
-    // This was causing slippage, where some frames the player does not get the platform's linvel
-    // We add a timeout period so that if the player has been on a platform within ON_PLATFORM_TIMER seconds,
-    // We add the linvel of the platform that the player was most recently on to the player's linvel.
-    if *grounded_platform_timer > 0.0 {
-        *grounded_platform_timer -= delta_time;
-        translation += *grounded_platform_linvel;
-        if jump_speed > 0.0 {
-            // If the player jumped in this frame, then immediately set him to off the platform.
-            *grounded_platform_timer = 0.0;
+    movement.y = motion.vertical_speed;
+    motion.vertical_speed +=
+        GRAVITY * delta_time * controller.custom_mass.unwrap_or(1.0) * motion.gravity_scale;
+    let mut translation = player_transform.rotation * movement + motion.external_velocity;
+
+    let drag = if grounded { GROUND_FRICTION } else { AIR_DRAG };
+    motion.external_velocity *= (1.0 - drag * delta_time).max(0.0);
+    motion.gravity_scale = 1.0;
+    motion.walk_scale = 1.0;
+
+    let jumped = motion.vertical_speed > 0.0;
+    if let Some(platform) = support.filter(|_| !jumped) {
+        motion.riding = Some(platform);
+        motion.riding_timer = ON_PLATFORM_TIMER;
+    }
+
+    // Carry the player rigidly with the platform, in the platform's frame, so that rotating and accelerating
+    // platforms don't slide out from under them.
+    let riding = motion
+        .riding
+        .filter(|_| motion.riding_timer > 0.0)
+        .and_then(|entity| platforms.get(entity).ok())
+        .filter(|(_, _, platform)| {
+            within_footprint(
+                &platform.previous,
+                platform.half_extents,
+                player_position,
+                PLAYER_HALF_WIDTH,
+            )
+        });
+    if let Some((_, platform_transform, platform)) = riding {
+        motion.riding_timer -= delta_time;
+        let carry_velocity =
+            (platform.carry(platform_transform, player_position) - player_position) / delta_time;
+        translation += carry_velocity;
+        if jumped {
+            // Jumping off a moving platform keeps its momentum.
+            motion.external_velocity += carry_velocity;
+            motion.riding = None;
+            motion.riding_timer = 0.0;
+        } else {
+            carried_yaw = Some(carry_yaw(&platform.previous, platform_transform).to_degrees());
+        }
+    } else {
+        motion.riding = None;
+        motion.riding_timer = 0.0;
+    }
+
+    // Platforms moving into the player from the side push them along.
+    for (platform_entity, platform_transform, platform) in platforms {
+        if Some(platform_entity) == motion.riding {
+            continue;
+        }
+        let Some(push) = lateral_push(
+            platform_transform,
+            platform.half_extents,
+            player_position,
+            PLAYER_HALF_WIDTH,
+        ) else {
+            continue;
+        };
+        let platform_motion = platform.carry(platform_transform, player_position) - player_position;
+        if platform_motion.dot(push) > 0.0 {
+            translation += push / delta_time;
         }
     }
     controller.translation = Some(translation * delta_time);
+    carried_yaw
 }
 
 fn player_look(
@@ -223,15 +500,38 @@ fn player_look(
     camera_transform.rotation = Quat::from_axis_angle(Vec3::X, look.y.to_radians());
 }
 
-fn spawn_player(
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn spawn_player(
     mut commands: Commands,
     assets: Res<AssetServer>, // mut meshes: ResMut<Assets<Mesh>>,
     cache: Res<AssetCache>,
     // mut materials: ResMut<Assets<StandardMaterial>>,
     mut next_state: ResMut<NextState<GameState>>,
     game_world: Res<GameWorldImage>,
+    spawn_points: Query<&Transform, With<SpawnPoint>>,
+    transforms: Query<&Transform>,
+    settings: Res<RespawnSettings>,
+    checkpoint: Res<Checkpoint>,
+    last_death: Res<LastDeath>,
+    mut look: ResMut<LookInput>,
+    progression: Res<Progression>,
 ) {
     info!("Spawning Player");
+    let stats = progression.stats();
+    let points: Vec<Transform> = spawn_points.iter().copied().collect();
+    let checkpoint = checkpoint.0.and_then(|entity| transforms.get(entity).ok());
+    let died_at = last_death.0.as_ref().map(|death| death.position);
+    let spawn = choose_spawn(
+        settings.policy,
+        &points,
+        checkpoint.copied(),
+        died_at,
+        &mut rand::thread_rng(),
+    )
+    .unwrap_or_else(fallback_spawn);
+    // player_look turns the player to face the look input, so face along the spawn point from the start.
+    let (yaw, _, _) = spawn.rotation.to_euler(EulerRot::YXZ);
+    **look = Vec2::new(yaw.to_degrees(), 0.0);
     let flashlight = (
         SpotLightBundle {
             spot_light: SpotLight {
@@ -248,68 +548,15 @@ fn spawn_player(
         },
         Name::new("flashlight"),
     );
-    let mut fireball_timer =
-        Timer::new(std::time::Duration::from_millis(100), TimerMode::Repeating);
-
-    // Player should start with it ready.
-    fireball_timer.tick(Duration::from_millis(1000));
-
     let player = (
         Name::new("player"),
         SceneBundle {
             scene: assets.load("Player.gltf#Scene0"),
-            transform: Transform::from_xyz(102.173, 250., 54.987)
-                .looking_at(Vec3::new(0., -1., -1.), Vec3::Y),
-            ..default()
-        },
-        Leash(1000.),
-        RigidBody::KinematicPositionBased,
-        Collider::cuboid(0.3, 1.0, 0.3),
-        ActiveEvents::COLLISION_EVENTS, // Make sure that we always solve for player contacts.
-        KinematicCharacterController {
-            custom_mass: Some(5.0),
-            up: Vec3::Y,
-            offset: CharacterLength::Absolute(0.0001),
-            slide: true,
-            autostep: Some(CharacterAutostep {
-                max_height: CharacterLength::Relative(0.3),
-                min_width: CharacterLength::Relative(0.5),
-                include_dynamic_bodies: false,
-            }),
-            // Don’t allow climbing slopes larger than 45 degrees.
-            max_slope_climb_angle: 45.0_f32.to_radians(),
-            // Automatically slide down on slopes smaller than 30 degrees.
-            min_slope_slide_angle: 30.0_f32.to_radians(),
-            apply_impulse_to_dynamic_bodies: true,
-            snap_to_ground: Some(CharacterLength::Absolute(0.2)),
+            transform: spawn,
             ..default()
         },
         Player,
-        FireballAbility {
-            mana_cost: 5,
-            cooldown_timer: fireball_timer,
-            projectile_speed: 70.,
-            projectile_radius: 0.3,
-            gravity: 1.,
-            damping: 1.,
-            ..default()
-        },
-        Hp {
-            current: 100,
-            max: 100,
-        },
-        HpRegen {
-            tick_timer: Timer::new(Duration::from_millis(1000), TimerMode::Repeating),
-            regen_per_tick: 5,
-        },
-        Mana {
-            current: 100,
-            max: 100,
-        },
-        ManaRegen {
-            regen_mana_timer: Timer::new(Duration::from_millis(1000), TimerMode::Repeating),
-            regen_per_tick: 5,
-        },
+        character_bundle(&assets, &stats),
     );
 
     commands.spawn(player).with_children(|b| {
@@ -327,7 +574,7 @@ fn spawn_player(
                     target: game_world.0.clone().into(),
                     ..default()
                 },
-                transform: Transform::from_xyz(0.0, 0.7, -1.0),
+                transform: Transform::from_translation(EYE_OFFSET),
                 ..default()
             },
             Skybox {
@@ -352,3 +599,80 @@ fn spawn_player(
     });
     next_state.set(GameState::InGame)
 }
+
+/// Where a character drops in when the level has no spawn points: over the canyon.
+pub(crate) fn fallback_spawn() -> Transform {
+    Transform::from_xyz(102.173, 250., 54.987).looking_at(Vec3::new(0., -1., -1.), Vec3::Y)
+}
+
+/// Makes a collider into a sensor that characters set off by walking into it. Characters are kinematic, and
+/// Rapier leaves out kinematic bodies touching fixed colliders unless it's asked for them.
+pub fn character_sensor() -> impl Bundle {
+    (
+        Sensor,
+        ActiveEvents::COLLISION_EVENTS,
+        ActiveCollisionTypes::default() | ActiveCollisionTypes::KINEMATIC_STATIC,
+    )
+}
+
+/// Everything that walks, casts and takes damage like a player, apart from its transform and its looks.
+/// The local `Player` is built on this, and so are the characters that the network server runs for its
+/// clients.
+pub fn character_bundle(assets: &AssetServer, stats: &CharacterStats) -> impl Bundle {
+    (
+        OutOfBounds::Despawn,
+        RigidBody::KinematicPositionBased,
+        Collider::cuboid(PLAYER_HALF_WIDTH, PLAYER_HALF_HEIGHT, PLAYER_HALF_WIDTH),
+        ActiveEvents::COLLISION_EVENTS, // Make sure that we always solve for player contacts.
+        KinematicCharacterController {
+            custom_mass: Some(5.0),
+            up: Vec3::Y,
+            offset: CharacterLength::Absolute(0.0001),
+            slide: true,
+            autostep: Some(CharacterAutostep {
+                max_height: CharacterLength::Relative(0.3),
+                min_width: CharacterLength::Relative(0.5),
+                include_dynamic_bodies: false,
+            }),
+            // Don’t allow climbing slopes larger than 45 degrees.
+            max_slope_climb_angle: 45.0_f32.to_radians(),
+            // Automatically slide down on slopes smaller than 30 degrees.
+            min_slope_slide_angle: 30.0_f32.to_radians(),
+            apply_impulse_to_dynamic_bodies: true,
+            snap_to_ground: Some(CharacterLength::Absolute(0.2)),
+            ..default()
+        },
+        (
+            Spellbook::starting_spells(assets),
+            HitscanWeapon::default(),
+            Inventory::default(),
+            Equipment::default(),
+        ),
+        Hp {
+            current: stats.max_hp,
+            max: stats.max_hp,
+        },
+        HpRegen {
+            tick_timer: Timer::new(Duration::from_millis(1000), TimerMode::Repeating),
+            regen_per_tick: stats.hp_regen,
+        },
+        Mana {
+            current: stats.max_mana,
+            max: stats.max_mana,
+        },
+        ManaRegen {
+            regen_mana_timer: Timer::new(Duration::from_millis(1000), TimerMode::Repeating),
+            regen_per_tick: stats.mana_regen,
+        },
+        // Bundle tuples are limited to 15 elements, so the movement components are grouped.
+        (
+            MovementProfile::default(),
+            PlayerMotion::default(),
+            PlayerInput::default(),
+            JetpackAbility::default(),
+            DashAbility::default(),
+            SlideAbility::default(),
+            WallRunAbility::default(),
+        ),
+    )
+}
@@ -1,4 +1,4 @@
-use crate::{hitpoints::Hp, mana::Mana, prelude::*};
+use crate::{hitpoints::Hp, items::JetpackAbility, mana::Mana, prelude::*};
 use bevy::prelude::*;
 
 pub const RIGHT_HAND_SIDE_WIDTH: f32 = 200.;
@@ -15,6 +15,9 @@ pub struct HpBar;
 #[derive(Component)]
 pub struct ManaBar;
 
+#[derive(Component)]
+pub struct FuelBar;
+
 impl Plugin for PlayerHudPlugin {
     fn build(&self, app: &mut App) {
         app.add_systems(OnEnter(GameState::InGame), insert_bars);
@@ -114,6 +117,27 @@ fn insert_bars(mut commands: Commands) {
                         ManaBar,
                         Name::new("player_mana_bar"),
                     ));
+
+                    p.spawn((
+                        NodeBundle {
+                            style: Style {
+                                margin: UiRect {
+                                    top: Val::Px(BAR_MARGIN_Y),
+                                    bottom: Val::Px(BAR_MARGIN_Y),
+                                    left: Val::Px(BAR_MARGIN_X),
+                                    right: Val::Px(BAR_MARGIN_X),
+                                },
+                                align_self: AlignSelf::FlexEnd,
+                                width: Val::Px(BAR_WIDTH),
+                                height: Val::Px(BAR_HEIGHT),
+                                ..default()
+                            },
+                            background_color: Palette::Yellow.into(),
+                            ..default()
+                        },
+                        FuelBar,
+                        Name::new("player_fuel_bar"),
+                    ));
                 });
             });
         });
@@ -123,6 +147,7 @@ fn remove_bars(
     mut commands: Commands,
     mut hp: Query<Entity, With<HpBar>>,
     mut mana: Query<Entity, With<ManaBar>>,
+    mut fuel: Query<Entity, With<FuelBar>>,
 ) {
     if let Ok(hp) = hp.get_single() {
         commands.entity(hp).despawn_recursive();
@@ -130,12 +155,17 @@ fn remove_bars(
     if let Ok(mana) = mana.get_single() {
         commands.entity(mana).despawn_recursive();
     }
+    if let Ok(fuel) = fuel.get_single() {
+        commands.entity(fuel).despawn_recursive();
+    }
 }
 fn update_bars(
     player_mana: Query<&Mana, With<Player>>,
     player_hp: Query<&Hp, With<Player>>,
-    mut hp_bar: Query<&mut Style, (With<HpBar>, Without<ManaBar>)>,
-    mut mana_bar: Query<&mut Style, With<ManaBar>>,
+    player_fuel: Query<&JetpackAbility, With<Player>>,
+    mut hp_bar: Query<&mut Style, (With<HpBar>, Without<ManaBar>, Without<FuelBar>)>,
+    mut mana_bar: Query<&mut Style, (With<ManaBar>, Without<FuelBar>)>,
+    mut fuel_bar: Query<&mut Style, With<FuelBar>>,
 ) {
     if let Ok(mut mana_bar) = mana_bar.get_single_mut() {
         if let Ok(mana) = player_mana.get_single() {
@@ -149,4 +179,10 @@ fn update_bars(
             hp_bar.height = Val::Px(BAR_HEIGHT * percent);
         }
     }
+    if let Ok(mut fuel_bar) = fuel_bar.get_single_mut() {
+        if let Ok(jetpack) = player_fuel.get_single() {
+            let percent = jetpack.fuel / jetpack.max_fuel;
+            fuel_bar.height = Val::Px(BAR_HEIGHT * percent);
+        }
+    }
 }
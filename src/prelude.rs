@@ -1,5 +1,5 @@
 #[allow(unused)]
 pub(crate) use crate::{
     asset_cache::AssetCache, palette::Palette, player::Player, routes::hud_route::GameWorldImage,
-    world::Leash, GameState,
+    world::OutOfBounds, GameState,
 };
@@ -0,0 +1,690 @@
+use crate::{
+    hitpoints::{Dead, Hp, HpRegen},
+    items::{InnateProjectileModifiers, ProjectileModifiers, SpellCostScale, SpellSlot, Spellbook},
+    mana::{Mana, ManaRegen},
+    missions::{MissionState, ObjectiveCompleted},
+    prelude::*,
+};
+use bevy::prelude::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Experience from kills and mission objectives, the character levels that it adds up to, and the talents that
+/// levelling pays for. Levels raise the player's base stats; talents upgrade their spells. K opens the talent
+/// panel, where the arrow keys pick a talent and L learns it.
+pub struct ProgressionPlugin;
+
+impl Plugin for ProgressionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Progression>();
+        app.init_resource::<TalentPanel>();
+        app.add_event::<GainXp>();
+        app.observe(award_kills);
+        app.add_systems(OnEnter(GameState::InGame), spawn_progression_hud);
+        app.add_systems(OnEnter(MissionState::Succeeded), award_mission);
+        app.add_systems(
+            Update,
+            (
+                award_objectives,
+                gain_xp,
+                talent_input.run_if(in_state(GameState::InGame)),
+                apply_progression,
+                update_progression_hud,
+                fade_level_up_notice,
+            )
+                .chain(),
+        );
+    }
+}
+
+const XP_PER_LEVEL: u32 = 100;
+pub const MAX_LEVEL: u32 = 20;
+const OBJECTIVE_XP: u32 = 50;
+const MISSION_XP: u32 = 150;
+const LEVEL_UP_NOTICE_SECONDS: f32 = 3.0;
+
+/// Experience for whoever kills this entity.
+#[derive(Component, Debug, Clone, Copy)]
+pub struct XpBounty(pub u32);
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct GainXp(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum Talent {
+    /// Faster projectiles.
+    Kindling,
+    /// Bigger projectiles and blasts.
+    Conflagration,
+    /// Cheaper spells.
+    Focus,
+    /// More hit points.
+    Vitality,
+    /// Unlocks the Ward spell.
+    Warding,
+}
+
+/// Where a talent sits in the tree.
+#[derive(Debug, Clone, Copy)]
+pub struct TalentInfo {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub max_rank: u32,
+    /// The character level that the first rank needs.
+    pub min_level: u32,
+    /// A talent, and the rank of it, that has to be learned first.
+    pub requires: Option<(Talent, u32)>,
+}
+
+impl Talent {
+    pub const ALL: [Talent; 5] = [
+        Talent::Kindling,
+        Talent::Conflagration,
+        Talent::Focus,
+        Talent::Vitality,
+        Talent::Warding,
+    ];
+
+    pub fn info(self) -> TalentInfo {
+        match self {
+            Talent::Kindling => TalentInfo {
+                name: "Kindling",
+                description: "Projectiles fly 20% faster per rank.",
+                max_rank: 3,
+                min_level: 1,
+                requires: None,
+            },
+            Talent::Conflagration => TalentInfo {
+                name: "Conflagration",
+                description: "Projectiles and their blasts are 15% bigger per rank.",
+                max_rank: 3,
+                min_level: 3,
+                requires: Some((Talent::Kindling, 1)),
+            },
+            Talent::Focus => TalentInfo {
+                name: "Focus",
+                description: "Spells cost 10% less mana per rank.",
+                max_rank: 3,
+                min_level: 1,
+                requires: None,
+            },
+            Talent::Vitality => TalentInfo {
+                name: "Vitality",
+                description: "20 more hit points per rank.",
+                max_rank: 3,
+                min_level: 1,
+                requires: None,
+            },
+            Talent::Warding => TalentInfo {
+                name: "Warding",
+                description: "Learn Ward, a spell that shields you from damage.",
+                max_rank: 1,
+                min_level: 4,
+                requires: Some((Talent::Focus, 1)),
+            },
+        }
+    }
+
+    /// The spell that learning the talent adds to the spellbook.
+    pub fn spell(self) -> Option<&'static str> {
+        match self {
+            Talent::Warding => Some("spells/ward.spell.ron"),
+            _ => None,
+        }
+    }
+}
+
+/// The player's stats at their level and with their talents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharacterStats {
+    pub max_hp: u32,
+    pub max_mana: u32,
+    pub hp_regen: u32,
+    pub mana_regen: u32,
+}
+
+/// The player's experience, level and talents. It outlives the player, so it's kept through respawning.
+#[derive(Resource, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Progression {
+    pub level: u32,
+    /// Experience toward the next level.
+    pub xp: u32,
+    pub talent_points: u32,
+    /// Each talent learned, and to which rank.
+    pub talents: BTreeMap<Talent, u32>,
+}
+
+impl Default for Progression {
+    fn default() -> Self {
+        Progression {
+            level: 1,
+            xp: 0,
+            talent_points: 0,
+            talents: BTreeMap::new(),
+        }
+    }
+}
+
+impl Progression {
+    pub fn xp_to_next_level(&self) -> u32 {
+        XP_PER_LEVEL * self.level
+    }
+
+    /// Adds `xp`, levelling up as many times as it pays for with a talent point each time. Returns how many
+    /// levels were gained.
+    pub fn gain(&mut self, xp: u32) -> u32 {
+        if self.level >= MAX_LEVEL {
+            return 0;
+        }
+        self.xp += xp;
+        let mut levels = 0;
+        while self.level < MAX_LEVEL && self.xp >= self.xp_to_next_level() {
+            self.xp -= self.xp_to_next_level();
+            self.level += 1;
+            self.talent_points += 1;
+            levels += 1;
+        }
+        if self.level >= MAX_LEVEL {
+            self.xp = 0;
+        }
+        levels
+    }
+
+    pub fn rank(&self, talent: Talent) -> u32 {
+        self.talents.get(&talent).copied().unwrap_or_default()
+    }
+
+    /// Spends a talent point on another rank of `talent`, or says why it can't.
+    pub fn learn(&mut self, talent: Talent) -> Result<(), &'static str> {
+        let info = talent.info();
+        if self.talent_points == 0 {
+            return Err("No talent points to spend.");
+        }
+        if self.rank(talent) >= info.max_rank {
+            return Err("Already at the highest rank.");
+        }
+        if self.level < info.min_level {
+            return Err("Not a high enough level yet.");
+        }
+        if let Some((required, rank)) = info.requires {
+            if self.rank(required) < rank {
+                return Err("Needs another talent first.");
+            }
+        }
+        self.talent_points -= 1;
+        *self.talents.entry(talent).or_default() += 1;
+        Ok(())
+    }
+
+    pub fn stats(&self) -> CharacterStats {
+        let gained = self.level - 1;
+        CharacterStats {
+            max_hp: 100 + 10 * gained + 20 * self.rank(Talent::Vitality),
+            max_mana: 100 + 10 * gained,
+            hp_regen: 5 + gained / 2,
+            mana_regen: 5 + gained / 2,
+        }
+    }
+
+    pub fn projectile_modifiers(&self) -> ProjectileModifiers {
+        let speed = 1.0 + 0.2 * self.rank(Talent::Kindling) as f32;
+        let size = 1.0 + 0.15 * self.rank(Talent::Conflagration) as f32;
+        ProjectileModifiers {
+            speed,
+            radius: size,
+            blast_radius: size,
+            ..default()
+        }
+    }
+
+    pub fn mana_cost_scale(&self) -> f32 {
+        1.0 - 0.1 * self.rank(Talent::Focus) as f32
+    }
+
+    /// Spells that the player's talents have added to their spellbook.
+    pub fn unlocked_spells(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.talents.keys().filter_map(|talent| talent.spell())
+    }
+}
+
+#[derive(Resource, Debug, Default)]
+struct TalentPanel {
+    open: bool,
+    selected: usize,
+    message: String,
+}
+
+#[derive(Component)]
+struct ProgressionText;
+
+#[derive(Component)]
+struct TalentPanelText;
+
+/// Announces a new level in the middle of the HUD, and clears itself when the timer runs out.
+#[derive(Component)]
+struct LevelUpNotice(Timer);
+
+fn award_kills(
+    trigger: Trigger<OnAdd, Dead>,
+    dead: Query<(&Dead, &XpBounty)>,
+    players: Query<(), With<Player>>,
+    mut xp: EventWriter<GainXp>,
+) {
+    let Ok((dead, bounty)) = dead.get(trigger.entity()) else {
+        return;
+    };
+    if dead.killer.is_some_and(|killer| players.contains(killer)) {
+        xp.send(GainXp(bounty.0));
+    }
+}
+
+fn award_objectives(mut completed: EventReader<ObjectiveCompleted>, mut xp: EventWriter<GainXp>) {
+    for _ in completed.read() {
+        xp.send(GainXp(OBJECTIVE_XP));
+    }
+}
+
+fn award_mission(mut xp: EventWriter<GainXp>) {
+    xp.send(GainXp(MISSION_XP));
+}
+
+fn gain_xp(
+    mut events: EventReader<GainXp>,
+    mut progression: ResMut<Progression>,
+    mut notices: Query<(&mut Text, &mut LevelUpNotice)>,
+) {
+    for GainXp(xp) in events.read() {
+        let levels = progression.gain(*xp);
+        if levels > 0 {
+            info!("Reached level {}", progression.level);
+            for (mut text, mut notice) in &mut notices {
+                text.sections[0].value = format!(
+                    "Reached level {}! Press K to spend talent points.",
+                    progression.level
+                );
+                notice.0.reset();
+            }
+        }
+    }
+}
+
+fn fade_level_up_notice(time: Res<Time>, mut notices: Query<(&mut Text, &mut LevelUpNotice)>) {
+    for (mut text, mut notice) in &mut notices {
+        if notice.0.tick(time.delta()).just_finished() {
+            text.sections[0].value.clear();
+        }
+    }
+}
+
+fn talent_input(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut panel: ResMut<TalentPanel>,
+    mut progression: ResMut<Progression>,
+) {
+    if keyboard.just_pressed(KeyCode::KeyK) {
+        panel.open = !panel.open;
+        panel.message.clear();
+    }
+    if !panel.open {
+        return;
+    }
+    let last = Talent::ALL.len() - 1;
+    if keyboard.just_pressed(KeyCode::ArrowRight) {
+        panel.selected = (panel.selected + 1).min(last);
+    }
+    if keyboard.just_pressed(KeyCode::ArrowLeft) {
+        panel.selected = panel.selected.saturating_sub(1);
+    }
+    if keyboard.just_pressed(KeyCode::KeyL) {
+        let talent = Talent::ALL[panel.selected];
+        panel.message = match progression.learn(talent) {
+            Ok(()) => format!(
+                "Learned {} rank {}",
+                talent.info().name,
+                progression.rank(talent)
+            ),
+            Err(reason) => reason.to_string(),
+        };
+    }
+}
+
+/// Keeps the player's stats, spell modifiers and spellbook in line with their level and talents.
+#[allow(clippy::type_complexity)]
+fn apply_progression(
+    mut commands: Commands,
+    progression: Res<Progression>,
+    assets: Res<AssetServer>,
+    spawned: Query<(), Added<Player>>,
+    mut player: Query<
+        (
+            Entity,
+            &mut Hp,
+            &mut Mana,
+            Option<&mut HpRegen>,
+            Option<&mut ManaRegen>,
+            Option<&mut Spellbook>,
+        ),
+        With<Player>,
+    >,
+) {
+    if !progression.is_changed() && spawned.is_empty() {
+        return;
+    }
+    let Ok((entity, mut hp, mut mana, hp_regen, mana_regen, spellbook)) = player.get_single_mut()
+    else {
+        return;
+    };
+    let stats = progression.stats();
+    // Whatever the maximum grows by is topped up too.
+    let gained = stats.max_hp.saturating_sub(hp.max);
+    hp.max = stats.max_hp;
+    hp.current = (hp.current + gained).min(hp.max);
+    let gained = stats.max_mana.saturating_sub(mana.max);
+    mana.max = stats.max_mana;
+    mana.current = (mana.current + gained).min(mana.max);
+    if let Some(mut regen) = hp_regen {
+        regen.regen_per_tick = stats.hp_regen;
+    }
+    if let Some(mut regen) = mana_regen {
+        regen.regen_per_tick = stats.mana_regen;
+    }
+    commands.entity(entity).insert((
+        InnateProjectileModifiers(progression.projectile_modifiers()),
+        SpellCostScale(progression.mana_cost_scale()),
+    ));
+    let Some(mut spellbook) = spellbook else {
+        return;
+    };
+    // Talent spells are unlocked where they sit in the spellbook, and only added if it doesn't have them.
+    for talent in Talent::ALL {
+        let Some(path) = talent.spell() else {
+            continue;
+        };
+        let learned = progression.rank(talent) > 0;
+        if !spellbook.set_locked(path, !learned) && learned {
+            spellbook.slots.push(SpellSlot::new(assets.load(path)));
+        }
+    }
+}
+
+fn spawn_progression_hud(mut commands: Commands) {
+    commands.spawn((
+        Name::new("progression_text"),
+        ProgressionText,
+        StateScoped(GameState::InGame),
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 20.0,
+                color: Palette::Yellow.to_color(),
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(80.0),
+            left: Val::Px(20.0),
+            ..default()
+        }),
+    ));
+    commands.spawn((
+        Name::new("talent_panel"),
+        TalentPanelText,
+        StateScoped(GameState::InGame),
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 20.0,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Px(120.0),
+            right: Val::Px(20.0),
+            ..default()
+        })
+        .with_background_color(Palette::HudBackground.to_color().with_alpha(0.1)),
+    ));
+    commands.spawn((
+        Name::new("level_up_notice"),
+        LevelUpNotice(Timer::from_seconds(
+            LEVEL_UP_NOTICE_SECONDS,
+            TimerMode::Once,
+        )),
+        StateScoped(GameState::InGame),
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 32.0,
+                color: Palette::Yellow.to_color(),
+                ..default()
+            },
+        )
+        .with_text_justify(JustifyText::Center)
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            top: Val::Percent(25.0),
+            width: Val::Percent(100.0),
+            ..default()
+        }),
+    ));
+}
+
+fn update_progression_hud(
+    progression: Res<Progression>,
+    panel: Res<TalentPanel>,
+    spawned: Query<(), Added<ProgressionText>>,
+    mut hud: Query<&mut Text, (With<ProgressionText>, Without<TalentPanelText>)>,
+    mut talents: Query<(&mut Text, &mut Visibility), With<TalentPanelText>>,
+) {
+    if !progression.is_changed() && !panel.is_changed() && spawned.is_empty() {
+        return;
+    }
+    if let Ok(mut text) = hud.get_single_mut() {
+        let mut line = format!("Level {}", progression.level);
+        if progression.level < MAX_LEVEL {
+            line += &format!("  XP {}/{}", progression.xp, progression.xp_to_next_level());
+        }
+        if progression.talent_points > 0 {
+            line += &format!("  {} talent points (K)", progression.talent_points);
+        }
+        text.sections[0].value = line;
+    }
+    let Ok((mut text, mut visibility)) = talents.get_single_mut() else {
+        return;
+    };
+    *visibility = if panel.open {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    let style = |color: Color| TextStyle {
+        font_size: 20.0,
+        color,
+        ..default()
+    };
+    let mut sections = vec![TextSection::new(
+        format!("Talents ({} points)\n", progression.talent_points),
+        style(Color::WHITE),
+    )];
+    for (i, talent) in Talent::ALL.into_iter().enumerate() {
+        let info = talent.info();
+        let color = if i == panel.selected {
+            Palette::Yellow.to_color()
+        } else if progression.rank(talent) > 0 {
+            Palette::Blue.to_color()
+        } else {
+            Color::WHITE
+        };
+        sections.push(TextSection::new(
+            format!(
+                "{} {}/{}\n",
+                info.name,
+                progression.rank(talent),
+                info.max_rank
+            ),
+            style(color),
+        ));
+    }
+    let info = Talent::ALL[panel.selected].info();
+    let mut requirements = format!("Level {}", info.min_level);
+    if let Some((required, rank)) = info.requires {
+        requirements += &format!(", {} {rank}", required.info().name);
+    }
+    sections.push(TextSection::new(
+        format!(
+            "{}\nNeeds: {requirements}\n{}\nLeft/Right: choose  L: learn",
+            info.description, panel.message
+        ),
+        style(Color::WHITE),
+    ));
+    text.sections = sections;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn experience_levels_up_and_pays_talent_points() {
+        let mut progression = Progression::default();
+        assert_eq!(progression.gain(90), 0);
+        // 100 to reach level 2, then 200 more for level 3.
+        assert_eq!(progression.gain(260), 2);
+        assert_eq!(progression.level, 3);
+        assert_eq!(progression.xp, 50);
+        assert_eq!(progression.talent_points, 2);
+        assert_eq!(progression.stats().max_hp, 120);
+
+        progression.gain(u32::MAX / 2);
+        assert_eq!(progression.level, MAX_LEVEL);
+        assert_eq!(progression.xp, 0);
+    }
+
+    #[test]
+    fn talents_need_points_levels_and_prerequisites() {
+        let mut progression = Progression {
+            level: 3,
+            talent_points: 3,
+            ..default()
+        };
+        assert!(progression.learn(Talent::Conflagration).is_err());
+        assert!(progression.learn(Talent::Warding).is_err());
+        progression.learn(Talent::Kindling).unwrap();
+        progression.learn(Talent::Conflagration).unwrap();
+        progression.learn(Talent::Focus).unwrap();
+        assert_eq!(progression.talent_points, 0);
+        assert!(progression.learn(Talent::Kindling).is_err());
+
+        let modifiers = progression.projectile_modifiers();
+        assert_eq!(modifiers.speed, 1.2);
+        assert_eq!(modifiers.blast_radius, 1.15);
+        assert_eq!(progression.mana_cost_scale(), 0.9);
+        assert_eq!(progression.unlocked_spells().count(), 0);
+    }
+
+    #[test]
+    fn only_the_players_kills_earn_experience() {
+        let mut world = World::new();
+        world.init_resource::<Events<GainXp>>();
+        world.observe(award_kills);
+        let player = world.spawn(Player).id();
+        let enemy = world.spawn_empty().id();
+        for killer in [Some(player), Some(enemy), None] {
+            world.spawn(XpBounty(25)).insert(Dead {
+                killer,
+                ..default()
+            });
+        }
+        let gained: Vec<u32> = world
+            .resource_mut::<Events<GainXp>>()
+            .drain()
+            .map(|GainXp(xp)| xp)
+            .collect();
+        assert_eq!(gained, vec![25]);
+    }
+
+    #[test]
+    fn levelling_up_is_announced_on_the_hud() {
+        let mut world = World::new();
+        world.init_resource::<Events<GainXp>>();
+        world.init_resource::<Progression>();
+        let notice = world
+            .spawn((
+                Text::from_section("", default()),
+                LevelUpNotice(Timer::from_seconds(
+                    LEVEL_UP_NOTICE_SECONDS,
+                    TimerMode::Once,
+                )),
+            ))
+            .id();
+        world.send_event(GainXp(50));
+        world.run_system_once(gain_xp);
+        // Every run_system_once starts a fresh reader, which would read this experience again next time.
+        world.resource_mut::<Events<GainXp>>().clear();
+        assert_eq!(world.get::<Text>(notice).unwrap().sections[0].value, "");
+
+        world.send_event(GainXp(50));
+        world.run_system_once(gain_xp);
+        assert!(world.get::<Text>(notice).unwrap().sections[0]
+            .value
+            .starts_with("Reached level 2!"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn talent_spells_keep_their_hotbar_slot() {
+        use crate::items::SpellDefinition;
+
+        let mut app = App::new();
+        app.add_plugins((MinimalPlugins, AssetPlugin::default()))
+            .init_asset::<SpellDefinition>()
+            .init_resource::<Progression>()
+            .add_systems(Update, apply_progression);
+        let spellbook = Spellbook::starting_spells(app.world().resource::<AssetServer>());
+        let player = app
+            .world_mut()
+            .spawn((
+                Player,
+                Hp {
+                    current: 100,
+                    max: 100,
+                },
+                Mana {
+                    current: 100,
+                    max: 100,
+                },
+                spellbook,
+            ))
+            .id();
+        let slots = |app: &App| -> Vec<(String, bool)> {
+            app.world()
+                .get::<Spellbook>(player)
+                .unwrap()
+                .slots
+                .iter()
+                .map(|slot| (slot.spell.path().unwrap().to_string(), slot.locked))
+                .collect()
+        };
+        let ward = (Talent::Warding.spell().unwrap().to_string(), true);
+        app.update();
+        let before = slots(&app);
+        assert_eq!(before[4], ward);
+        assert_eq!(before.iter().filter(|slot| slot.1).count(), 1);
+
+        app.world_mut()
+            .resource_mut::<Progression>()
+            .talents
+            .insert(Talent::Warding, 1);
+        app.update();
+        let after = slots(&app);
+        assert_eq!(after.len(), before.len());
+        assert_eq!(after[4], (ward.0, false));
+        assert_eq!(after[5], before[5]);
+    }
+}
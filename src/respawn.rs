@@ -0,0 +1,288 @@
+use crate::{
+    hitpoints::{DamageType, Dead, Hp, Invulnerable},
+    mana::Mana,
+    player::spawn_player,
+    prelude::*,
+};
+use bevy::prelude::*;
+use rand::{seq::SliceRandom, Rng};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Where the player comes back after dying, and what happens in between: `Prespawn` shows how the player died
+/// until `RespawnSettings::delay` is up, then a click respawns them. A fresh player starts with full `Hp` and
+/// `Mana`, and can't be hurt for a moment.
+pub struct RespawnPlugin;
+
+impl Plugin for RespawnPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RespawnSettings>();
+        app.init_resource::<Checkpoint>();
+        app.init_resource::<LastDeath>();
+        app.observe(record_death);
+        app.add_systems(
+            OnEnter(GameState::Spawning),
+            prepare_spawned_player.after(spawn_player),
+        );
+        app.add_systems(OnEnter(GameState::Prespawn), spawn_death_screen);
+        app.add_systems(
+            Update,
+            update_death_screen.run_if(in_state(GameState::Prespawn)),
+        );
+    }
+}
+
+/// How `spawn_player` picks among the level's spawn points. Without any, the player drops in over the canyon.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SpawnPolicy {
+    /// The last checkpoint the player reached, or the first spawn point until then.
+    #[default]
+    Checkpoint,
+    /// The spawn point nearest to where the player died.
+    Nearest,
+    Random,
+}
+
+#[derive(Resource, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct RespawnSettings {
+    pub policy: SpawnPolicy,
+    /// Seconds after dying before the player can respawn.
+    pub delay: f32,
+    /// Seconds after spawning that the player can't be hurt.
+    pub invulnerability: f32,
+}
+
+impl Default for RespawnSettings {
+    fn default() -> Self {
+        RespawnSettings {
+            policy: SpawnPolicy::Checkpoint,
+            delay: 3.0,
+            invulnerability: 2.0,
+        }
+    }
+}
+
+/// The last `CheckpointVolume` that the player reached, where `SpawnPolicy::Checkpoint` respawns them.
+#[derive(Resource, Debug, Default)]
+pub struct Checkpoint(pub Option<Entity>);
+
+/// How the player last died, until they respawn.
+#[derive(Resource, Debug, Default)]
+pub struct LastDeath(pub Option<Death>);
+
+#[derive(Debug)]
+pub struct Death {
+    pub cause: String,
+    pub position: Vec3,
+    /// Counts down to when the player can respawn.
+    pub timer: Timer,
+}
+
+impl LastDeath {
+    pub fn can_respawn(&self) -> bool {
+        self.0.as_ref().is_none_or(|death| death.timer.finished())
+    }
+}
+
+#[derive(Component)]
+struct DeathScreenText;
+
+/// Where `policy` spawns the player, given the level's spawn `points` in the level's order.
+pub fn choose_spawn(
+    policy: SpawnPolicy,
+    points: &[Transform],
+    checkpoint: Option<Transform>,
+    died_at: Option<Vec3>,
+    rng: &mut impl Rng,
+) -> Option<Transform> {
+    let chosen = match policy {
+        SpawnPolicy::Checkpoint => checkpoint.as_ref(),
+        SpawnPolicy::Nearest => died_at.and_then(|died_at| {
+            points.iter().min_by(|a, b| {
+                a.translation
+                    .distance_squared(died_at)
+                    .total_cmp(&b.translation.distance_squared(died_at))
+            })
+        }),
+        SpawnPolicy::Random => points.choose(rng),
+    };
+    chosen.or(points.first()).copied()
+}
+
+/// What killed the player, for the death screen. A player removed without being `Dead` went out of bounds.
+fn cause_of_death(dead: Option<&Dead>, killer: Option<&Name>) -> String {
+    let Some(dead) = dead else {
+        return "Lost beyond the edge of the world".into();
+    };
+    let verb = match dead.damage_type {
+        DamageType::Physical => "Killed",
+        DamageType::Fire => "Burned to death",
+        DamageType::Arcane => "Unmade",
+    };
+    match killer {
+        Some(killer) => format!("{verb} by {killer}"),
+        None => verb.into(),
+    }
+}
+
+fn record_death(
+    trigger: Trigger<OnRemove, Player>,
+    players: Query<(&GlobalTransform, Option<&Dead>)>,
+    names: Query<&Name>,
+    settings: Res<RespawnSettings>,
+    mut last_death: ResMut<LastDeath>,
+) {
+    let Ok((transform, dead)) = players.get(trigger.entity()) else {
+        return;
+    };
+    let killer = dead
+        .and_then(|dead| dead.killer)
+        .and_then(|killer| names.get(killer).ok());
+    let cause = cause_of_death(dead, killer);
+    info!("Player died: {cause}");
+    last_death.0 = Some(Death {
+        cause,
+        position: transform.translation(),
+        timer: Timer::from_seconds(settings.delay, TimerMode::Once),
+    });
+}
+
+fn prepare_spawned_player(
+    mut commands: Commands,
+    settings: Res<RespawnSettings>,
+    mut last_death: ResMut<LastDeath>,
+    mut player: Query<(Entity, &mut Hp, &mut Mana), With<Player>>,
+) {
+    let Ok((entity, mut hp, mut mana)) = player.get_single_mut() else {
+        return;
+    };
+    hp.restore();
+    mana.restore();
+    commands
+        .entity(entity)
+        .insert(Invulnerable(Timer::from_seconds(
+            settings.invulnerability,
+            TimerMode::Once,
+        )));
+    last_death.0 = None;
+}
+
+fn spawn_death_screen(mut commands: Commands, last_death: Res<LastDeath>) {
+    if last_death.0.is_none() {
+        return;
+    }
+    commands
+        .spawn((
+            Name::new("death_screen"),
+            StateScoped(GameState::Prespawn),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    top: Val::Percent(40.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                DeathScreenText,
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 40.0,
+                        color: Palette::Red.to_color(),
+                        ..default()
+                    },
+                )
+                .with_text_justify(JustifyText::Center),
+            ));
+        });
+}
+
+fn update_death_screen(
+    time: Res<Time>,
+    mut last_death: ResMut<LastDeath>,
+    mut text: Query<&mut Text, With<DeathScreenText>>,
+) {
+    let Some(death) = &mut last_death.0 else {
+        return;
+    };
+    death.timer.tick(time.delta());
+    let Ok(mut text) = text.get_single_mut() else {
+        return;
+    };
+    let prompt = if death.timer.finished() {
+        "Click to respawn".to_string()
+    } else {
+        format!(
+            "Respawning in {:.0}...",
+            death.timer.remaining_secs().ceil()
+        )
+    };
+    text.sections[0].value = format!("{}\n{prompt}", death.cause);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn points(positions: &[Vec3]) -> Vec<Transform> {
+        positions
+            .iter()
+            .map(|position| Transform::from_translation(*position))
+            .collect()
+    }
+
+    #[test]
+    fn checkpoints_fall_back_to_the_first_spawn_point() {
+        let points = points(&[Vec3::ZERO, Vec3::X * 100.0]);
+        let mut rng = StdRng::seed_from_u64(1);
+        let spawn = |checkpoint, rng: &mut StdRng| {
+            choose_spawn(SpawnPolicy::Checkpoint, &points, checkpoint, None, rng)
+                .map(|transform| transform.translation)
+        };
+        assert_eq!(spawn(None, &mut rng), Some(Vec3::ZERO));
+        let checkpoint = Transform::from_xyz(5.0, 6.0, 7.0);
+        assert_eq!(
+            spawn(Some(checkpoint), &mut rng),
+            Some(Vec3::new(5.0, 6.0, 7.0))
+        );
+        assert_eq!(
+            choose_spawn(SpawnPolicy::Random, &[], None, None, &mut rng),
+            None
+        );
+    }
+
+    #[test]
+    fn nearest_spawns_closest_to_the_death() {
+        let points = points(&[Vec3::ZERO, Vec3::X * 100.0, Vec3::Z * 100.0]);
+        let mut rng = StdRng::seed_from_u64(1);
+        let died_at = Some(Vec3::new(90.0, -20.0, 10.0));
+        let spawn = choose_spawn(SpawnPolicy::Nearest, &points, None, died_at, &mut rng);
+        assert_eq!(spawn.unwrap().translation, Vec3::X * 100.0);
+    }
+
+    #[test]
+    fn deaths_name_their_cause() {
+        let dead = Dead {
+            killer: None,
+            damage_type: DamageType::Fire,
+        };
+        let killer = Name::new("caster");
+        assert_eq!(
+            cause_of_death(Some(&dead), Some(&killer)),
+            "Burned to death by caster"
+        );
+        assert_eq!(cause_of_death(Some(&dead), None), "Burned to death");
+        assert_eq!(
+            cause_of_death(None, None),
+            "Lost beyond the edge of the world"
+        );
+    }
+}
@@ -297,6 +297,116 @@ fn build_route(
                                 .pack::<Base>(),
                             crate::components::ui::button::Button { text: text.into() },
                         ));
+                        above += 1.0;
+                        let text = "Pad";
+                        ui.spawn((
+                            root.add("LaunchPad"),
+                            UiDepthBias(50.0),
+                            MaterialMesh2dBundle {
+                                mesh: Mesh2dHandle(meshes.add(Rectangle {
+                                    half_size: Vec2::new(50., 25.),
+                                })),
+                                material: materials.add(Palette::Blue.to_color()),
+                                ..default()
+                            },
+                            OnUiClickCommands::new(|commands| {
+                                info!("Spawning launch pad");
+                                commands.spawn(crate::items::LaunchPad::default());
+                            }),
+                            Element,
+                            Dimension::default(),
+                            UiLayout::window()
+                                .pos((
+                                    Rl(100.) - Ab(90.),
+                                    Ab(BUTTON_SPACING + (BUTTON_HEIGHT + BUTTON_SPACING) * above),
+                                ))
+                                .size(Ab((100., 50.)))
+                                .pack::<Base>(),
+                            crate::components::ui::button::Button { text: text.into() },
+                        ));
+                        above += 1.0;
+                        let text = "Clear";
+                        ui.spawn((
+                            root.add("ClearTargets"),
+                            UiDepthBias(50.0),
+                            MaterialMesh2dBundle {
+                                mesh: Mesh2dHandle(meshes.add(Rectangle {
+                                    half_size: Vec2::new(50., 25.),
+                                })),
+                                material: materials.add(Palette::Blue.to_color()),
+                                ..default()
+                            },
+                            OnUiClickCommands::new(|commands| {
+                                commands.add(|world: &mut World| {
+                                    world.send_event(crate::objects::TargetRangeCommand::Clear);
+                                });
+                            }),
+                            Element,
+                            Dimension::default(),
+                            UiLayout::window()
+                                .pos((
+                                    Rl(100.) - Ab(90.),
+                                    Ab(BUTTON_SPACING + (BUTTON_HEIGHT + BUTTON_SPACING) * above),
+                                ))
+                                .size(Ab((100., 50.)))
+                                .pack::<Base>(),
+                            crate::components::ui::button::Button { text: text.into() },
+                        ));
+                        above += 1.0;
+                        let text = "Reset";
+                        ui.spawn((
+                            root.add("ResetTargets"),
+                            UiDepthBias(50.0),
+                            MaterialMesh2dBundle {
+                                mesh: Mesh2dHandle(meshes.add(Rectangle {
+                                    half_size: Vec2::new(50., 25.),
+                                })),
+                                material: materials.add(Palette::Blue.to_color()),
+                                ..default()
+                            },
+                            OnUiClickCommands::new(|commands| {
+                                commands.add(|world: &mut World| {
+                                    world.send_event(crate::objects::TargetRangeCommand::Reset);
+                                });
+                            }),
+                            Element,
+                            Dimension::default(),
+                            UiLayout::window()
+                                .pos((
+                                    Rl(100.) - Ab(90.),
+                                    Ab(BUTTON_SPACING + (BUTTON_HEIGHT + BUTTON_SPACING) * above),
+                                ))
+                                .size(Ab((100., 50.)))
+                                .pack::<Base>(),
+                            crate::components::ui::button::Button { text: text.into() },
+                        ));
+                        above += 1.0;
+                        let text = "Enemy";
+                        ui.spawn((
+                            root.add("Enemy"),
+                            UiDepthBias(50.0),
+                            MaterialMesh2dBundle {
+                                mesh: Mesh2dHandle(meshes.add(Rectangle {
+                                    half_size: Vec2::new(50., 25.),
+                                })),
+                                material: materials.add(Palette::Blue.to_color()),
+                                ..default()
+                            },
+                            OnUiClickCommands::new(|commands| {
+                                info!("Spawning enemy");
+                                commands.spawn(crate::enemy::EnemyBundle::default());
+                            }),
+                            Element,
+                            Dimension::default(),
+                            UiLayout::window()
+                                .pos((
+                                    Rl(100.) - Ab(90.),
+                                    Ab(BUTTON_SPACING + (BUTTON_HEIGHT + BUTTON_SPACING) * above),
+                                ))
+                                .size(Ab((100., 50.)))
+                                .pack::<Base>(),
+                            crate::components::ui::button::Button { text: text.into() },
+                        ));
                     });
             });
     }
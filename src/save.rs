@@ -0,0 +1,962 @@
+use crate::{
+    encounters::{Encounter, EncounterMember, EncounterState},
+    hitpoints::Hp,
+    items::{
+        Equipment, EquipmentSlot, Inventory, ItemDefinition, ItemStack, Pickup, Platform,
+        PlatformPath, Spellbook,
+    },
+    leylines::{LeylinePuzzle, Leystone},
+    mana::Mana,
+    missions::{Mission, MissionState},
+    objects::{CheckpointReached, CheckpointVolume, Target, Targets},
+    prelude::*,
+    progression::Progression,
+    respawn::Checkpoint,
+};
+use anyhow::{bail, Result};
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+};
+
+/// Saves the player and their progression, targets, platforms, pickups, checkpoints, encounters, leyline
+/// puzzles and mission progress to numbered slots in `saves/`, and restores them. F5 and F9 quicksave and
+/// quickload; F6 picks one of the other slots, which F7 saves to and F8 loads. Loading from the flycam spawns
+/// the player first.
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<SaveGame>();
+        app.add_event::<LoadGame>();
+        app.init_resource::<SaveSlots>();
+        app.add_systems(Startup, spawn_save_message);
+        app.add_systems(
+            Update,
+            (
+                save_input,
+                save_and_load,
+                apply_pending_load
+                    .run_if(in_state(GameState::InGame).and_then(resource_exists::<PendingLoad>)),
+                fade_save_message,
+            )
+                .chain(),
+        );
+    }
+}
+
+/// Bumped whenever `SavedGame` changes shape. Files from other versions are refused rather than misread.
+pub const SAVE_VERSION: u32 = 6;
+/// The slot that F5 and F9 use.
+pub const QUICKSAVE_SLOT: usize = 0;
+/// Slots that F6 cycles through, after the quicksave slot.
+pub const SAVE_SLOTS: usize = 3;
+const SAVE_MESSAGE_SECONDS: f32 = 2.0;
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct SaveGame {
+    pub slot: usize,
+}
+
+#[derive(Event, Debug, Clone, Copy)]
+pub struct LoadGame {
+    pub slot: usize,
+}
+
+#[derive(Resource, Debug, Clone)]
+pub struct SaveSlots {
+    pub directory: PathBuf,
+    /// The slot that F7 and F8 save to and load from.
+    pub selected: usize,
+}
+
+impl Default for SaveSlots {
+    fn default() -> Self {
+        SaveSlots {
+            directory: "saves".into(),
+            selected: 1,
+        }
+    }
+}
+
+impl SaveSlots {
+    pub fn path(&self, slot: usize) -> PathBuf {
+        if slot == QUICKSAVE_SLOT {
+            self.directory.join("quicksave.sav")
+        } else {
+            self.directory.join(format!("slot{slot}.sav"))
+        }
+    }
+}
+
+/// What's on disk: the version, then the game.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveFile {
+    pub version: u32,
+    pub game: SavedGame,
+}
+
+/// Read before the rest of the file, so that a save from another version is caught before it fails to parse.
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SavedGame {
+    pub player: Option<SavedPlayer>,
+    /// Experience, level and talents, which outlast the player.
+    pub progression: Option<Progression>,
+    pub targets: Vec<SavedTargets>,
+    pub platforms: Vec<SavedPlatform>,
+    pub pickups: Vec<SavedPickup>,
+    pub checkpoints: SavedCheckpoints,
+    pub encounters: Vec<SavedEncounter>,
+    pub puzzles: Vec<SavedPuzzle>,
+    pub mission: Option<SavedMission>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedPlayer {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub hp: u32,
+    pub max_hp: u32,
+    pub mana: u32,
+    pub max_mana: u32,
+    /// The asset path of each spell in the player's spellbook, in slot order.
+    pub spells: Vec<String>,
+    pub selected_spell: usize,
+    /// The player's inventory, in slot order.
+    pub inventory: Vec<SavedItems>,
+    /// The asset path of each item that the player is wearing.
+    pub equipment: Vec<(EquipmentSlot, String)>,
+}
+
+/// A stack of items, by their asset path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedItems {
+    pub item: String,
+    pub count: u32,
+}
+
+/// An item that's still lying where it can be picked up.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedPickup {
+    pub position: Vec3,
+    pub items: SavedItems,
+}
+
+/// A group of targets, with only the ones still standing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedTargets {
+    pub position: Vec3,
+    pub targets: Targets,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedPlatform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub linvel: Vec3,
+    pub angvel: Vec3,
+    pub half_extents: Vec3,
+    pub path: Option<PlatformPath>,
+}
+
+/// The checkpoints the player has reached, by name, and the one they'll respawn at.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SavedCheckpoints {
+    pub reached: Vec<String>,
+    pub current: Option<String>,
+}
+
+/// How far an encounter has got, matched up by its `Name` when loading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedEncounter {
+    pub name: String,
+    pub state: EncounterState,
+}
+
+/// A leyline puzzle's progress, matched up by name when loading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedPuzzle {
+    pub name: String,
+    pub solved: bool,
+    pub stones: Vec<SavedLeystone>,
+}
+
+/// How a leystone has been turned and moved. Stones have no names, so they're matched up within their puzzle
+/// by the first of their positions, where the level stands them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedLeystone {
+    pub home: Vec3,
+    pub orientation: usize,
+    pub position: usize,
+}
+
+/// Progress through the level's mission, which is matched up by name when loading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedMission {
+    pub name: String,
+    pub progress: Vec<u32>,
+    pub elapsed: f32,
+}
+
+/// A save waiting for the player to spawn.
+#[derive(Resource)]
+struct PendingLoad(SavedGame);
+
+#[derive(Component)]
+struct SaveMessage(Timer);
+
+pub fn write_save<P: AsRef<Path>>(path: P, game: &SavedGame) -> Result<()> {
+    if let Some(directory) = path.as_ref().parent() {
+        std::fs::create_dir_all(directory)?;
+    }
+    let file = SaveFile {
+        version: SAVE_VERSION,
+        game: game.clone(),
+    };
+    ciborium::into_writer(&file, BufWriter::new(File::create(path)?))?;
+    Ok(())
+}
+
+pub fn read_save<P: AsRef<Path>>(path: P) -> Result<SavedGame> {
+    let header: SaveHeader = ciborium::from_reader(BufReader::new(File::open(&path)?))?;
+    if header.version != SAVE_VERSION {
+        bail!(
+            "save is version {}, but this build reads version {SAVE_VERSION}",
+            header.version
+        );
+    }
+    let file: SaveFile = ciborium::from_reader(BufReader::new(File::open(&path)?))?;
+    Ok(file.game)
+}
+
+impl SavedGame {
+    pub fn capture(world: &mut World) -> Self {
+        let player = world
+            .query_filtered::<(
+                &Transform,
+                &Hp,
+                &Mana,
+                Option<&Spellbook>,
+                Option<&Inventory>,
+                Option<&Equipment>,
+            ), With<Player>>()
+            .get_single(world)
+            .ok()
+            .map(
+                |(transform, hp, mana, spellbook, inventory, equipment)| SavedPlayer {
+                    translation: transform.translation,
+                    rotation: transform.rotation,
+                    hp: hp.current,
+                    max_hp: hp.max,
+                    mana: mana.current,
+                    max_mana: mana.max,
+                    spells: spellbook
+                        .map(|spellbook| {
+                            spellbook
+                                .slots
+                                .iter()
+                                .filter_map(|slot| slot.spell.path().map(|path| path.to_string()))
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                    selected_spell: spellbook.map(|spellbook| spellbook.selected).unwrap_or(0),
+                    inventory: inventory
+                        .map(|inventory| {
+                            inventory
+                                .stacks
+                                .iter()
+                                .filter_map(|stack| saved_items(&stack.item, stack.count))
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                    equipment: equipment
+                        .map(|equipment| {
+                            equipment
+                                .0
+                                .iter()
+                                .filter_map(|(slot, item)| {
+                                    item.path().map(|path| (*slot, path.to_string()))
+                                })
+                                .collect()
+                        })
+                        .unwrap_or_default(),
+                },
+            );
+
+        // Each group keeps only the targets that are still standing, where they've drifted to.
+        let mut targets = vec![];
+        let mut groups = world.query::<(&Targets, &Transform, Option<&Children>)>();
+        let mut standing = world.query_filtered::<&Transform, With<Target>>();
+        for (group, transform, children) in groups.iter(world) {
+            let positions: Vec<Vec3> = children
+                .into_iter()
+                .flatten()
+                .filter_map(|child| standing.get(world, *child).ok())
+                .map(|transform| transform.translation)
+                .collect();
+            if positions.is_empty() {
+                continue;
+            }
+            let mut group = group.clone();
+            group.positions = positions;
+            targets.push(SavedTargets {
+                position: transform.translation,
+                targets: group,
+            });
+        }
+
+        let platforms = world
+            .query::<(&Transform, &Platform, Option<&PlatformPath>)>()
+            .iter(world)
+            .map(|(transform, platform, path)| SavedPlatform {
+                translation: transform.translation,
+                rotation: transform.rotation,
+                linvel: platform.linvel,
+                angvel: platform.angvel,
+                half_extents: platform.half_extents,
+                path: path.cloned(),
+            })
+            .collect();
+
+        let pickups = world
+            .query::<(&Pickup, &Transform)>()
+            .iter(world)
+            .filter_map(|(pickup, transform)| {
+                Some(SavedPickup {
+                    position: transform.translation,
+                    items: saved_items(&pickup.item, pickup.count)?,
+                })
+            })
+            .collect();
+
+        let current = world
+            .get_resource::<Checkpoint>()
+            .and_then(|checkpoint| checkpoint.0);
+        let mut checkpoints = SavedCheckpoints::default();
+        for (entity, volume, reached) in world
+            .query::<(Entity, &CheckpointVolume, Has<CheckpointReached>)>()
+            .iter(world)
+        {
+            if reached {
+                checkpoints.reached.push(volume.name.clone());
+            }
+            if current == Some(entity) {
+                checkpoints.current = Some(volume.name.clone());
+            }
+        }
+
+        let encounters = world
+            .query::<(&Name, &Encounter)>()
+            .iter(world)
+            .map(|(name, encounter)| SavedEncounter {
+                name: name.to_string(),
+                state: encounter.state.clone(),
+            })
+            .collect();
+
+        let mut stones = world.query::<&Leystone>();
+        let puzzles = world
+            .query::<(Entity, &LeylinePuzzle)>()
+            .iter(world)
+            .map(|(entity, puzzle)| SavedPuzzle {
+                name: puzzle.name.clone(),
+                solved: puzzle.solved,
+                stones: stones
+                    .iter(world)
+                    .filter(|stone| stone.puzzle == Some(entity))
+                    .filter_map(|stone| {
+                        Some(SavedLeystone {
+                            home: *stone.positions.first()?,
+                            orientation: stone.orientation,
+                            position: stone.position,
+                        })
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let mission = world.get_resource::<Mission>().map(|mission| SavedMission {
+            name: mission.definition.name.clone(),
+            progress: mission.progress.clone(),
+            elapsed: mission.elapsed,
+        });
+
+        let progression = world.get_resource::<Progression>().cloned();
+
+        SavedGame {
+            player,
+            progression,
+            targets,
+            platforms,
+            pickups,
+            checkpoints,
+            encounters,
+            puzzles,
+            mission,
+        }
+    }
+
+    /// Puts the world back the way it was saved. Targets, platforms and pickups are replaced outright; the
+    /// player, who must already have spawned, is moved and has their stats and belongings restored.
+    pub fn restore(&self, world: &mut World) {
+        let assets = world.get_resource::<AssetServer>().cloned();
+        if let Some(progression) = &self.progression {
+            world.insert_resource(progression.clone());
+        }
+        if let Some(saved) = &self.player {
+            let mut players = world.query_filtered::<(
+                &mut Transform,
+                &mut Hp,
+                &mut Mana,
+                Option<&mut Spellbook>,
+                Option<&mut Inventory>,
+                Option<&mut Equipment>,
+            ), With<Player>>();
+            if let Ok((mut transform, mut hp, mut mana, spellbook, inventory, equipment)) =
+                players.get_single_mut(world)
+            {
+                transform.translation = saved.translation;
+                transform.rotation = saved.rotation;
+                *hp = Hp {
+                    current: saved.hp,
+                    max: saved.max_hp,
+                };
+                *mana = Mana {
+                    current: saved.mana,
+                    max: saved.max_mana,
+                };
+                if let (Some(mut spellbook), Some(assets)) = (spellbook, &assets) {
+                    let paths: Vec<&str> = saved.spells.iter().map(String::as_str).collect();
+                    *spellbook = Spellbook::from_files(assets, &paths);
+                    spellbook.selected = saved
+                        .selected_spell
+                        .min(spellbook.slots.len().saturating_sub(1));
+                }
+                if let (Some(mut inventory), Some(assets)) = (inventory, &assets) {
+                    inventory.stacks = saved
+                        .inventory
+                        .iter()
+                        .map(|saved| ItemStack {
+                            item: assets.load(&saved.item),
+                            count: saved.count,
+                        })
+                        .collect();
+                }
+                if let (Some(mut equipment), Some(assets)) = (equipment, &assets) {
+                    equipment.0 = saved
+                        .equipment
+                        .iter()
+                        .map(|(slot, path)| (*slot, assets.load(path)))
+                        .collect();
+                }
+            }
+        }
+
+        let stale: Vec<Entity> = world
+            .query_filtered::<Entity, Or<(With<Targets>, With<Platform>, With<Pickup>)>>()
+            .iter(world)
+            .collect();
+        for entity in stale {
+            world.entity_mut(entity).despawn_recursive();
+        }
+        for saved in &self.targets {
+            world.spawn((
+                saved.targets.clone(),
+                Transform::from_translation(saved.position),
+            ));
+        }
+        // Bare platforms, which `load_platforms` builds boxes for.
+        for saved in &self.platforms {
+            let mut platform = world.spawn((
+                Platform {
+                    linvel: saved.linvel,
+                    angvel: saved.angvel,
+                    half_extents: saved.half_extents,
+                    ..default()
+                },
+                Transform::from_translation(saved.translation).with_rotation(saved.rotation),
+            ));
+            if let Some(path) = &saved.path {
+                platform.insert(path.clone());
+            }
+        }
+        // Bare pickups too, for `load_pickups`.
+        if let Some(assets) = &assets {
+            for saved in &self.pickups {
+                world.spawn((
+                    Pickup {
+                        item: assets.load(&saved.items.item),
+                        count: saved.items.count,
+                    },
+                    Transform::from_translation(saved.position),
+                ));
+            }
+        }
+
+        // Checkpoints belong to the level, so they stay put and only their progress is restored.
+        let volumes: Vec<(Entity, String)> = world
+            .query::<(Entity, &CheckpointVolume)>()
+            .iter(world)
+            .map(|(entity, volume)| (entity, volume.name.clone()))
+            .collect();
+        let mut current = None;
+        for (entity, name) in volumes {
+            if self.checkpoints.reached.contains(&name) {
+                world.entity_mut(entity).insert(CheckpointReached);
+            } else {
+                world.entity_mut(entity).remove::<CheckpointReached>();
+            }
+            if self.checkpoints.current.as_ref() == Some(&name) {
+                current = Some(entity);
+            }
+        }
+        if let Some(mut checkpoint) = world.get_resource_mut::<Checkpoint>() {
+            checkpoint.0 = current;
+        }
+
+        // So do encounters and puzzles. Enemies aren't saved, so the restored encounters' ones go.
+        let mut restored = vec![];
+        for (entity, name, mut encounter) in world
+            .query::<(Entity, &Name, &mut Encounter)>()
+            .iter_mut(world)
+        {
+            if let Some(saved) = self
+                .encounters
+                .iter()
+                .find(|saved| saved.name == name.as_str())
+            {
+                encounter.resume(saved.state.clone());
+                restored.push(entity);
+            }
+        }
+        let members: Vec<Entity> = world
+            .query::<(Entity, &EncounterMember)>()
+            .iter(world)
+            .filter(|(_, member)| restored.contains(&member.encounter))
+            .map(|(entity, _)| entity)
+            .collect();
+        for member in members {
+            world.entity_mut(member).despawn_recursive();
+        }
+        let mut puzzles = vec![];
+        for (entity, mut puzzle) in world
+            .query::<(Entity, &mut LeylinePuzzle)>()
+            .iter_mut(world)
+        {
+            if let Some(saved) = self.puzzles.iter().find(|saved| saved.name == puzzle.name) {
+                puzzle.solved = saved.solved;
+                puzzles.push((entity, saved));
+            }
+        }
+        for mut stone in world.query::<&mut Leystone>().iter_mut(world) {
+            let Some(saved) = puzzles
+                .iter()
+                .filter(|(puzzle, _)| stone.puzzle == Some(*puzzle))
+                .flat_map(|(_, saved)| &saved.stones)
+                .find(|saved| stone.positions.first() == Some(&saved.home))
+            else {
+                continue;
+            };
+            stone.orientation = saved
+                .orientation
+                .min(stone.orientations.len().saturating_sub(1));
+            stone.position = saved.position.min(stone.positions.len().saturating_sub(1));
+        }
+
+        let (Some(saved), Some(mut mission)) = (&self.mission, world.get_resource_mut::<Mission>())
+        else {
+            return;
+        };
+        if saved.name != mission.definition.name || saved.progress.len() != mission.progress.len() {
+            warn!("The saved mission {:?} isn't this level's.", saved.name);
+            return;
+        }
+        mission.progress = saved.progress.clone();
+        mission.elapsed = saved.elapsed;
+        // Straight back into it, without the briefing.
+        if let Some(mut state) = world.get_resource_mut::<NextState<MissionState>>() {
+            state.set(MissionState::Active);
+        }
+    }
+}
+
+/// Items are saved by their asset path, so ones that weren't loaded from a file can't be.
+fn saved_items(item: &Handle<ItemDefinition>, count: u32) -> Option<SavedItems> {
+    Some(SavedItems {
+        item: item.path()?.to_string(),
+        count,
+    })
+}
+
+fn save_input(
+    keyboard: Res<ButtonInput<KeyCode>>,
+    mut slots: ResMut<SaveSlots>,
+    mut saves: EventWriter<SaveGame>,
+    mut loads: EventWriter<LoadGame>,
+    mut message: Query<(&mut Text, &mut SaveMessage)>,
+) {
+    if keyboard.just_pressed(KeyCode::F5) {
+        saves.send(SaveGame {
+            slot: QUICKSAVE_SLOT,
+        });
+    }
+    if keyboard.just_pressed(KeyCode::F9) {
+        loads.send(LoadGame {
+            slot: QUICKSAVE_SLOT,
+        });
+    }
+    if keyboard.just_pressed(KeyCode::F6) {
+        slots.selected = slots.selected % SAVE_SLOTS + 1;
+        show_message(&mut message, &format!("Slot {} selected", slots.selected));
+    }
+    if keyboard.just_pressed(KeyCode::F7) {
+        saves.send(SaveGame {
+            slot: slots.selected,
+        });
+    }
+    if keyboard.just_pressed(KeyCode::F8) {
+        loads.send(LoadGame {
+            slot: slots.selected,
+        });
+    }
+}
+
+fn save_and_load(world: &mut World) {
+    let saves: Vec<SaveGame> = world.resource_mut::<Events<SaveGame>>().drain().collect();
+    let loads: Vec<LoadGame> = world.resource_mut::<Events<LoadGame>>().drain().collect();
+    let slots = world.resource::<SaveSlots>().clone();
+    let in_game = world
+        .get_resource::<State<GameState>>()
+        .is_some_and(|state| *state.get() == GameState::InGame);
+
+    for SaveGame { slot } in saves {
+        if !in_game {
+            set_message(world, "Nothing to save until you've spawned");
+            continue;
+        }
+        let path = slots.path(slot);
+        let game = SavedGame::capture(world);
+        match write_save(&path, &game) {
+            Ok(()) => {
+                info!("Saved to {path:?}");
+                set_message(world, &format!("Saved to {}", slot_name(slot)));
+            }
+            Err(e) => {
+                warn!("Couldn't save to {path:?}: {e}");
+                set_message(world, &format!("Couldn't save: {e}"));
+            }
+        }
+    }
+
+    // Only the last load matters.
+    if let Some(LoadGame { slot }) = loads.last().copied() {
+        let path = slots.path(slot);
+        match read_save(&path) {
+            Ok(game) => {
+                info!("Loading {path:?}");
+                set_message(world, &format!("Loaded {}", slot_name(slot)));
+                world.insert_resource(PendingLoad(game));
+                if !in_game {
+                    world
+                        .resource_mut::<NextState<GameState>>()
+                        .set(GameState::Spawning);
+                }
+            }
+            Err(e) => {
+                warn!("Couldn't load {path:?}: {e}");
+                set_message(world, &format!("Couldn't load {}: {e}", slot_name(slot)));
+            }
+        }
+    }
+}
+
+fn apply_pending_load(world: &mut World) {
+    if world
+        .query_filtered::<(), With<Player>>()
+        .iter(world)
+        .next()
+        .is_none()
+    {
+        return;
+    }
+    if let Some(PendingLoad(game)) = world.remove_resource::<PendingLoad>() {
+        game.restore(world);
+    }
+}
+
+fn slot_name(slot: usize) -> String {
+    if slot == QUICKSAVE_SLOT {
+        "quicksave".into()
+    } else {
+        format!("slot {slot}")
+    }
+}
+
+fn spawn_save_message(mut commands: Commands) {
+    commands.spawn((
+        Name::new("save_message"),
+        SaveMessage(Timer::from_seconds(SAVE_MESSAGE_SECONDS, TimerMode::Once)),
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font_size: 24.0,
+                ..default()
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            bottom: Val::Px(20.0),
+            right: Val::Px(20.0),
+            ..default()
+        }),
+    ));
+}
+
+fn show_message<'a>(
+    messages: impl IntoIterator<Item = (Mut<'a, Text>, Mut<'a, SaveMessage>)>,
+    text: &str,
+) {
+    for (mut message_text, mut message) in messages {
+        message_text.sections[0].value = text.into();
+        message.0.reset();
+    }
+}
+
+fn set_message(world: &mut World, text: &str) {
+    show_message(
+        world
+            .query::<(&mut Text, &mut SaveMessage)>()
+            .iter_mut(world),
+        text,
+    );
+}
+
+fn fade_save_message(time: Res<Time>, mut message: Query<(&mut Text, &mut SaveMessage)>) {
+    for (mut text, mut message) in &mut message {
+        if message.0.tick(time.delta()).just_finished() {
+            text.sections[0].value.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        encounters::{EncounterDefinition, WaveDefinition},
+        progression::Talent,
+    };
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("save_test_{}", std::process::id()))
+            .join(name)
+    }
+
+    fn world_with_player() -> (World, Entity) {
+        let mut world = World::new();
+        let player = world
+            .spawn((
+                Player,
+                Transform::from_xyz(1.0, 2.0, 3.0),
+                Hp {
+                    current: 40,
+                    max: 100,
+                },
+                Mana {
+                    current: 25,
+                    max: 100,
+                },
+            ))
+            .id();
+        (world, player)
+    }
+
+    #[test]
+    fn saves_round_trip_through_disk() {
+        let (mut world, _) = world_with_player();
+        world.spawn((
+            Platform {
+                linvel: Vec3::X,
+                half_extents: Vec3::splat(2.0),
+                ..default()
+            },
+            Transform::from_xyz(5.0, 6.0, 7.0),
+        ));
+        let mut progression = Progression {
+            level: 4,
+            talent_points: 1,
+            ..default()
+        };
+        progression.talents.insert(Talent::Focus, 2);
+        world.insert_resource(progression.clone());
+        let game = SavedGame::capture(&mut world);
+        let path = temp_path("round_trip.sav");
+        write_save(&path, &game).unwrap();
+        let loaded = read_save(&path).unwrap();
+
+        let player = loaded.player.unwrap();
+        assert_eq!(player.translation, Vec3::new(1.0, 2.0, 3.0));
+        assert_eq!((player.hp, player.mana), (40, 25));
+        assert_eq!(loaded.platforms.len(), 1);
+        assert_eq!(loaded.platforms[0].translation, Vec3::new(5.0, 6.0, 7.0));
+        assert_eq!(loaded.platforms[0].linvel, Vec3::X);
+        assert_eq!(loaded.progression, Some(progression));
+    }
+
+    #[test]
+    fn checkpoint_progress_is_matched_up_by_name() {
+        let (mut world, _) = world_with_player();
+        world.init_resource::<Checkpoint>();
+        let volume = |name: &str| CheckpointVolume {
+            name: name.into(),
+            ..default()
+        };
+        world.spawn((volume("start"), CheckpointReached));
+        let bridge = world.spawn((volume("bridge"), CheckpointReached)).id();
+        world.spawn(volume("summit"));
+        world.resource_mut::<Checkpoint>().0 = Some(bridge);
+        let game = SavedGame::capture(&mut world);
+        assert_eq!(game.checkpoints.current.as_deref(), Some("bridge"));
+
+        // A fresh run of the level, with new entities and nothing reached.
+        let mut world = World::new();
+        world.init_resource::<Checkpoint>();
+        let start_again = world.spawn(volume("start")).id();
+        let bridge_again = world.spawn(volume("bridge")).id();
+        let summit_again = world.spawn(volume("summit")).id();
+        game.restore(&mut world);
+        assert_eq!(world.resource::<Checkpoint>().0, Some(bridge_again));
+        assert!(world.get::<CheckpointReached>(start_again).is_some());
+        assert!(world.get::<CheckpointReached>(summit_again).is_none());
+    }
+
+    #[test]
+    fn encounters_and_puzzles_are_matched_up_by_name() {
+        let definition = EncounterDefinition {
+            position: Vec3::ZERO,
+            trigger_radius: 10.0,
+            spawn_points: vec![],
+            waves: vec![
+                WaveDefinition {
+                    delay: 3.0,
+                    groups: vec![],
+                    time_limit: None,
+                };
+                2
+            ],
+            escalation: None,
+        };
+        let leystone = |puzzle| Leystone {
+            orientations: vec![0.0, 90.0, 180.0],
+            positions: vec![Vec3::X, Vec3::Z],
+            puzzle: Some(puzzle),
+            ..default()
+        };
+        let (mut world, _) = world_with_player();
+        let mut fighting = Encounter::new(definition.clone());
+        fighting.state = EncounterState::InWave {
+            wave: 1,
+            elapsed: 5.0,
+        };
+        world.spawn((Name::new("encounter 0"), fighting));
+        let mut cleared = Encounter::new(definition.clone());
+        cleared.state = EncounterState::Cleared;
+        world.spawn((Name::new("encounter 1"), cleared));
+        let puzzle = world
+            .spawn(LeylinePuzzle {
+                name: "gate".into(),
+                solved: true,
+            })
+            .id();
+        world.spawn(Leystone {
+            orientation: 2,
+            position: 1,
+            ..leystone(puzzle)
+        });
+        let game = SavedGame::capture(&mut world);
+        let path = temp_path("encounters.sav");
+        write_save(&path, &game).unwrap();
+        let game = read_save(&path).unwrap();
+
+        // A fresh run of the level, spawned in another order, with an enemy from a wave that's started since.
+        let mut world = World::new();
+        let second = world
+            .spawn((Name::new("encounter 1"), Encounter::new(definition.clone())))
+            .id();
+        let first = world
+            .spawn((Name::new("encounter 0"), Encounter::new(definition)))
+            .id();
+        let enemy = world.spawn(EncounterMember { encounter: first }).id();
+        let puzzle = world
+            .spawn(LeylinePuzzle {
+                name: "gate".into(),
+                solved: false,
+            })
+            .id();
+        let stone = world.spawn(leystone(puzzle)).id();
+        game.restore(&mut world);
+
+        // The wave that was under way starts over.
+        assert_eq!(
+            world.get::<Encounter>(first).unwrap().state,
+            EncounterState::Countdown {
+                wave: 1,
+                remaining: 3.0
+            }
+        );
+        assert_eq!(
+            world.get::<Encounter>(second).unwrap().state,
+            EncounterState::Cleared
+        );
+        assert!(world.get_entity(enemy).is_none());
+        assert!(world.get::<LeylinePuzzle>(puzzle).unwrap().solved);
+        let stone = world.get::<Leystone>(stone).unwrap();
+        assert_eq!((stone.orientation, stone.position), (2, 1));
+    }
+
+    #[test]
+    fn refuses_other_versions() {
+        let path = temp_path("old_version.sav");
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = SaveFile {
+            version: SAVE_VERSION + 1,
+            game: SavedGame::default(),
+        };
+        ciborium::into_writer(&file, File::create(&path).unwrap()).unwrap();
+        assert!(read_save(&path).is_err());
+    }
+
+    #[test]
+    fn restoring_moves_the_player_and_replaces_platforms() {
+        let (mut world, player) = world_with_player();
+        world.spawn((Platform::default(), Transform::default()));
+        let mut game = SavedGame::capture(&mut world);
+        let saved = game.player.as_mut().unwrap();
+        saved.translation = Vec3::new(10.0, 20.0, 30.0);
+        saved.hp = 90;
+        game.platforms[0].translation = Vec3::new(-4.0, 0.0, 0.0);
+
+        // Things move on after the save, and a second platform turns up.
+        world.spawn((Platform::default(), Transform::default()));
+        game.restore(&mut world);
+
+        let transform = world.get::<Transform>(player).unwrap();
+        assert_eq!(transform.translation, Vec3::new(10.0, 20.0, 30.0));
+        assert_eq!(world.get::<Hp>(player).unwrap().current, 90);
+        let platforms: Vec<Vec3> = world
+            .query_filtered::<&Transform, With<Platform>>()
+            .iter(&world)
+            .map(|transform| transform.translation)
+            .collect();
+        assert_eq!(platforms, vec![Vec3::new(-4.0, 0.0, 0.0)]);
+    }
+}
@@ -0,0 +1,517 @@
+use crate::{
+    hitpoints::{DamageEvent, DamageSet, DamageType},
+    mana::Mana,
+    player::{MovementProfile, MovementSet},
+    prelude::*,
+};
+use bevy::{prelude::*, utils::HashMap};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Timed effects on an entity: burning, slowed, hasted, shielded or surging with mana. Anything can apply one
+/// by sending `ApplyStatus`; damage applies the `status` that it carries if it lands, and buff spells and
+/// potions apply theirs to whoever uses them. The player's show along the bottom of the screen.
+pub struct StatusPlugin;
+
+impl Plugin for StatusPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ApplyStatus>();
+        app.add_systems(OnEnter(GameState::InGame), spawn_status_hud);
+        app.add_systems(
+            Update,
+            (
+                tick_statuses.before(DamageSet),
+                apply_statuses.after(DamageSet),
+                update_status_hud.after(apply_statuses),
+            ),
+        );
+        app.add_systems(
+            FixedUpdate,
+            apply_movement_statuses.before(MovementSet::Integrate),
+        );
+    }
+}
+
+/// How often burning hurts and mana surges.
+const PULSE_SECONDS: f32 = 1.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum StatusKind {
+    /// Fire damage each second, per stack.
+    Burn,
+    /// Takes `magnitude` off the fraction of normal speed that the entity moves at.
+    Slow,
+    /// Adds `magnitude` to the fraction of normal speed that the entity moves at.
+    Haste,
+    /// Soaks up `magnitude` damage before any reaches `Hp`.
+    Shield,
+    /// Mana each second.
+    ManaSurge,
+}
+
+/// What happens when a status is applied to an entity that already has it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stacking {
+    /// The longer duration and the stronger magnitude win.
+    Refresh,
+    /// Another stack, up to `max_stacks`, and the duration starts over.
+    Intensify { max_stacks: u32 },
+    /// The magnitudes add up, and the duration starts over.
+    Pool,
+}
+
+impl StatusKind {
+    pub const ALL: [StatusKind; 5] = [
+        StatusKind::Burn,
+        StatusKind::Slow,
+        StatusKind::Haste,
+        StatusKind::Shield,
+        StatusKind::ManaSurge,
+    ];
+
+    pub fn stacking(self) -> Stacking {
+        match self {
+            StatusKind::Burn => Stacking::Intensify { max_stacks: 3 },
+            StatusKind::Slow | StatusKind::Haste | StatusKind::ManaSurge => Stacking::Refresh,
+            StatusKind::Shield => Stacking::Pool,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            StatusKind::Burn => "Burn",
+            StatusKind::Slow => "Slow",
+            StatusKind::Haste => "Haste",
+            StatusKind::Shield => "Shield",
+            StatusKind::ManaSurge => "Surge",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            StatusKind::Burn => Palette::Red.to_color(),
+            StatusKind::Slow => Color::srgb(0.4, 0.6, 0.7),
+            StatusKind::Haste => Palette::Yellow.to_color(),
+            StatusKind::Shield => Color::srgb(0.7, 0.7, 0.8),
+            StatusKind::ManaSurge => Palette::Blue.to_color(),
+        }
+    }
+}
+
+/// A status to apply, as written in spell and item files.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    /// Seconds that it lasts.
+    pub duration: f32,
+    /// How strong it is; what that means depends on the `kind`.
+    pub magnitude: f32,
+}
+
+#[derive(Event, Debug, Clone)]
+pub struct ApplyStatus {
+    pub target: Entity,
+    pub source: Option<Entity>,
+    pub effect: StatusEffect,
+}
+
+#[derive(Debug, Clone)]
+pub struct ActiveStatus {
+    pub kind: StatusKind,
+    pub magnitude: f32,
+    pub stacks: u32,
+    pub remaining: f32,
+    /// Whoever applied it last, who's to blame for a burn.
+    pub source: Option<Entity>,
+    pulse: Timer,
+}
+
+/// Something that happens because of a status, once every `PULSE_SECONDS`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatusPulse {
+    pub kind: StatusKind,
+    pub amount: f32,
+    pub source: Option<Entity>,
+}
+
+/// The statuses on an entity, at most one of each kind. Added by `apply_statuses` when the first one lands.
+#[derive(Component, Debug, Clone, Default)]
+pub struct StatusEffects(pub Vec<ActiveStatus>);
+
+impl StatusEffects {
+    pub fn get(&self, kind: StatusKind) -> Option<&ActiveStatus> {
+        self.0.iter().find(|status| status.kind == kind)
+    }
+
+    pub fn apply(&mut self, effect: &StatusEffect, source: Option<Entity>) {
+        let Some(status) = self.0.iter_mut().find(|status| status.kind == effect.kind) else {
+            self.0.push(ActiveStatus {
+                kind: effect.kind,
+                magnitude: effect.magnitude,
+                stacks: 1,
+                remaining: effect.duration,
+                source,
+                pulse: Timer::from_seconds(PULSE_SECONDS, TimerMode::Repeating),
+            });
+            return;
+        };
+        status.source = source.or(status.source);
+        match effect.kind.stacking() {
+            Stacking::Refresh => {
+                status.magnitude = status.magnitude.max(effect.magnitude);
+                status.remaining = status.remaining.max(effect.duration);
+            }
+            Stacking::Intensify { max_stacks } => {
+                status.magnitude = status.magnitude.max(effect.magnitude);
+                status.stacks = (status.stacks + 1).min(max_stacks);
+                status.remaining = effect.duration;
+            }
+            Stacking::Pool => {
+                status.magnitude += effect.magnitude;
+                status.remaining = effect.duration;
+            }
+        }
+    }
+
+    /// Lets any shield soak up `amount` of damage, and returns what gets through.
+    pub fn absorb(&mut self, amount: f32) -> f32 {
+        let Some(index) = self
+            .0
+            .iter()
+            .position(|status| status.kind == StatusKind::Shield)
+        else {
+            return amount;
+        };
+        let shield = &mut self.0[index];
+        let absorbed = amount.min(shield.magnitude);
+        shield.magnitude -= absorbed;
+        if shield.magnitude <= 0.0 {
+            self.0.remove(index);
+        }
+        amount - absorbed
+    }
+
+    /// What haste and slow make of the entity's normal speed.
+    pub fn speed_multiplier(&self) -> f32 {
+        let magnitude = |kind| self.get(kind).map_or(0.0, |status| status.magnitude);
+        ((1.0 + magnitude(StatusKind::Haste)) * (1.0 - magnitude(StatusKind::Slow))).max(0.0)
+    }
+
+    /// Runs the statuses down by `delta`, dropping the ones that run out, and returns whatever pulsed.
+    pub fn tick(&mut self, delta: Duration) -> Vec<StatusPulse> {
+        let mut pulses = vec![];
+        for status in &mut self.0 {
+            status.remaining -= delta.as_secs_f32();
+            status.pulse.tick(delta);
+            for _ in 0..status.pulse.times_finished_this_tick() {
+                pulses.push(StatusPulse {
+                    kind: status.kind,
+                    amount: status.magnitude * status.stacks as f32,
+                    source: status.source,
+                });
+            }
+        }
+        self.0.retain(|status| status.remaining > 0.0);
+        pulses
+    }
+}
+
+/// The walk speed that an entity's `MovementProfile` has without haste or slow, kept while either is on it.
+#[derive(Component, Debug, Clone, Copy)]
+struct UnmodifiedWalkSpeed(f32);
+
+#[derive(Component)]
+struct StatusIcon(StatusKind);
+
+#[derive(Component)]
+struct StatusIconText(StatusKind);
+
+fn apply_statuses(
+    mut commands: Commands,
+    mut events: EventReader<ApplyStatus>,
+    mut affected: Query<&mut StatusEffects>,
+) {
+    // Entities getting their first statuses this frame, which can't be looked up until the commands run.
+    let mut fresh: HashMap<Entity, StatusEffects> = HashMap::default();
+    for event in events.read() {
+        match affected.get_mut(event.target) {
+            Ok(mut statuses) => statuses.apply(&event.effect, event.source),
+            Err(_) => fresh
+                .entry(event.target)
+                .or_default()
+                .apply(&event.effect, event.source),
+        }
+    }
+    for (entity, statuses) in fresh {
+        if let Some(mut entity) = commands.get_entity(entity) {
+            entity.try_insert(statuses);
+        }
+    }
+}
+
+fn tick_statuses(
+    time: Res<Time>,
+    mut affected: Query<(
+        Entity,
+        &mut StatusEffects,
+        Option<&GlobalTransform>,
+        Option<&mut Mana>,
+    )>,
+    mut damage: EventWriter<DamageEvent>,
+) {
+    for (entity, mut statuses, transform, mut mana) in &mut affected {
+        for pulse in statuses.tick(time.delta()) {
+            match pulse.kind {
+                StatusKind::Burn => {
+                    damage.send(DamageEvent {
+                        source: pulse.source,
+                        target: entity,
+                        amount: pulse.amount,
+                        damage_type: DamageType::Fire,
+                        hit_point: transform
+                            .map(|transform| transform.translation())
+                            .unwrap_or_default(),
+                        status: None,
+                    });
+                }
+                StatusKind::ManaSurge => {
+                    if let Some(mana) = mana.as_mut() {
+                        mana.current = (mana.current + pulse.amount.round() as u32).min(mana.max);
+                    }
+                }
+                StatusKind::Slow | StatusKind::Haste | StatusKind::Shield => {}
+            }
+        }
+    }
+}
+
+/// Scales the walk speed, and with it sprinting, in the `MovementProfile` of anyone hasted or slowed, and
+/// puts it back once they aren't.
+fn apply_movement_statuses(
+    mut commands: Commands,
+    mut moving: Query<(
+        Entity,
+        &StatusEffects,
+        &mut MovementProfile,
+        Option<&UnmodifiedWalkSpeed>,
+    )>,
+) {
+    for (entity, statuses, mut profile, unmodified) in &mut moving {
+        let multiplier = statuses.speed_multiplier();
+        match unmodified {
+            Some(&UnmodifiedWalkSpeed(walk_speed)) if multiplier == 1.0 => {
+                profile.walk_speed = walk_speed;
+                commands.entity(entity).remove::<UnmodifiedWalkSpeed>();
+            }
+            Some(&UnmodifiedWalkSpeed(walk_speed)) => profile.walk_speed = walk_speed * multiplier,
+            None if multiplier != 1.0 => {
+                commands
+                    .entity(entity)
+                    .insert(UnmodifiedWalkSpeed(profile.walk_speed));
+                profile.walk_speed *= multiplier;
+            }
+            None => {}
+        }
+    }
+}
+
+fn spawn_status_hud(mut commands: Commands) {
+    commands
+        .spawn((
+            Name::new("status_hud"),
+            StateScoped(GameState::InGame),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    bottom: Val::Px(20.0),
+                    width: Val::Percent(100.0),
+                    justify_content: JustifyContent::Center,
+                    column_gap: Val::Px(8.0),
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            for kind in StatusKind::ALL {
+                parent
+                    .spawn((
+                        StatusIcon(kind),
+                        NodeBundle {
+                            style: Style {
+                                width: Val::Px(64.0),
+                                height: Val::Px(48.0),
+                                justify_content: JustifyContent::Center,
+                                align_items: AlignItems::Center,
+                                display: Display::None,
+                                ..default()
+                            },
+                            background_color: BackgroundColor(kind.color().with_alpha(0.6)),
+                            ..default()
+                        },
+                    ))
+                    .with_children(|icon| {
+                        icon.spawn((
+                            StatusIconText(kind),
+                            TextBundle::from_section(
+                                "",
+                                TextStyle {
+                                    font_size: 16.0,
+                                    ..default()
+                                },
+                            )
+                            .with_text_justify(JustifyText::Center),
+                        ));
+                    });
+            }
+        });
+}
+
+fn update_status_hud(
+    player: Query<&StatusEffects, With<Player>>,
+    mut icons: Query<(&StatusIcon, &mut Style)>,
+    mut texts: Query<(&StatusIconText, &mut Text)>,
+) {
+    let statuses = player.get_single().ok();
+    let active = |kind| statuses.and_then(|statuses| statuses.get(kind));
+    for (icon, mut style) in &mut icons {
+        let display = if active(icon.0).is_some() {
+            Display::Flex
+        } else {
+            Display::None
+        };
+        if style.display != display {
+            style.display = display;
+        }
+    }
+    for (text, mut value) in &mut texts {
+        let Some(status) = active(text.0) else {
+            continue;
+        };
+        let mut label = text.0.label().to_string();
+        if status.stacks > 1 {
+            label += &format!(" x{}", status.stacks);
+        }
+        if text.0 == StatusKind::Shield {
+            label += &format!(" {:.0}", status.magnitude);
+        }
+        value.sections[0].value = format!("{label}\n{:.1}s", status.remaining.max(0.0));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::hitpoints::{apply_damage, DeathEvent, Hp};
+    use bevy::ecs::system::RunSystemOnce;
+
+    fn effect(kind: StatusKind, duration: f32, magnitude: f32) -> StatusEffect {
+        StatusEffect {
+            kind,
+            duration,
+            magnitude,
+        }
+    }
+
+    #[test]
+    fn statuses_stack_by_their_kind() {
+        let mut statuses = StatusEffects::default();
+        for _ in 0..5 {
+            statuses.apply(&effect(StatusKind::Burn, 4.0, 2.0), None);
+        }
+        statuses.apply(&effect(StatusKind::Slow, 5.0, 0.5), None);
+        statuses.apply(&effect(StatusKind::Slow, 2.0, 0.2), None);
+        statuses.apply(&effect(StatusKind::Shield, 10.0, 20.0), None);
+        statuses.apply(&effect(StatusKind::Shield, 10.0, 15.0), None);
+
+        assert_eq!(statuses.0.len(), 3);
+        assert_eq!(statuses.get(StatusKind::Burn).unwrap().stacks, 3);
+        let slow = statuses.get(StatusKind::Slow).unwrap();
+        assert_eq!((slow.magnitude, slow.remaining), (0.5, 5.0));
+        assert_eq!(statuses.get(StatusKind::Shield).unwrap().magnitude, 35.0);
+    }
+
+    #[test]
+    fn statuses_pulse_each_second_and_run_out() {
+        let mut statuses = StatusEffects::default();
+        let source = Some(Entity::from_raw(7));
+        statuses.apply(&effect(StatusKind::Burn, 2.5, 3.0), source);
+        statuses.apply(&effect(StatusKind::Burn, 2.5, 3.0), source);
+        statuses.apply(&effect(StatusKind::Haste, 1.5, 0.5), None);
+        statuses.apply(&effect(StatusKind::Slow, 2.5, 0.25), None);
+        assert_eq!(statuses.speed_multiplier(), 1.5 * 0.75);
+
+        let pulses = statuses.tick(Duration::from_secs(1));
+        let burn = StatusPulse {
+            kind: StatusKind::Burn,
+            amount: 6.0,
+            source,
+        };
+        assert!(pulses.contains(&burn));
+        statuses.tick(Duration::from_secs(1));
+        assert!(statuses.get(StatusKind::Haste).is_none());
+        statuses.tick(Duration::from_secs(1));
+        assert!(statuses.0.is_empty());
+    }
+
+    #[test]
+    fn haste_speeds_up_the_movement_profile_until_it_runs_out() {
+        let mut world = World::new();
+        let mut statuses = StatusEffects::default();
+        statuses.apply(&effect(StatusKind::Haste, 1.0, 0.5), None);
+        let walk_speed = MovementProfile::default().walk_speed;
+        let hasted = world.spawn((statuses, MovementProfile::default())).id();
+        let walk_speed_of =
+            |world: &World| world.get::<MovementProfile>(hasted).unwrap().walk_speed;
+
+        // It's scaled once, not again every tick.
+        world.run_system_once(apply_movement_statuses);
+        world.run_system_once(apply_movement_statuses);
+        assert_eq!(walk_speed_of(&world), walk_speed * 1.5);
+
+        world
+            .get_mut::<StatusEffects>(hasted)
+            .unwrap()
+            .tick(Duration::from_secs(2));
+        world.run_system_once(apply_movement_statuses);
+        assert_eq!(walk_speed_of(&world), walk_speed);
+        assert!(world.get::<UnmodifiedWalkSpeed>(hasted).is_none());
+    }
+
+    #[test]
+    fn shields_soak_up_damage_and_damage_carries_statuses() {
+        let mut world = World::new();
+        world.init_resource::<Events<DamageEvent>>();
+        world.init_resource::<Events<DeathEvent>>();
+        world.init_resource::<Events<ApplyStatus>>();
+        let mut statuses = StatusEffects::default();
+        statuses.apply(&effect(StatusKind::Shield, 10.0, 15.0), None);
+        let target = world
+            .spawn((
+                Hp {
+                    current: 100,
+                    max: 100,
+                },
+                statuses,
+            ))
+            .id();
+        world.send_event(DamageEvent {
+            source: None,
+            target,
+            amount: 40.0,
+            damage_type: DamageType::Fire,
+            hit_point: Vec3::ZERO,
+            status: Some(effect(StatusKind::Burn, 3.0, 2.0)),
+        });
+        world.run_system_once(apply_damage);
+        world.run_system_once(apply_statuses);
+
+        assert_eq!(world.get::<Hp>(target).unwrap().current, 75);
+        let statuses = world.get::<StatusEffects>(target).unwrap();
+        assert!(statuses.get(StatusKind::Shield).is_none());
+        assert!(statuses.get(StatusKind::Burn).is_some());
+    }
+}
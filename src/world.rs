@@ -1,28 +1,169 @@
 use bevy::{pbr::wireframe::WireframeConfig, prelude::*};
 
-use crate::{bevy_rtin, bevy_rtin::MeshOptions, prelude::*};
+use crate::{
+    bevy_rtin,
+    bevy_rtin::MeshOptions,
+    hitpoints::{DamageEvent, DamageSet, DamageType},
+    player::PlayerMotion,
+    prelude::*,
+    respawn::LastDeath,
+    rtin::MeshData,
+};
 use bevy_rapier3d::{math::Vect, prelude::*};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 pub struct WorldPlugin {
-    pub(crate) terrain_path: PathBuf,
+    pub terrain_path: PathBuf,
 }
 
-#[derive(Component, Debug)]
-pub struct Leash(pub f32);
+/// What happens to something once it leaves the `WorldBounds`. The player gets `PLAYER_GRACE` seconds of
+/// warning first, in case they make it back.
+#[derive(Component, Debug, Clone, Copy, PartialEq)]
+pub enum OutOfBounds {
+    Despawn,
+    /// Put back where it last was in bounds.
+    Teleport,
+    /// Hurt until it comes back or dies.
+    Damage {
+        per_second: f32,
+    },
+    /// Left alone, apart from a `KillPlane` event for whoever wants to deal with it.
+    KillPlane,
+}
+
+/// Sent once each time something with `OutOfBounds::KillPlane` leaves the bounds.
+#[derive(Event, Debug, Clone, Copy)]
+pub struct KillPlane {
+    pub entity: Entity,
+    pub position: Vec3,
+}
+
+/// How long something with `OutOfBounds` has been out of bounds, and where it last was in bounds.
+#[derive(Component, Debug, Default)]
+pub struct BoundsTracker {
+    pub outside_for: f32,
+    pub last_safe: Option<Vec3>,
+    // Hp only takes whole points, so damage builds up here until there's at least one.
+    damage_owed: f32,
+}
+
+/// The box that everything with `OutOfBounds` has to stay in: the terrain's, with some room around it and
+/// plenty overhead for launch pads and jetpacks.
+#[derive(Resource, Debug, Clone, Copy, PartialEq)]
+pub struct WorldBounds {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl WorldBounds {
+    pub fn around(min: Vec3, max: Vec3) -> Self {
+        WorldBounds {
+            min: min - BOUNDS_MARGIN,
+            max: max + BOUNDS_MARGIN + Vec3::Y * BOUNDS_HEADROOM,
+        }
+    }
+
+    /// How far `point` is inside the bounds, from the nearest face. Negative outside.
+    pub fn depth(&self, point: Vec3) -> f32 {
+        (point - self.min).min(self.max - point).min_element()
+    }
+
+    pub fn contains(&self, point: Vec3) -> bool {
+        self.depth(point) >= 0.0
+    }
+}
+
+const BOUNDS_MARGIN: Vec3 = Vec3::new(50.0, 100.0, 50.0);
+const BOUNDS_HEADROOM: f32 = 1000.0;
+/// Seconds that the player can spend out of bounds before it catches up with them.
+pub const PLAYER_GRACE: f32 = 3.0;
+// How close to the edge of the bounds the player gets warned.
+const WARNING_DEPTH: f32 = 20.0;
+// Only places at least this far inside the bounds count as safe to teleport back to.
+const SAFE_DEPTH: f32 = 5.0;
+
+#[derive(Component)]
+struct BoundsWarning;
+
+/// Marks the terrain's collider, for raycasts that are only after the ground.
+#[derive(Component, Debug, Default)]
+pub struct TerrainCollider;
+
+/// Where the terrain's heightmap comes from and how it's scaled into the world. The heightmap's pixels are
+/// one unit apart before scaling, and its brightness is the height from 0 to 1. A level can replace this.
+#[derive(Resource, Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(default))]
+pub struct TerrainSettings {
+    pub heightmap: PathBuf,
+    pub scale: Vec3,
+}
+
+impl Default for TerrainSettings {
+    fn default() -> Self {
+        TerrainSettings {
+            heightmap: "assets/grand_canyon_small_heightmap.png".into(),
+            scale: Vec3::new(3., 300., 3.),
+        }
+    }
+}
+
+/// The terrain's triangles, in heightmap space, and the transform that takes them into the world. Replacing
+/// this resource is how anything that depends on the shape of the terrain finds out that it changed.
+#[derive(Resource, Debug, Clone)]
+pub struct Terrain {
+    pub mesh_data: MeshData,
+    pub transform: Transform,
+}
+
+impl Terrain {
+    /// The corners of the box around the terrain, in world space.
+    pub fn aabb(&self) -> (Vec3, Vec3) {
+        let affine = self.transform.compute_affine();
+        self.mesh_data.vertices.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), v| {
+                let point = affine.transform_point3(Vec3::new(v.x, v.z, v.y));
+                (min.min(point), max.max(point))
+            },
+        )
+    }
+
+    /// Each of the terrain's triangles, in world space.
+    pub fn triangles(&self) -> impl Iterator<Item = [Vec3; 3]> + '_ {
+        let affine = self.transform.compute_affine();
+        self.mesh_data.indices.chunks_exact(3).map(move |triangle| {
+            triangle.map(|i| {
+                let v = self.mesh_data.vertices[i as usize];
+                affine.transform_point3(Vec3::new(v.x, v.z, v.y))
+            })
+        })
+    }
+}
 
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            Startup,
-            (make_spawn_floor(self.terrain_path.clone()), spawn_light),
-        );
+        app.insert_resource(TerrainSettings {
+            heightmap: self.terrain_path.clone(),
+            ..default()
+        });
+        app.add_systems(Startup, (spawn_floor, spawn_light));
         app.add_systems(Update, wireframe_control);
         app.add_systems(
             Update,
             handle_prespawning_inputs.run_if(in_state(GameState::Prespawn)),
         );
-        app.add_systems(FixedUpdate, leash_system);
+        app.add_event::<KillPlane>();
+        app.add_systems(OnEnter(GameState::InGame), spawn_bounds_warning);
+        app.add_systems(
+            Update,
+            (
+                enforce_bounds.before(DamageSet),
+                update_bounds_warning.run_if(in_state(GameState::InGame)),
+            )
+                .chain(),
+        );
     }
 }
 fn wireframe_control(
@@ -46,65 +187,77 @@ fn handle_prespawning_inputs(
     mut mouse: Res<ButtonInput<MouseButton>>,
     mut config: ResMut<WireframeConfig>,
     mut rapier_wireframes: ResMut<DebugRenderContext>,
+    last_death: Res<LastDeath>,
 ) {
-    if mouse.just_pressed(MouseButton::Left) {
+    if mouse.just_pressed(MouseButton::Left) && last_death.can_respawn() {
         next_state.set(GameState::Spawning);
     }
 }
-fn make_spawn_floor(
-    terrain_path: PathBuf,
-) -> impl FnMut(Commands, ResMut<Assets<Mesh>>, ResMut<Assets<StandardMaterial>>) {
-    move |mut commands, mut meshes, mut materials| {
-        let (shaded, shaded_mesh_data) =
-            bevy_rtin::load_mesh(&terrain_path, MeshOptions::default()).unwrap();
-        info!("Spawning terrain mesh from {:?}", terrain_path);
-        let parry3d_vertices: Vec<Vect> = shaded_mesh_data
-            .vertices
-            .into_iter()
-            .map(|v| Vect::new(v[0], v[2], v[1]))
-            .collect();
-
-        let parry3d_indices = shaded_mesh_data
-            .indices
-            .into_iter()
-            .array_chunks::<3>()
-            .collect();
-
-        let collider = Collider::trimesh(parry3d_vertices, parry3d_indices);
-
-        let shaded_handle = meshes.add(shaded);
-        let mat = StandardMaterial {
-            cull_mode: None,
-            unlit: false,
-            metallic: 0.,
-            perceptual_roughness: 0.5,
-            base_color: Color::WHITE,
-            ..default()
-        };
-        let white_material = materials.add(mat);
-
-        commands
-            .spawn((
-                PbrBundle {
-                    mesh: shaded_handle,
-                    material: white_material.clone(),
-                    transform: Transform::from_scale(Vec3::new(3., 300., 3.0)),
+fn spawn_floor(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    settings: Res<TerrainSettings>,
+) {
+    let (shaded, shaded_mesh_data) =
+        bevy_rtin::load_mesh(&settings.heightmap, MeshOptions::default()).unwrap();
+    info!("Spawning terrain mesh from {:?}", settings.heightmap);
+    let parry3d_vertices: Vec<Vect> = shaded_mesh_data
+        .vertices
+        .iter()
+        .map(|v| Vect::new(v[0], v[2], v[1]))
+        .collect();
+
+    let parry3d_indices = shaded_mesh_data
+        .indices
+        .iter()
+        .copied()
+        .array_chunks::<3>()
+        .collect();
+
+    let collider = Collider::trimesh(parry3d_vertices, parry3d_indices);
+
+    let shaded_handle = meshes.add(shaded);
+    let mat = StandardMaterial {
+        cull_mode: None,
+        unlit: false,
+        metallic: 0.,
+        perceptual_roughness: 0.5,
+        base_color: Color::WHITE,
+        ..default()
+    };
+    let white_material = materials.add(mat);
+    let terrain_transform = Transform::from_scale(settings.scale);
+    let terrain = Terrain {
+        mesh_data: shaded_mesh_data,
+        transform: terrain_transform,
+    };
+    let (min, max) = terrain.aabb();
+    commands.insert_resource(WorldBounds::around(min, max));
+    commands.insert_resource(terrain);
+
+    commands
+        .spawn((
+            PbrBundle {
+                mesh: shaded_handle,
+                material: white_material.clone(),
+                transform: terrain_transform,
+                ..default()
+            },
+            RigidBody::Fixed,
+            Name::new("shaded_floor"),
+        ))
+        .with_children(|p| {
+            p.spawn((
+                Name::new("terrain_collider"),
+                TerrainCollider,
+                collider,
+                TransformBundle {
+                    local: Transform::from_scale(Vec3::new(1., 1.0, 1.0)),
                     ..default()
                 },
-                RigidBody::Fixed,
-                Name::new("shaded_floor"),
-            ))
-            .with_children(|p| {
-                p.spawn((
-                    Name::new("terrain_collider"),
-                    collider,
-                    TransformBundle {
-                        local: Transform::from_scale(Vec3::new(1., 1.0, 1.0)),
-                        ..default()
-                    },
-                ));
-            });
-    }
+            ));
+        });
 }
 
 fn spawn_light(mut commands: Commands) {
@@ -123,17 +276,266 @@ fn spawn_light(mut commands: Commands) {
     ));
 }
 
-fn leash_system(
+#[allow(clippy::type_complexity)]
+fn enforce_bounds(
     mut commands: Commands,
-    mut n: Local<usize>,
-    leashes: Query<(Entity, &GlobalTransform, &Leash)>,
+    time: Res<Time>,
+    bounds: Option<Res<WorldBounds>>,
+    mut things: Query<(
+        Entity,
+        &GlobalTransform,
+        &mut Transform,
+        &OutOfBounds,
+        Option<&mut BoundsTracker>,
+        Has<Player>,
+        Option<&mut Velocity>,
+        Option<&mut PlayerMotion>,
+    )>,
+    mut damage: EventWriter<DamageEvent>,
+    mut kill_planes: EventWriter<KillPlane>,
 ) {
-    *n = (*n + 1) % 10;
-    if *n == 0 {
-        for (entity, global_transform, leash) in &leashes {
-            if global_transform.translation().length() > leash.0 {
+    let Some(bounds) = bounds else {
+        return;
+    };
+    let delta = time.delta_seconds();
+    for (entity, global, mut transform, policy, tracker, is_player, velocity, motion) in &mut things
+    {
+        let position = global.translation();
+        let Some(mut tracker) = tracker else {
+            commands.entity(entity).insert(BoundsTracker::default());
+            continue;
+        };
+        let depth = bounds.depth(position);
+        if depth >= 0.0 {
+            tracker.outside_for = 0.0;
+            if depth >= SAFE_DEPTH {
+                tracker.last_safe = Some(position);
+            }
+            continue;
+        }
+        let grace = if is_player { PLAYER_GRACE } else { 0.0 };
+        let was_caught = tracker.outside_for > grace;
+        tracker.outside_for += delta;
+        if tracker.outside_for <= grace {
+            continue;
+        }
+        match *policy {
+            OutOfBounds::Despawn => {
+                info!("{entity} went out of bounds at {position}; despawning it.");
                 commands.entity(entity).despawn_recursive();
             }
+            OutOfBounds::Teleport => {
+                let Some(safe) = tracker.last_safe else {
+                    commands.entity(entity).despawn_recursive();
+                    continue;
+                };
+                transform.translation += safe - position;
+                tracker.outside_for = 0.0;
+                if let Some(mut velocity) = velocity {
+                    *velocity = Velocity::zero();
+                }
+                if let Some(mut motion) = motion {
+                    motion.vertical_speed = 0.0;
+                    motion.external_velocity = Vec3::ZERO;
+                }
+            }
+            OutOfBounds::Damage { per_second } => {
+                tracker.damage_owed += per_second * delta;
+                let amount = tracker.damage_owed.floor();
+                if amount >= 1.0 {
+                    tracker.damage_owed -= amount;
+                    damage.send(DamageEvent {
+                        source: None,
+                        target: entity,
+                        amount,
+                        damage_type: DamageType::Physical,
+                        hit_point: position,
+                        status: None,
+                    });
+                }
+            }
+            OutOfBounds::KillPlane => {
+                if !was_caught {
+                    kill_planes.send(KillPlane { entity, position });
+                }
+            }
+        }
+    }
+}
+
+fn spawn_bounds_warning(mut commands: Commands) {
+    commands
+        .spawn((
+            Name::new("bounds_warning"),
+            StateScoped(GameState::InGame),
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    width: Val::Percent(100.0),
+                    top: Val::Percent(20.0),
+                    justify_content: JustifyContent::Center,
+                    ..default()
+                },
+                ..default()
+            },
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                BoundsWarning,
+                TextBundle::from_section(
+                    "",
+                    TextStyle {
+                        font_size: 32.0,
+                        color: Palette::Red.to_color(),
+                        ..default()
+                    },
+                )
+                .with_text_justify(JustifyText::Center),
+            ));
+        });
+}
+
+fn update_bounds_warning(
+    bounds: Option<Res<WorldBounds>>,
+    player: Query<(&GlobalTransform, Option<&BoundsTracker>), With<Player>>,
+    mut text: Query<&mut Text, With<BoundsWarning>>,
+) {
+    let Ok(mut text) = text.get_single_mut() else {
+        return;
+    };
+    let warning = match (bounds, player.get_single()) {
+        (Some(bounds), Ok((transform, tracker))) => {
+            let depth = bounds.depth(transform.translation());
+            let outside_for = tracker.map_or(0.0, |tracker| tracker.outside_for);
+            if depth < 0.0 {
+                let left = (PLAYER_GRACE - outside_for).max(0.0).ceil();
+                format!("Out of bounds! Turn back: {left:.0}")
+            } else if depth < WARNING_DEPTH {
+                "You're nearing the edge of the world".to_string()
+            } else {
+                String::new()
+            }
+        }
+        _ => String::new(),
+    };
+    if text.sections[0].value != warning {
+        text.sections[0].value = warning;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bevy::time::TimeUpdateStrategy;
+    use std::time::Duration;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins);
+        app.insert_resource(TimeUpdateStrategy::ManualDuration(Duration::from_millis(
+            100,
+        )));
+        app.add_event::<DamageEvent>();
+        app.add_event::<KillPlane>();
+        app.insert_resource(WorldBounds {
+            min: Vec3::splat(-100.0),
+            max: Vec3::splat(100.0),
+        });
+        app.add_systems(Update, enforce_bounds);
+        app
+    }
+
+    fn spawn(app: &mut App, policy: OutOfBounds, position: Vec3) -> Entity {
+        let transform = Transform::from_translation(position);
+        app.world_mut()
+            .spawn((policy, transform, GlobalTransform::from(transform)))
+            .id()
+    }
+
+    fn move_to(app: &mut App, entity: Entity, position: Vec3) {
+        let transform = Transform::from_translation(position);
+        let mut entity = app.world_mut().entity_mut(entity);
+        entity.insert((transform, GlobalTransform::from(transform)));
+    }
+
+    #[test]
+    fn bounds_depth_is_negative_outside() {
+        let bounds = WorldBounds {
+            min: Vec3::ZERO,
+            max: Vec3::splat(10.0),
+        };
+        assert_eq!(bounds.depth(Vec3::new(5.0, 2.0, 5.0)), 2.0);
+        assert_eq!(bounds.depth(Vec3::new(5.0, 5.0, 13.0)), -3.0);
+        assert!(!bounds.contains(Vec3::new(-1.0, 5.0, 5.0)));
+    }
+
+    #[test]
+    fn teleports_back_to_the_last_safe_position() {
+        let mut app = app();
+        let entity = spawn(&mut app, OutOfBounds::Teleport, Vec3::ZERO);
+        for _ in 0..3 {
+            app.update();
+        }
+        move_to(&mut app, entity, Vec3::new(0.0, -150.0, 0.0));
+        app.update();
+        let transform = app.world().get::<Transform>(entity).unwrap();
+        assert_eq!(transform.translation, Vec3::ZERO);
+    }
+
+    #[test]
+    fn despawns_and_signals_kill_planes() {
+        let mut app = app();
+        let despawned = spawn(&mut app, OutOfBounds::Despawn, Vec3::ZERO);
+        let signalled = spawn(&mut app, OutOfBounds::KillPlane, Vec3::ZERO);
+        app.update();
+        for entity in [despawned, signalled] {
+            move_to(&mut app, entity, Vec3::X * 200.0);
+        }
+        app.update();
+        app.update();
+        assert!(app.world().get_entity(despawned).is_none());
+        assert!(app.world().get_entity(signalled).is_some());
+        assert_eq!(app.world().resource::<Events<KillPlane>>().len(), 1);
+    }
+
+    #[test]
+    fn damage_builds_up_while_out_of_bounds() {
+        let mut app = app();
+        let entity = spawn(
+            &mut app,
+            OutOfBounds::Damage { per_second: 5.0 },
+            Vec3::ZERO,
+        );
+        app.update();
+        move_to(&mut app, entity, Vec3::Z * 200.0);
+        let mut reader = app.world().resource::<Events<DamageEvent>>().get_reader();
+        let mut total = 0.0;
+        for _ in 0..10 {
+            app.update();
+            let events = app.world().resource::<Events<DamageEvent>>();
+            total += reader.read(events).map(|event| event.amount).sum::<f32>();
+        }
+        assert_eq!(total, 5.0);
+    }
+
+    #[test]
+    fn players_get_a_grace_period() {
+        let mut app = app();
+        // Despawning the player changes the GameState.
+        app.add_plugins(bevy::state::app::StatesPlugin);
+        app.init_state::<GameState>();
+        let player = spawn(&mut app, OutOfBounds::Despawn, Vec3::ZERO);
+        app.world_mut().entity_mut(player).insert(Player);
+        app.update();
+        move_to(&mut app, player, Vec3::Y * -200.0);
+        let ticks = (PLAYER_GRACE / 0.1) as usize;
+        for _ in 0..ticks - 1 {
+            app.update();
+        }
+        assert!(app.world().get_entity(player).is_some());
+        for _ in 0..3 {
+            app.update();
         }
+        assert!(app.world().get_entity(player).is_none());
     }
 }